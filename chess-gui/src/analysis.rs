@@ -0,0 +1,253 @@
+//analysis mode: the built-in search runs on the position on the board until it changes, looking for the best few lines.
+//the search runs on its own thread and reports every finished depth, poll picks the reports up without waiting for them
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+
+use crate::my_chess::{Game, Move};
+use crate::search::{self, Limits, SearchResult, Searcher, MAX_DEPTH};
+
+//most lines multi-PV shows
+pub const MAX_LINES: usize = 5;
+//plies of each line that are written out
+const PV_PLIES: usize = 8;
+
+pub struct Analysis {
+    //how many of the best moves are shown
+    lines: usize,
+    //the position analysed and the deepest search report on it so far
+    game: Option<Game>,
+    result: Option<SearchResult>,
+    //the position changed and a search on it has to be started once the old one has ended
+    waiting: bool,
+    thread: Option<(JoinHandle<Searcher>, Arc<AtomicBool>)>,
+    //None after the position changed, reports about the old one are dropped with it
+    reports: Option<Receiver<SearchResult>>,
+    //between searches, its transposition table carries over from one position to the next
+    searcher: Option<Searcher>,
+}
+
+impl Analysis {
+    pub fn new(lines: usize) -> Analysis {
+        return Analysis{lines: lines.clamp(1, MAX_LINES), game: None, result: None, waiting: false, thread: None, reports: None, searcher: None};
+    }
+
+    pub fn lines(&self) -> usize {
+        return self.lines;
+    }
+
+    //the search starts over with the new number of lines
+    pub fn set_lines(&mut self, lines: usize) {
+        let lines = lines.clamp(1, MAX_LINES);
+        if lines != self.lines {
+            self.lines = lines;
+            if let Some(game) = self.game.take() {
+                self.follow(&game);
+            }
+        }
+    }
+
+    //analyses game from now on, nothing changes if it is the position analysed already
+    pub fn follow(&mut self, game: &Game) {
+        if let Some(current) = &self.game {
+            if current.start_fen == game.start_fen && current.history == game.history {
+                return;
+            }
+        }
+        self.game = Some(game.clone());
+        self.result = None;
+        self.reports = None;
+        self.waiting = true;
+        if let Some((_, stop)) = &self.thread {
+            stop.store(true, Ordering::Relaxed);
+        }
+    }
+
+    //takes in the search's reports and starts the search on a new position once the old search has ended
+    pub fn poll(&mut self) {
+        self.take_reports();
+        if let Some((handle, _)) = &self.thread {
+            if !handle.is_finished() {
+                return;
+            }
+            let (handle, _) = self.thread.take().unwrap();
+            self.searcher = Some(handle.join().unwrap());
+            //what was sent just before the end
+            self.take_reports();
+        }
+        if self.waiting {
+            self.start();
+        }
+    }
+
+    fn take_reports(&mut self) {
+        let Some(reports) = &self.reports else {return};
+        while let Ok(result) = reports.try_recv() {
+            self.result = Some(result);
+        }
+    }
+
+    fn start(&mut self) {
+        self.waiting = false;
+        let Some(game) = self.game.clone() else {return};
+        let mut searcher = self.searcher.take().unwrap_or_else(Searcher::new);
+        searcher.multi_pv = self.lines;
+        searcher.stop.store(false, Ordering::Relaxed);
+        let stop = searcher.stop.clone();
+        let (sender, reports) = mpsc::channel();
+        let handle = thread::spawn(move || {
+            searcher.search(&game, Limits::depth(MAX_DEPTH), |result| {
+                let _ = sender.send(result.clone());
+            });
+            return searcher;
+        });
+        self.thread = Some((handle, stop));
+        self.reports = Some(reports);
+    }
+
+    pub fn is_searching(&self) -> bool {
+        return self.thread.is_some() || self.waiting;
+    }
+
+    //the deepest finished depth on the current position
+    pub fn result(&self) -> Option<&SearchResult> {
+        return self.result.as_ref();
+    }
+
+    //centipawns from white's point of view for the eval bar, None until the first depth is done
+    pub fn white_score(&self) -> Option<i32> {
+        let result = self.result.as_ref()?;
+        let game = self.game.as_ref()?;
+        return Some(if game.turn_counter % 2 == 1 {result.score} else {-result.score});
+    }
+
+    //each line with its score from white's point of view and its moves in SAN, e.g. "+0.35  12. Nf3 Nc6 13. Bb5"
+    pub fn lines_text(&self) -> Vec<String> {
+        let (Some(result), Some(game)) = (&self.result, &self.game) else {return vec![]};
+        let white = game.turn_counter % 2 == 1;
+        let mut text = vec![];
+        for line in &result.lines {
+            let score = if white {line.score} else {-line.score};
+            text.push(format!("{}  {}", format_score(score), pv_san(game, &line.pv)));
+        }
+        return text;
+    }
+}
+
+impl Drop for Analysis {
+    //the search would go on to MAX_DEPTH otherwise
+    fn drop(&mut self) {
+        let Some((handle, stop)) = self.thread.take() else {return};
+        stop.store(true, Ordering::Relaxed);
+        let _ = handle.join();
+    }
+}
+
+//pawns with a sign, or # and the moves to mate, negative if black mates
+pub fn format_score(score: i32) -> String {
+    return match search::mate_in(score) {
+        Some(moves) => format!("#{}", moves),
+        None => format!("{:+.2}", score as f64 / 100.0),
+    };
+}
+
+//white's part of the eval bar, a pawn up fills about 60%
+pub fn white_share(score: i32) -> f32 {
+    if let Some(moves) = search::mate_in(score) {
+        return if moves > 0 {1.0} else {0.0};
+    }
+    return 1.0 / (1.0 + 10f32.powf(-score as f32 / 400.0));
+}
+
+//the first PV_PLIES moves of pv in SAN with move numbers, played from game's position
+pub fn pv_san(game: &Game, pv: &[Move]) -> String {
+    let mut replay = game.clone();
+    let mut text = vec![];
+    for (n, mv) in pv.iter().take(PV_PLIES).enumerate() {
+        let number = (replay.turn_counter + 1) / 2;
+        let white = replay.turn_counter % 2 == 1;
+        if white {
+            text.push(format!("{}.", number));
+        } else if n == 0 {
+            text.push(format!("{}...", number));
+        }
+        text.push(replay.to_san(*mv));
+        if replay.move_piece(*mv).is_err() {
+            break;
+        }
+    }
+    return text.join(" ");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::my_chess;
+    use std::time::Duration;
+
+    //polls until the search has reached depth on the position
+    fn wait_for_depth(analysis: &mut Analysis, depth: usize) -> SearchResult {
+        for _ in 0..10_000 {
+            analysis.poll();
+            if let Some(result) = analysis.result() {
+                if result.depth >= depth {
+                    return result.clone();
+                }
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+        panic!("the analysis didn't reach depth {}", depth);
+    }
+
+    #[test]
+    fn follows_the_board() {
+        let mut analysis = Analysis::new(3);
+        let mut game = my_chess::start();
+        analysis.follow(&game);
+        let result = wait_for_depth(&mut analysis, 3);
+        assert_eq!(result.lines.len(), 3);
+        let text = analysis.lines_text();
+        assert_eq!(text.len(), 3);
+        assert!(text[0].contains("  1. "), "{}", text[0]);
+
+        //a move played on the board restarts the search from black's side
+        game.move_piece(my_chess::Move::parse("e2e4").unwrap()).unwrap();
+        analysis.follow(&game);
+        assert!(analysis.result().is_none());
+        wait_for_depth(&mut analysis, 2);
+        assert!(analysis.lines_text()[0].contains("  1... "));
+
+        analysis.set_lines(1);
+        assert!(analysis.result().is_none());
+        assert_eq!(wait_for_depth(&mut analysis, 1).lines.len(), 1);
+    }
+
+    #[test]
+    fn mate_on_the_board() {
+        let mut analysis = Analysis::new(1);
+        let game = my_chess::Game::from_fen("r5k1/5ppp/8/8/8/8/5PPP/6K1 b - - 0 1").unwrap();
+        analysis.follow(&game);
+        wait_for_depth(&mut analysis, 1);
+        assert_eq!(analysis.lines_text(), vec!["#-1  1... Ra1#"]);
+        assert_eq!(analysis.white_score().map(white_share), Some(0.0));
+
+        //nothing to analyse once the game is over
+        let game = my_chess::Game::from_fen("6k1/5ppp/8/8/8/8/5PPP/r5K1 w - - 1 2").unwrap();
+        analysis.follow(&game);
+        while analysis.is_searching() {
+            analysis.poll();
+        }
+        assert!(analysis.lines_text().is_empty());
+    }
+
+    #[test]
+    fn scores() {
+        assert_eq!(format_score(35), "+0.35");
+        assert_eq!(format_score(-120), "-1.20");
+        assert_eq!(format_score(search::MATE - 3), "#2");
+        assert_eq!(white_share(0), 0.5);
+        assert!(white_share(100) > 0.55 && white_share(100) < 0.7);
+        assert!(white_share(-100) < 0.45);
+    }
+}
@@ -0,0 +1,408 @@
+//engine programs that speak xboard's Chess Engine Communication Protocol (CECP, protover 2).
+//such an engine keeps the game itself, so it is told only the moves it hasn't seen and set up again when the game went elsewhere
+use std::io;
+use std::sync::mpsc::TryRecvError;
+use std::time::{Duration, Instant};
+
+use crate::external::{Event, ExternalEngine, Info, Process, Score, HANDSHAKE_TIMEOUT};
+use crate::my_chess::{Game, Move, START_FEN};
+
+//engines that send no features at all are protocol version 1, they get this long to start sending them
+const FEATURE_TIMEOUT: Duration = Duration::from_secs(2);
+//thinking output gives a mate in n moves as 100000 + n
+const MATE_SCORE: i32 = 100000;
+
+pub struct CecpEngine {
+    name: String,
+    info: Info,
+    process: Process,
+    //features the engine asked for
+    usermove: bool,
+    setboard: bool,
+    ping: bool,
+    //start position and moves the engine has been told about, None when it has to be set up again
+    known: Option<(String, Vec<Move>)>,
+    //the position being searched, to read the engine's move and pv in
+    searching: Option<Game>,
+    //output before the engine answers this ping belongs to searches that were stopped
+    awaiting_pong: Option<usize>,
+    pings: usize,
+    //engines without ping: searches that were stopped and haven't sent their move yet, each ends with one move or resign
+    stopped: usize,
+}
+
+impl CecpEngine {
+    //xboard and protover 2, then the engine's features until it says done=1
+    pub fn new(process: Process) -> io::Result<CecpEngine> {
+        let mut engine = CecpEngine{name: String::new(), info: Info::default(), process: process, usermove: false, setboard: false, ping: false,
+            known: None, searching: None, awaiting_pong: None, pings: 0, stopped: 0};
+        engine.process.send("xboard")?;
+        engine.process.send("protover 2")?;
+        let mut deadline = Instant::now() + FEATURE_TIMEOUT;
+        loop {
+            let line = match engine.process.wait_for_line(deadline.saturating_duration_since(Instant::now())) {
+                Ok(Some(line)) => line,
+                Ok(None) => return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "engine exited before done=1")),
+                //protocol version 1, nothing more to wait for
+                Err(e) if e.kind() == io::ErrorKind::TimedOut => break,
+                Err(e) => return Err(e),
+            };
+            let Some(features) = line.strip_prefix("feature ") else {continue};
+            let mut done = false;
+            for (name, value) in parse_features(features) {
+                let on = value == "1";
+                match name.as_str() {
+                    "myname" => engine.name = value.clone(),
+                    "usermove" => engine.usermove = on,
+                    "setboard" => engine.setboard = on,
+                    "ping" => engine.ping = on,
+                    //done=0 asks for more time
+                    "done" if on => done = true,
+                    "done" => deadline = Instant::now() + HANDSHAKE_TIMEOUT,
+                    _ => {}
+                }
+                //moves in SAN would need the position they are played in, coordinates are enough
+                let answer = if name == "san" && on {"rejected"} else {"accepted"};
+                engine.process.send(&format!("{} {}", answer, name))?;
+            }
+            if done {
+                break;
+            }
+        }
+        engine.process.send("post")?;
+        return Ok(engine);
+    }
+
+    //the move as the engine expects it, castling in chess960 is written O-O or O-O-O
+    fn move_command(&self, chess960: bool, mv: Move) -> String {
+        let text = if mv.is_castle() && chess960 {
+            if mv.to.x > mv.from.x {"O-O".to_string()} else {"O-O-O".to_string()}
+        } else {
+            mv.to_string()
+        };
+        return if self.usermove {format!("usermove {}", text)} else {text};
+    }
+
+    //brings the engine to the game's position in force mode, so it doesn't answer the moves it is told
+    fn set_up(&mut self, game: &Game) -> io::Result<()> {
+        let told = match &self.known {
+            Some((start, moves)) if *start == game.start_fen && game.history.len() >= moves.len()
+                && game.history.iter().zip(moves).all(|(a, b)| a.matches(*b)) => {
+                self.process.send("force")?;
+                moves.len()
+            },
+            _ => {
+                self.process.send("new")?;
+                if game.chess960 {
+                    self.process.send("variant fischerandom")?;
+                }
+                self.process.send("force")?;
+                if game.start_fen != START_FEN {
+                    if !self.setboard {
+                        return Err(io::Error::new(io::ErrorKind::Unsupported, "engine can't set up positions"));
+                    }
+                    self.process.send(&format!("setboard {}", game.start_fen))?;
+                }
+                self.known = Some((game.start_fen.clone(), vec![]));
+                0
+            }
+        };
+        for mv in &game.history[told..] {
+            let command = self.move_command(game.chess960, *mv);
+            self.process.send(&command)?;
+            if let Some((_, moves)) = &mut self.known {
+                moves.push(*mv);
+            }
+        }
+        return Ok(());
+    }
+}
+
+impl ExternalEngine for CecpEngine {
+    fn name(&self) -> &str {
+        return &self.name;
+    }
+
+    fn info(&self) -> &Info {
+        return &self.info;
+    }
+
+    fn new_game(&mut self) -> io::Result<()> {
+        self.stop()?;
+        self.known = None;
+        return Ok(());
+    }
+
+    fn go(&mut self, game: &Game, movetime: Duration) -> io::Result<()> {
+        self.stop()?;
+        self.set_up(game)?;
+        //st only takes whole seconds
+        self.process.send(&format!("st {}", movetime.as_secs().max(1)))?;
+        if self.ping {
+            self.pings += 1;
+            self.process.send(&format!("ping {}", self.pings))?;
+            self.awaiting_pong = Some(self.pings);
+        }
+        self.process.send("go")?;
+        self.searching = Some(game.clone());
+        self.info = Info::default();
+        return Ok(());
+    }
+
+    fn stop(&mut self) -> io::Result<()> {
+        if self.searching.take().is_some() {
+            //the engine plays the move it has on its own board, so it is set up again next time
+            self.known = None;
+            if !self.ping {
+                self.stopped += 1;
+            }
+            return self.process.send("?");
+        }
+        return Ok(());
+    }
+
+    fn is_thinking(&self) -> bool {
+        return self.searching.is_some();
+    }
+
+    fn poll(&mut self) -> Option<Event> {
+        loop {
+            let line = match self.process.try_line() {
+                Ok(line) => line,
+                Err(TryRecvError::Empty) => return None,
+                Err(TryRecvError::Disconnected) => {
+                    self.searching.take()?;
+                    return Some(Event::Exited);
+                }
+            };
+            if let Some(n) = self.awaiting_pong {
+                if line.trim() == format!("pong {}", n) {
+                    self.awaiting_pong = None;
+                }
+                continue;
+            }
+            if self.stopped > 0 {
+                if matches!(line.split_whitespace().next(), Some("move") | Some("resign")) {
+                    self.stopped -= 1;
+                }
+                continue;
+            }
+            let Some(game) = &self.searching else {continue};
+            let mut words = line.split_whitespace();
+            match words.next() {
+                Some("move") => {
+                    let mut game = self.searching.take().unwrap();
+                    let best = words.next().and_then(|text| read_move(&mut game, text));
+                    match (best, &mut self.known) {
+                        (Some(mv), Some((_, moves))) => moves.push(mv),
+                        _ => self.known = None,
+                    }
+                    return Some(Event::BestMove(best));
+                }
+                Some("resign") => {
+                    self.searching = None;
+                    self.known = None;
+                    return Some(Event::Resigned);
+                }
+                //thinking output starts with the depth
+                Some(word) if word.starts_with(|c: char| c.is_ascii_digit()) => {
+                    if let Some(info) = parse_thinking(game, &line) {
+                        if !info.pv.is_empty() {
+                            self.info = info.clone();
+                        }
+                        return Some(Event::Info(info));
+                    }
+                }
+                //the engine didn't take one of the moves, what it thinks the position is can't be trusted
+                Some("Illegal") | Some("Error") => self.known = None,
+                _ => {}
+            }
+        }
+    }
+}
+
+//name=value pairs, values may be quoted and then contain spaces
+pub fn parse_features(text: &str) -> Vec<(String, String)> {
+    let mut features = vec![];
+    let mut rest = text.trim_start();
+    while let Some(eq) = rest.find('=') {
+        let name = rest[..eq].trim().to_string();
+        rest = &rest[eq + 1..];
+        let value;
+        if let Some(quoted) = rest.strip_prefix('"') {
+            let end = quoted.find('"').unwrap_or(quoted.len());
+            value = quoted[..end].to_string();
+            rest = quoted.get(end + 1..).unwrap_or("");
+        } else {
+            let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            value = rest[..end].to_string();
+            rest = &rest[end..];
+        }
+        features.push((name, value));
+        rest = rest.trim_start();
+    }
+    return features;
+}
+
+//a move in coordinates or SAN (O-O in chess960), as the legal move of the position
+pub fn read_move(game: &mut Game, text: &str) -> Option<Move> {
+    let mv = match Move::parse(text) {
+        Ok(mv) => mv,
+        Err(_) => game.parse_san(text).ok()?,
+    };
+    return game.get_all_legal_moves().into_iter().find(|m| m.matches(mv));
+}
+
+//ply score time nodes pv, the pv is read as far as its moves are legal and may have move numbers in between
+pub fn parse_thinking(game: &Game, line: &str) -> Option<Info> {
+    let mut words = line.split_whitespace();
+    let depth = words.next()?.trim_end_matches(|c: char| !c.is_ascii_digit()).parse().ok()?;
+    let score: i32 = words.next()?.parse().ok()?;
+    let _time: u64 = words.next()?.parse().ok()?;
+    let nodes = words.next()?.parse().ok()?;
+    let score = if score >= MATE_SCORE {
+        Score::Mate(score - MATE_SCORE)
+    } else if score <= -MATE_SCORE {
+        Score::Mate(score + MATE_SCORE)
+    } else {
+        Score::Cp(score)
+    };
+    let mut replay = game.clone();
+    let mut pv = vec![];
+    for word in words.filter(|w| !w.ends_with('.')) {
+        let Some(mv) = read_move(&mut replay, word) else {break};
+        if replay.move_piece(mv).is_err() {
+            break;
+        }
+        pv.push(mv);
+    }
+    return Some(Info{depth: Some(depth), score: Some(score), nodes: Some(nodes), pv: pv});
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::external::tests::scripted;
+    use crate::my_chess;
+    use std::sync::{Arc, Mutex};
+
+    fn engine(script: fn(&str) -> Vec<&'static str>) -> (CecpEngine, Arc<Mutex<Vec<String>>>) {
+        let (process, received) = scripted(script);
+        return (CecpEngine::new(process).unwrap(), received);
+    }
+
+    fn sparring(command: &str) -> Vec<&'static str> {
+        match command.split_whitespace().next() {
+            Some("protover") => return vec!["feature done=0", "feature myname=\"Sparring 2.0\" usermove=1 setboard=1 ping=1 san=1", "feature done=1"],
+            Some("ping") if command == "ping 1" => return vec!["pong 1"],
+            Some("ping") => return vec!["pong 2"],
+            Some("go") => return vec!["1 35 0 20 e7e5", "2 -100003 1 400 1. ... e7e5 2. Nf3", "move e7e5"],
+            _ => return vec![],
+        }
+    }
+
+    #[test]
+    fn plays_scripted_engine() {
+        let (mut engine, received) = engine(sparring);
+        assert_eq!(engine.name(), "Sparring 2.0");
+        assert!(engine.usermove && engine.setboard && engine.ping);
+
+        let mut game = my_chess::start();
+        game.move_piece(Move::parse("e2e4").unwrap()).unwrap();
+        engine.go(&game, Duration::from_millis(1500)).unwrap();
+        assert!(engine.is_thinking());
+        match engine.poll() {
+            Some(Event::Info(info)) => assert_eq!((info.depth, info.score, info.nodes, info.pv.len()), (Some(1), Some(Score::Cp(35)), Some(20), 1)),
+            other => panic!("expected info, got {:?}", other),
+        }
+        match engine.poll() {
+            Some(Event::Info(info)) => assert_eq!((info.score, info.pv.len()), (Some(Score::Mate(-3)), 2)),
+            other => panic!("expected info, got {:?}", other),
+        }
+        assert!(matches!(engine.poll(), Some(Event::BestMove(Some(mv))) if mv.matches(Move::parse("e7e5").unwrap())));
+        assert_eq!(engine.poll(), None);
+        assert!(!engine.is_thinking());
+
+        //only the new move is sent the next time
+        game.move_piece(Move::parse("e7e5").unwrap()).unwrap();
+        game.move_piece(Move::parse("g1f3").unwrap()).unwrap();
+        engine.go(&game, Duration::from_millis(100)).unwrap();
+        let received = received.lock().unwrap();
+        assert_eq!(received[..3], ["xboard", "protover 2", "accepted done"]);
+        assert!(received.contains(&"rejected san".to_string()));
+        let start = received.iter().position(|c| c == "post").unwrap() + 1;
+        assert_eq!(received[start..], ["new", "force", "usermove e2e4", "st 1", "ping 1", "go", "force", "usermove g1f3", "st 1", "ping 2", "go"]);
+    }
+
+    //answers go only after ?, like a search that takes a while
+    fn slow(command: &str) -> Vec<&'static str> {
+        match command {
+            "?" => return vec!["9 0 100 5000 a7a6", "move a7a6"],
+            "go" => return vec![],
+            _ => return sparring(command),
+        }
+    }
+
+    #[test]
+    fn stopped_search_is_dropped() {
+        let (mut engine, received) = engine(slow);
+        let mut game = my_chess::start();
+        game.move_piece(Move::parse("e2e4").unwrap()).unwrap();
+        engine.go(&game, Duration::from_secs(1)).unwrap();
+        //after undo the engine is set up again and the stopped search's move is skipped
+        game.undo();
+        engine.go(&game, Duration::from_secs(1)).unwrap();
+        assert_eq!(engine.poll(), None);
+        assert!(engine.is_thinking());
+        let received = received.lock().unwrap();
+        let start = received.iter().position(|c| c == "?").unwrap();
+        assert_eq!(received[start..], ["?", "new", "force", "st 1", "ping 2", "go"]);
+    }
+
+    //no ping to tell the stopped search's output apart, the move it sends after ? is legal in the new search's position too
+    fn pingless(command: &str) -> Vec<&'static str> {
+        match command {
+            "protover 2" => return vec!["feature myname=\"Pingless\" usermove=1 setboard=1 done=1"],
+            "?" => return vec!["move a7a6"],
+            "st 1" => return vec!["move e7e5"],
+            _ => return vec![],
+        }
+    }
+
+    #[test]
+    fn stopped_search_without_ping() {
+        let (mut engine, _) = engine(pingless);
+        assert!(!engine.ping);
+        let mut game = my_chess::start();
+        game.move_piece(Move::parse("e2e4").unwrap()).unwrap();
+        engine.go(&game, Duration::from_secs(2)).unwrap();
+        engine.go(&game, Duration::from_secs(1)).unwrap();
+        assert!(matches!(engine.poll(), Some(Event::BestMove(Some(mv))) if mv.matches(Move::parse("e7e5").unwrap())));
+        assert_eq!(engine.poll(), None);
+    }
+
+    #[test]
+    fn chess960_castling() {
+        let (mut engine, received) = engine(sparring);
+        let mut game = my_chess::Game::from_fen("4k3/pppppppp/8/8/8/8/8/1R2K1R1 w GB - 0 1").unwrap();
+        game.move_piece(Move::parse("e1g1").unwrap()).unwrap();
+        engine.go(&game, Duration::from_secs(1)).unwrap();
+        let received = received.lock().unwrap();
+        let start = received.iter().position(|c| c == "new").unwrap();
+        assert_eq!(received[start..start + 4], ["new", "variant fischerandom", "force", "setboard 4k3/pppppppp/8/8/8/8/8/1R2K1R1 w GB - 0 1"]);
+        assert_eq!(received[start + 4], "usermove O-O");
+    }
+
+    #[test]
+    fn engine_output() {
+        assert_eq!(parse_features(" myname=\"Fruit 2.1\" ping=1  variants=\"normal,fischerandom\" done=1"), vec![
+            ("myname".to_string(), "Fruit 2.1".to_string()), ("ping".to_string(), "1".to_string()),
+            ("variants".to_string(), "normal,fischerandom".to_string()), ("done".to_string(), "1".to_string())]);
+        let mut game = my_chess::start();
+        assert_eq!(read_move(&mut game, "Nf3").map(|m| m.to_string()), Some("g1f3".to_string()));
+        assert_eq!(read_move(&mut game, "e2e5"), None);
+        let info = parse_thinking(&game, "12& 250 310 123456 d2d4 Nf6 c2c4 zz e6").unwrap();
+        assert_eq!((info.depth, info.score, info.pv.len()), (Some(12), Some(Score::Cp(250)), 3));
+        assert_eq!(parse_thinking(&game, "tellics say hello"), None);
+    }
+}
@@ -0,0 +1,291 @@
+//plays random games on my_chess and chesslib side by side and stops at the first ply where they disagree.
+//run with `chess-gui --diff [games] [seed]`
+use std::collections::BTreeSet;
+
+use chesslib::{Chess, PieceType, Side, State};
+
+use crate::my_chess::{self, Game, GameState, Move};
+
+//games stop here if they have not ended on their own
+const MAX_PLIES: usize = 300;
+
+//what both engines are compared on after every ply
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Snapshot {
+    pub white_to_move: bool,
+    //from and to as square indices, promotions to different pieces count once since chesslib asks for the piece afterwards
+    pub moves: BTreeSet<(usize, usize)>,
+    pub in_check: bool,
+    pub result: Outcome,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Outcome {
+    Ongoing,
+    Checkmate,
+    Stalemate,
+    Draw,
+}
+
+#[derive(Clone, Debug)]
+pub struct Disagreement {
+    //moves from the start position leading to the position the engines disagree on
+    pub moves: Vec<Move>,
+    pub fen: String,
+    pub my_chess: Snapshot,
+    pub chesslib: Snapshot,
+}
+
+impl std::fmt::Display for Disagreement {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let moves: Vec<String> = self.moves.iter().map(|m| m.to_string()).collect();
+        writeln!(f, "engines disagree after {} plies", self.moves.len())?;
+        writeln!(f, "moves: {}", if moves.is_empty() {"(start position)".to_string()} else {moves.join(" ")})?;
+        writeln!(f, "fen: {}", self.fen)?;
+        let (a, b) = (&self.my_chess, &self.chesslib);
+        if a.white_to_move != b.white_to_move {
+            writeln!(f, "side to move: my_chess {} chesslib {}", side_name(a.white_to_move), side_name(b.white_to_move))?;
+        }
+        if a.in_check != b.in_check {
+            writeln!(f, "in check: my_chess {} chesslib {}", a.in_check, b.in_check)?;
+        }
+        if a.result != b.result {
+            writeln!(f, "result: my_chess {:?} chesslib {:?}", a.result, b.result)?;
+        }
+        let only_mine: Vec<String> = a.moves.difference(&b.moves).map(|m| index_move(*m)).collect();
+        let only_theirs: Vec<String> = b.moves.difference(&a.moves).map(|m| index_move(*m)).collect();
+        if !only_mine.is_empty() {
+            writeln!(f, "only my_chess allows: {}", only_mine.join(" "))?;
+        }
+        if !only_theirs.is_empty() {
+            writeln!(f, "only chesslib allows: {}", only_theirs.join(" "))?;
+        }
+        return Ok(());
+    }
+}
+
+fn side_name(white: bool) -> &'static str {
+    return if white {"white"} else {"black"};
+}
+
+fn index_move(m: (usize, usize)) -> String {
+    return format!("{}{}", my_chess::Square::from_index(m.0), my_chess::Square::from_index(m.1));
+}
+
+//xorshift, good enough to pick moves and keeps the runs reproducible from the seed
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        return Rng(seed.max(1));
+    }
+
+    pub fn below(&mut self, n: usize) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        return (self.0 % n as u64) as usize;
+    }
+}
+
+pub fn snapshot_my_chess(game: &mut Game) -> Snapshot {
+    let white_to_move = game.turn_counter % 2 == 1;
+    let moves = game.get_all_legal_moves().iter().map(|m| (m.from.index(), m.to.index())).collect();
+    let king = if white_to_move {game.board.wk_pos} else {game.board.bk_pos};
+    let in_check = game.board.is_in_check(king);
+    let result = match game.check_state() {
+        GameState::ONGOING => Outcome::Ongoing,
+        GameState::WIN_W(_) | GameState::WIN_B(_) => Outcome::Checkmate,
+        GameState::DRAW(my_chess::DrawReason::STALEMATE) => Outcome::Stalemate,
+        GameState::DRAW(_) => Outcome::Draw,
+    };
+    return Snapshot{white_to_move, moves, in_check, result};
+}
+
+pub fn snapshot_chesslib(chess: &mut Chess) -> Snapshot {
+    let white_to_move = chess.get_playing_side() == Side::White;
+    let mut moves = BTreeSet::new();
+    for from in 0..64 {
+        if !chess.is_selectable(from) {
+            continue;
+        }
+        //chesslib gives the destinations as (x, y)
+        for (x, y) in chess.get_moves(from) {
+            moves.insert((from, x + y*8));
+        }
+    }
+    let state = chess.get_state();
+    let result = match state {
+        State::Checkmate => Outcome::Checkmate,
+        State::Stalemate => Outcome::Stalemate,
+        State::Draw => Outcome::Draw,
+        _ => Outcome::Ongoing,
+    };
+    //chesslib only reports check while the game goes on
+    let in_check = state == State::Check || state == State::Checkmate;
+    return Snapshot{white_to_move, moves, in_check, result};
+}
+
+//plays the move on chesslib, false if it refuses
+fn play_chesslib(chess: &mut Chess, mv: Move) -> bool {
+    if !chess.make_move(mv.from.index(), mv.to.index()) {
+        return false;
+    }
+    if let Some(promotion) = mv.promotion {
+        chess.promote(match promotion {
+            my_chess::PieceType::ROOK => PieceType::Rook,
+            my_chess::PieceType::BISHIOP => PieceType::Bishop,
+            my_chess::PieceType::KNIGHT => PieceType::Knight,
+            _ => PieceType::Queen,
+        });
+    }
+    return true;
+}
+
+//plays the moves on both engines and compares them after every ply, None if they agree all the way.
+//moves my_chess rejects end the replay without a verdict
+pub fn replay(moves: &[Move]) -> Option<Disagreement> {
+    return replay_from(my_chess::start(), Chess::new(), moves);
+}
+
+//replay from positions set up on each engine, the moves of a disagreement count from there
+pub fn replay_from(mut game: Game, mut chess: Chess, moves: &[Move]) -> Option<Disagreement> {
+    for n in 0..=moves.len() {
+        let mine = snapshot_my_chess(&mut game);
+        let theirs = snapshot_chesslib(&mut chess);
+        if mine != theirs {
+            return Some(Disagreement{moves: moves[..n].to_vec(), fen: game.to_fen(), my_chess: mine, chesslib: theirs});
+        }
+        if n == moves.len() || game.move_piece(moves[n]).is_err() {
+            return None;
+        }
+        //snapshots agreed on the legal moves, so a refusal here is a disagreement of its own
+        if !play_chesslib(&mut chess, moves[n]) {
+            let mut theirs = snapshot_chesslib(&mut chess);
+            theirs.moves.clear();
+            return Some(Disagreement{moves: moves[..=n].to_vec(), fen: game.to_fen(), my_chess: snapshot_my_chess(&mut game), chesslib: theirs});
+        }
+    }
+    return None;
+}
+
+//a random game on my_chess, the moves are checked against chesslib afterwards
+pub fn random_game(rng: &mut Rng, max_plies: usize) -> Vec<Move> {
+    let mut game = my_chess::start();
+    let mut moves = vec![];
+    while moves.len() < max_plies && game.check_state() == GameState::ONGOING {
+        let legal = game.get_all_legal_moves();
+        let mv = legal[rng.below(legal.len())];
+        game.move_piece(mv).unwrap();
+        moves.push(mv);
+    }
+    return moves;
+}
+
+//fails gives the length of the failing prefix, or None if the sequence passes.
+//drops a move and its reply, or single moves, for as long as the shorter sequence still fails.
+//the result is not the shortest possible but none of these cuts shortens it further
+pub fn minimize(moves: &[Move], mut fails: impl FnMut(&[Move]) -> Option<usize>) -> Vec<Move> {
+    let mut current = moves.to_vec();
+    if let Some(len) = fails(&current) {
+        current.truncate(len);
+    }
+    let mut changed = true;
+    while changed {
+        changed = false;
+        //pairs keep the side to move of the remaining moves
+        for chunk in [2, 1] {
+            let mut n = 0;
+            while n + chunk <= current.len() {
+                let mut shorter = current.clone();
+                shorter.drain(n..n+chunk);
+                //only sequences my_chess can play are worth keeping
+                if is_playable(&shorter) {
+                    if let Some(len) = fails(&shorter) {
+                        shorter.truncate(len);
+                        current = shorter;
+                        changed = true;
+                        continue;
+                    }
+                }
+                n += 1;
+            }
+        }
+    }
+    return current;
+}
+
+fn is_playable(moves: &[Move]) -> bool {
+    let mut game = my_chess::start();
+    return moves.iter().all(|m| game.move_piece(*m).is_ok());
+}
+
+//plays games until the engines disagree, returns the number of plies compared otherwise
+pub fn run(games: usize, seed: u64) -> Result<usize, Disagreement> {
+    let mut rng = Rng::new(seed);
+    let mut plies = 0;
+    for _ in 0..games {
+        let moves = random_game(&mut rng, MAX_PLIES);
+        if let Some(found) = replay(&moves) {
+            let minimal = minimize(&found.moves, |m| replay(m).map(|d| d.moves.len()));
+            return Err(replay(&minimal).unwrap_or(found));
+        }
+        plies += moves.len();
+    }
+    return Ok(plies);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mv(uci: &str) -> Move {
+        return Move::parse(uci).unwrap();
+    }
+
+    #[test]
+    fn minimize_keeps_needed_moves(){
+        //fails once the white queen stands on h5, whatever else happened
+        let moves: Vec<Move> = ["a2a3", "a7a6", "e2e4", "h7h6", "b2b3", "b7b6", "d1h5", "g7g6", "g1f3"].iter().map(|m| mv(m)).collect();
+        let fails = |m: &[Move]| m.iter().position(|x| *x == mv("d1h5")).map(|n| n + 1);
+        let minimal = minimize(&moves, fails);
+        //e2e4 opens the way for the queen and black has to move in between
+        assert_eq!(minimal.len(), 3);
+        assert_eq!(minimal[0], mv("e2e4"));
+        assert_eq!(minimal[2], mv("d1h5"));
+    }
+
+    #[test]
+    fn random_games_are_reproducible(){
+        let a = random_game(&mut Rng::new(7), 40);
+        let b = random_game(&mut Rng::new(7), 40);
+        assert_eq!(a, b);
+        assert!(is_playable(&a));
+    }
+
+    #[test]
+    fn replay_compares_engines(){
+        //an opening without castling, en passant or promotions, both engines have to agree on it
+        let moves: Vec<Move> = ["e2e4", "e7e5", "g1f3", "b8c6", "f1c4", "g8f6", "d2d3", "f8c5"].iter().map(|m| mv(m)).collect();
+        if let Some(found) = replay(&moves) {
+            panic!("{}", found);
+        }
+
+        //my_chess is a move ahead, so the very first comparison finds black to move on one side only
+        let game = Game::from_fen("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1").unwrap();
+        let found = replay_from(game, Chess::new(), &[mv("e7e5")]).unwrap();
+        assert!(found.moves.is_empty());
+        assert!(!found.my_chess.white_to_move);
+        assert!(found.chesslib.white_to_move);
+        assert!(found.to_string().contains("side to move: my_chess black chesslib white"));
+    }
+
+    //depends on chesslib agreeing with my_chess: cargo test -- --ignored
+    #[test]
+    #[ignore]
+    fn engines_agree(){
+        if let Err(found) = run(20, 1) {
+            panic!("{}", found);
+        }
+    }
+}
@@ -0,0 +1,711 @@
+//my_chess as a UCI or xboard (CECP) engine, so it can be loaded into other chess GUIs and tournament managers.
+//commands are read from stdin, the search runs on its own thread and its output is printed as it comes.
+//it speaks UCI until the first command is xboard
+//explicit returns and field: field initializers are how the whole codebase is written
+#![allow(clippy::needless_return, clippy::redundant_field_names)]
+mod my_chess;
+//the GUI uses parts of the search the engine doesn't, e.g. bench
+#[allow(dead_code)]
+mod search;
+
+use std::io::{self, BufRead, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use my_chess::{Color, Game, GameState, Move};
+use search::{Limits, SearchResult, Searcher, MAX_DEPTH};
+
+const NAME: &str = "my_chess";
+const AUTHOR: &str = "the chess-gui authors";
+
+//transposition table size limits for the Hash option, in megabytes
+const MAX_HASH_MB: usize = 1024;
+
+//left on the clock for the GUI to receive the move
+const MOVE_OVERHEAD: Duration = Duration::from_millis(50);
+//moves the remaining time is split over when the GUI doesn't say
+const DEFAULT_MOVES_TO_GO: u64 = 30;
+
+//answer to protover, xboard sends usermove before every move and setboard instead of edit
+const XBOARD_FEATURES: &str = "feature myname=\"my_chess\" ping=1 setboard=1 usermove=1 san=0 colors=0 sigint=0 sigterm=0 reuse=1 analyze=0 variants=\"normal,fischerandom\" done=1";
+//xboard's mate scores, 100000 + moves to mate
+const XBOARD_MATE: i32 = 100000;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Protocol {
+    Uci,
+    Xboard,
+}
+
+//what xboard told the engine, with xboard the engine keeps the game itself and plays its moves on it
+struct Xboard {
+    //side the engine plays, None in force mode
+    side: Option<Color>,
+    //thinking output after every finished depth
+    post: bool,
+    //the engine's clock as of the last time command
+    clock: Option<Duration>,
+    //from level, 0 moves per time control means the whole game
+    moves_per_control: u64,
+    increment: Duration,
+    //from st and sd
+    movetime: Option<Duration>,
+    depth: usize,
+}
+
+impl Xboard {
+    fn new() -> Xboard {
+        return Xboard{side: Some(Color::B), post: false, clock: None, moves_per_control: 0, increment: Duration::ZERO, movetime: None, depth: MAX_DEPTH};
+    }
+}
+
+//the search thread, which hands the searcher back with its result, and the flag that stops it
+//the search thread, which hands the searcher back with its result and whether it sent its answer,
+//the flag that stops it and the one that keeps an aborted search from answering
+type Thinking = (JoinHandle<(Searcher, SearchResult, bool)>, Arc<AtomicBool>, Arc<Mutex<bool>>);
+
+struct Engine {
+    game: Game,
+    //set by the UCI_Chess960 option, castling is then written as the king taking its own rook
+    chess960: bool,
+    //None while a search thread has it
+    searcher: Option<Searcher>,
+    thinking: Option<Thinking>,
+    //lines for the GUI, one message per line
+    out: Sender<String>,
+    protocol: Protocol,
+    xboard: Xboard,
+}
+
+impl Engine {
+    fn new(out: Sender<String>) -> Engine {
+        return Engine{game: my_chess::start(), chess960: false, searcher: Some(Searcher::new()), thinking: None, out: out, protocol: Protocol::Uci, xboard: Xboard::new()};
+    }
+
+    fn send(&self, line: String) {
+        //the receiver is only gone once the engine is shutting down
+        let _ = self.out.send(line);
+    }
+
+    //returns false on quit
+    fn handle(&mut self, line: &str) -> bool {
+        if self.protocol == Protocol::Xboard {
+            return self.handle_xboard(line);
+        }
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let Some((command, args)) = words.split_first() else {return true};
+        match *command {
+            "xboard" => self.protocol = Protocol::Xboard,
+            "uci" => {
+                self.send(format!("id name {}", NAME));
+                self.send(format!("id author {}", AUTHOR));
+                self.send(format!("option name Hash type spin default {} min 1 max {}", search::DEFAULT_TT_MB, MAX_HASH_MB));
+                self.send("option name Clear Hash type button".to_string());
+                self.send("option name UCI_Chess960 type check default false".to_string());
+                self.send("uciok".to_string());
+            }
+            "isready" => self.send("readyok".to_string()),
+            "ucinewgame" => {
+                self.stop_search();
+                self.game = my_chess::start();
+                self.searcher.as_mut().unwrap().clear();
+            }
+            "setoption" => {
+                self.stop_search();
+                self.set_option(args);
+            }
+            "position" => {
+                self.stop_search();
+                match self.position(args) {
+                    Ok(game) => self.game = game,
+                    Err(e) => self.send(format!("info string {}", e)),
+                }
+            }
+            "go" => {
+                self.stop_search();
+                self.go(args);
+            }
+            "stop" => self.stop_search(),
+            "quit" => {
+                self.stop_search();
+                return false;
+            }
+            _ => self.send(format!("info string unknown command {}", command)),
+        }
+        return true;
+    }
+
+    //setoption name <name> [value <value>], the name may contain spaces
+    fn set_option(&mut self, args: &[&str]) {
+        let value_at = args.iter().position(|a| *a == "value").unwrap_or(args.len());
+        let name = args.get(1..value_at).unwrap_or(&[]).join(" ");
+        let value = args.get(value_at + 1..).unwrap_or(&[]).join(" ");
+        match name.to_lowercase().as_str() {
+            "hash" => match value.parse::<usize>() {
+                Ok(mb) => self.searcher = Some(Searcher::with_tt_size(mb.clamp(1, MAX_HASH_MB))),
+                Err(_) => self.send(format!("info string bad Hash value {}", value)),
+            },
+            "clear hash" => self.searcher.as_mut().unwrap().clear(),
+            "uci_chess960" => self.chess960 = value.eq_ignore_ascii_case("true"),
+            _ => self.send(format!("info string unknown option {}", name)),
+        }
+    }
+
+    //position startpos|fen <fen> [moves <move>...], nothing changes if any part is wrong
+    fn position(&self, args: &[&str]) -> Result<Game, String> {
+        let moves_at = args.iter().position(|a| *a == "moves").unwrap_or(args.len());
+        let mut game = match args.first() {
+            Some(&"startpos") => my_chess::start(),
+            Some(&"fen") => Game::from_fen(&args[1..moves_at].join(" ")).map_err(|e| e.to_string())?,
+            _ => return Err("position needs startpos or fen".to_string()),
+        };
+        game.chess960 |= self.chess960;
+        for mv in args.get(moves_at + 1..).unwrap_or(&[]) {
+            let parsed = Move::parse(mv).map_err(|e| e.to_string())?;
+            game.move_piece(parsed).map_err(|e| format!("illegal move {}: {}", mv, e))?;
+        }
+        return Ok(game);
+    }
+
+    fn go(&mut self, args: &[&str]) {
+        let (limits, infinite) = go_limits(args, self.side_to_move());
+        self.think(limits, infinite);
+    }
+
+    fn side_to_move(&self) -> Color {
+        return if self.game.turn_counter % 2 == 1 {Color::W} else {Color::B};
+    }
+
+    //searches the current position on another thread, which sends the answer in the protocol spoken
+    fn think(&mut self, limits: Limits, infinite: bool) {
+        let mut searcher = self.searcher.take().unwrap();
+        searcher.stop.store(false, Ordering::Relaxed);
+        let stop = searcher.stop.clone();
+        let game = self.game.clone();
+        let out = self.out.clone();
+        let flag = stop.clone();
+        let protocol = self.protocol;
+        let post = protocol == Protocol::Uci || self.xboard.post;
+        let aborted = Arc::new(Mutex::new(false));
+        let abort = aborted.clone();
+        let handle = thread::spawn(move || {
+            let result = searcher.search(&game, limits, |r| {
+                if post {
+                    let _ = out.send(info_line(protocol, r));
+                }
+            });
+            //go infinite may only answer once it is told to stop
+            while infinite && !flag.load(Ordering::Relaxed) {
+                thread::sleep(Duration::from_millis(1));
+            }
+            let answer = match protocol {
+                Protocol::Uci => vec![bestmove_line(&result)],
+                Protocol::Xboard => xboard_answer(&game, &result),
+            };
+            //held while answering, so an abort comes either before the answer or after it
+            let aborted = abort.lock().unwrap();
+            if *aborted {
+                return (searcher, result, false);
+            }
+            for line in answer {
+                let _ = out.send(line);
+            }
+            drop(aborted);
+            return (searcher, result, true);
+        });
+        self.thinking = Some((handle, stop, aborted));
+    }
+
+    //ends a running search, which then sends its best move
+    fn stop_search(&mut self) {
+        self.finish_search(true, true);
+    }
+
+    //ends a running search without a move, xboard only wants one after ?
+    fn abort_search(&mut self) {
+        self.finish_search(true, false);
+    }
+
+    //picks up a search that has ended, with stop it is ended first and without play it doesn't answer.
+    //with xboard a move that was sent is played on the engine's game, xboard has played it already
+    fn finish_search(&mut self, stop: bool, play: bool) {
+        let Some((handle, flag, aborted)) = self.thinking.take() else {return};
+        if !stop && !handle.is_finished() {
+            self.thinking = Some((handle, flag, aborted));
+            return;
+        }
+        if !play {
+            *aborted.lock().unwrap() = true;
+        }
+        flag.store(true, Ordering::Relaxed);
+        let (searcher, result, answered) = handle.join().unwrap();
+        self.searcher = Some(searcher);
+        if answered && self.protocol == Protocol::Xboard {
+            if let Some(best) = result.best {
+                let _ = self.game.move_piece(best);
+            }
+        }
+    }
+
+    //returns false on quit
+    fn handle_xboard(&mut self, line: &str) -> bool {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let Some((command, args)) = words.split_first() else {return true};
+        //a move the engine sent comes before whatever xboard says after it
+        self.finish_search(false, true);
+        let number = |n: usize| -> Option<f64> {
+            return args.get(n)?.parse().ok();
+        };
+        match *command {
+            "protover" => self.send(XBOARD_FEATURES.to_string()),
+            "new" => {
+                self.abort_search();
+                self.game = my_chess::start();
+                self.chess960 = false;
+                self.xboard.side = Some(Color::B);
+                self.xboard.depth = MAX_DEPTH;
+                self.searcher.as_mut().unwrap().clear();
+            }
+            "variant" => {
+                self.abort_search();
+                self.chess960 = args.first() == Some(&"fischerandom");
+                self.game.chess960 = self.chess960;
+            }
+            "force" | "result" => {
+                self.abort_search();
+                self.xboard.side = None;
+            }
+            "go" => {
+                self.abort_search();
+                self.xboard.side = Some(self.side_to_move());
+                self.xboard_think();
+            }
+            "playother" => {
+                self.abort_search();
+                self.xboard.side = Some(self.side_to_move().get_inverted());
+            }
+            "usermove" => {
+                self.abort_search();
+                self.usermove(args.first().copied().unwrap_or(""));
+            }
+            "setboard" => {
+                self.abort_search();
+                match Game::from_fen(&args.join(" ")) {
+                    Ok(mut game) => {
+                        game.chess960 |= self.chess960;
+                        self.game = game;
+                    }
+                    Err(e) => self.send(format!("tellusererror Illegal position: {}", e)),
+                }
+            }
+            "undo" => {
+                self.abort_search();
+                self.game.undo();
+            }
+            "remove" => {
+                self.abort_search();
+                self.game.undo();
+                self.game.undo();
+            }
+            //move now, every other command ends a search without a move
+            "?" => self.stop_search(),
+            //centiseconds on the engine's clock
+            "time" => self.xboard.clock = number(0).map(|cs| Duration::from_millis((cs as u64).saturating_mul(10))),
+            //level <moves per control> <base as minutes or minutes:seconds> <increment in seconds>, the clock comes with time
+            //times too large for a Duration, e.g. inf, are refused and the old setting is kept
+            "level" => {
+                match Duration::try_from_secs_f64(number(2).unwrap_or(0.0).max(0.0)) {
+                    Ok(increment) => {
+                        self.xboard.moves_per_control = number(0).unwrap_or(0.0) as u64;
+                        self.xboard.increment = increment;
+                    }
+                    Err(_) => self.send(format!("Error (bad value): {}", line)),
+                }
+            }
+            "st" => {
+                match number(0).map(|secs| Duration::try_from_secs_f64(secs.max(0.0))) {
+                    Some(Err(_)) => self.send(format!("Error (bad value): {}", line)),
+                    movetime => self.xboard.movetime = movetime.and_then(|t| t.ok()),
+                }
+            }
+            "sd" => self.xboard.depth = number(0).map_or(MAX_DEPTH, |d| (d as usize).clamp(1, MAX_DEPTH)),
+            "post" => self.xboard.post = true,
+            "nopost" => self.xboard.post = false,
+            "ping" => self.send(format!("pong {}", args.first().unwrap_or(&""))),
+            "quit" => {
+                self.abort_search();
+                return false;
+            }
+            //nothing to do for these
+            "xboard" | "accepted" | "rejected" | "otim" | "hard" | "easy" | "random" | "computer" | "name" | "rating" | "ics" | "draw" => {}
+            //moves without usermove in front, if xboard rejected that feature
+            _ if Move::parse(command).is_ok() || command.starts_with("O-O") => {
+                self.abort_search();
+                self.usermove(command);
+            }
+            _ => self.send(format!("Error (unknown command): {}", command)),
+        }
+        return true;
+    }
+
+    //plays the opponent's move, the engine answers if it is its turn. castling may come as O-O in chess960
+    fn usermove(&mut self, text: &str) {
+        let parsed = match Move::parse(text) {
+            Ok(mv) => mv,
+            Err(_) => match self.game.parse_san(text) {
+                Ok(mv) => mv,
+                Err(e) => return self.send(format!("Illegal move ({}): {}", e, text)),
+            },
+        };
+        if let Err(e) = self.game.move_piece(parsed) {
+            return self.send(format!("Illegal move ({}): {}", e, text));
+        }
+        if let Some(line) = result_line(self.game.check_state()) {
+            self.send(line);
+        } else if self.xboard.side == Some(self.side_to_move()) {
+            self.xboard_think();
+        }
+    }
+
+    fn xboard_think(&mut self) {
+        if self.game.check_state().is_over() {
+            return;
+        }
+        let limits = self.xboard_limits();
+        self.think(limits, false);
+    }
+
+    //st wins over the clock, without either the search runs until ? or a limit from sd
+    fn xboard_limits(&self) -> Limits {
+        let movetime = self.xboard.movetime.or_else(|| {
+            let left = self.xboard.clock?;
+            //moves the engine has made in this game, counted from the FEN's move number
+            let made = ((self.game.turn_counter - 1) / 2) as u64;
+            let moves_to_go = match self.xboard.moves_per_control {
+                0 => None,
+                per_control => Some(per_control - made % per_control),
+            };
+            return Some(time_budget(left, self.xboard.increment, moves_to_go));
+        });
+        return Limits{depth: self.xboard.depth, movetime: movetime};
+    }
+}
+
+//search limits for go and whether it is go infinite, without any limit the search runs until stop
+fn go_limits(args: &[&str], color: Color) -> (Limits, bool) {
+    let value = |key: &str| -> Option<u64> {
+        let at = args.iter().position(|a| *a == key)?;
+        return args.get(at + 1)?.parse().ok();
+    };
+    let (time, inc) = match color {
+        Color::W => (value("wtime"), value("winc")),
+        Color::B => (value("btime"), value("binc")),
+    };
+    let movetime = match (value("movetime"), time) {
+        (Some(ms), _) => Some(Duration::from_millis(ms)),
+        (None, Some(left)) => Some(time_budget(Duration::from_millis(left), Duration::from_millis(inc.unwrap_or(0)), value("movestogo"))),
+        (None, None) => None,
+    };
+    let depth = value("depth").map_or(MAX_DEPTH, |d| (d as usize).clamp(1, MAX_DEPTH));
+    return (Limits{depth: depth, movetime: movetime}, args.contains(&"infinite"));
+}
+
+//time for one move, the clock is split over the moves to go and most of the increment is used as well
+fn time_budget(left: Duration, increment: Duration, moves_to_go: Option<u64>) -> Duration {
+    //absurd values from the GUI saturate instead of wrapping to zero or overflowing
+    let moves_to_go = u32::try_from(moves_to_go.unwrap_or(DEFAULT_MOVES_TO_GO)).unwrap_or(u32::MAX).max(1);
+    let share = (left / moves_to_go).saturating_add(increment.saturating_mul(3) / 4);
+    return share.min(left.saturating_sub(MOVE_OVERHEAD)).max(Duration::from_millis(1));
+}
+
+fn info_line(protocol: Protocol, result: &SearchResult) -> String {
+    let pv: Vec<String> = result.pv.iter().map(|m| m.to_string()).collect();
+    if protocol == Protocol::Xboard {
+        //ply score time nodes pv, time in centiseconds
+        let score = match result.mate_in() {
+            Some(moves) if moves > 0 => XBOARD_MATE + moves,
+            Some(moves) => -XBOARD_MATE + moves,
+            None => result.score,
+        };
+        return format!("{} {} {} {} {}", result.depth, score, result.elapsed.as_millis() / 10, result.nodes, pv.join(" "));
+    }
+    let score = match result.mate_in() {
+        Some(moves) => format!("mate {}", moves),
+        None => format!("cp {}", result.score),
+    };
+    return format!("info depth {} score {} nodes {} nps {} time {} pv {}", result.depth, score, result.nodes, result.nps(), result.elapsed.as_millis(), pv.join(" "));
+}
+
+//the engine's move and the result if the move ends the game. xboard wants chess960 castling as O-O or O-O-O
+fn xboard_answer(game: &Game, result: &SearchResult) -> Vec<String> {
+    let Some(best) = result.best else {return vec![]};
+    let text = if best.is_castle() && game.chess960 {
+        if best.to.x > best.from.x {"O-O".to_string()} else {"O-O-O".to_string()}
+    } else {
+        best.to_string()
+    };
+    let mut answer = vec![format!("move {}", text)];
+    let mut after = game.clone();
+    if after.move_piece(best).is_ok() {
+        answer.extend(result_line(after.check_state()));
+    }
+    return answer;
+}
+
+//e.g. 1-0 {White wins by checkmate}, None while the game goes on
+fn result_line(state: GameState) -> Option<String> {
+    let score = match state {
+        GameState::ONGOING => return None,
+        GameState::WIN_W(_) => "1-0",
+        GameState::WIN_B(_) => "0-1",
+        GameState::DRAW(_) => "1/2-1/2",
+    };
+    return Some(format!("{} {{{}}}", score, state));
+}
+
+//0000 is the null move, sent when there is nothing to play
+fn bestmove_line(result: &SearchResult) -> String {
+    return match (result.best, result.pv.get(1)) {
+        (Some(best), Some(ponder)) => format!("bestmove {} ponder {}", best, ponder),
+        (Some(best), None) => format!("bestmove {}", best),
+        (None, _) => "bestmove 0000".to_string(),
+    };
+}
+
+fn main() {
+    let (out, lines) = mpsc::channel::<String>();
+    //printed from one place so the search thread and the command loop don't interleave their lines
+    let printer = thread::spawn(move || {
+        let mut stdout = io::stdout();
+        for line in lines {
+            if writeln!(stdout, "{}", line).and_then(|_| stdout.flush()).is_err() {
+                return;
+            }
+        }
+    });
+
+    let mut engine = Engine::new(out);
+    for line in io::stdin().lock().lines() {
+        let Ok(line) = line else {break};
+        if !engine.handle(&line) {
+            break;
+        }
+    }
+    engine.stop_search();
+    drop(engine);
+    printer.join().unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc::Receiver;
+
+    fn engine() -> (Engine, Receiver<String>) {
+        let (out, lines) = mpsc::channel();
+        return (Engine::new(out), lines);
+    }
+
+    //skips lines until one starting with prefix arrives
+    fn wait_for(lines: &Receiver<String>, prefix: &str) -> String {
+        loop {
+            let line = lines.recv_timeout(Duration::from_secs(30)).expect("engine went quiet");
+            if line.starts_with(prefix) {
+                return line;
+            }
+        }
+    }
+
+    #[test]
+    fn handshake() {
+        let (mut engine, lines) = engine();
+        assert!(engine.handle("uci"));
+        assert_eq!(wait_for(&lines, "id name"), "id name my_chess");
+        wait_for(&lines, "uciok");
+        engine.handle("isready");
+        assert_eq!(lines.recv().unwrap(), "readyok");
+        engine.handle("setoption name Hash value 1");
+        engine.handle("setoption name UCI_Chess960 value true");
+        assert!(engine.chess960);
+        engine.handle("setoption name Contempt value 10");
+        assert_eq!(lines.recv().unwrap(), "info string unknown option Contempt");
+        assert!(!engine.handle("quit"));
+    }
+
+    #[test]
+    fn position() {
+        let (mut engine, lines) = engine();
+        engine.handle("position startpos moves e2e4 e7e5 g1f3");
+        assert_eq!(engine.game.to_fen(), "rnbqkbnr/pppp1ppp/8/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2");
+        engine.handle("position fen 4k3/8/8/8/8/8/8/4K2R w K - 0 1 moves e1g1");
+        assert_eq!(engine.game.to_fen(), "4k3/8/8/8/8/8/8/5RK1 b - - 1 1");
+
+        //a bad move leaves the last position alone
+        engine.handle("position startpos moves e2e5");
+        assert!(wait_for(&lines, "info string").contains("e2e5"));
+        assert_eq!(engine.game.to_fen(), "4k3/8/8/8/8/8/8/5RK1 b - - 1 1");
+
+        //chess960 castling is the king taking its rook
+        engine.handle("setoption name UCI_Chess960 value true");
+        engine.handle("position fen 4k3/8/8/8/8/8/8/1R2K1R1 w GB - 0 1 moves e1g1");
+        assert_eq!(engine.game.board.placement_fen(), "4k3/8/8/8/8/8/8/1R3RK1");
+    }
+
+    #[test]
+    fn go() {
+        let (mut engine, lines) = engine();
+        engine.handle("position fen 6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1");
+        engine.handle("go depth 3");
+        //a mate ends the search early, it won't get any shorter
+        let info = wait_for(&lines, "info depth 1 ");
+        assert!(info.contains("score mate 1") && info.ends_with("pv a1a8"), "{}", info);
+        assert_eq!(wait_for(&lines, "bestmove"), "bestmove a1a8");
+
+        //nothing to play when mated
+        engine.handle("position fen R5k1/5ppp/8/8/8/8/8/6K1 b - - 1 1");
+        engine.handle("go movetime 100");
+        assert_eq!(wait_for(&lines, "bestmove"), "bestmove 0000");
+    }
+
+    #[test]
+    fn go_infinite() {
+        let (mut engine, lines) = engine();
+        engine.handle("position startpos");
+        engine.handle("go infinite");
+        wait_for(&lines, "info depth 2 ");
+        engine.handle("stop");
+        let best = wait_for(&lines, "bestmove");
+        let mv = Move::parse(best.split_whitespace().nth(1).unwrap()).unwrap();
+        assert!(engine.game.get_all_legal_moves().iter().any(|m| m.matches(mv)));
+        //the searcher is back for the next go
+        assert!(engine.searcher.is_some());
+    }
+
+    #[test]
+    fn xboard_game() {
+        let (mut engine, lines) = engine();
+        for command in ["xboard", "protover 2", "new", "sd 2"] {
+            assert!(engine.handle(command));
+        }
+        assert!(wait_for(&lines, "feature").ends_with("done=1"));
+        assert_eq!(engine.protocol, Protocol::Xboard);
+
+        //the engine plays black after new and answers right away
+        engine.handle("usermove e2e4");
+        let reply = wait_for(&lines, "move ");
+        engine.handle("force");
+        assert_eq!(engine.game.history.len(), 2);
+        assert_eq!(format!("move {}", engine.game.history[1]), reply);
+
+        engine.handle("usermove e2e4");
+        assert!(wait_for(&lines, "Illegal move").ends_with("e2e4"));
+        engine.handle("remove");
+        assert!(engine.game.history.is_empty());
+        engine.handle("ping 7");
+        assert_eq!(lines.recv().unwrap(), "pong 7");
+        engine.handle("frobnicate");
+        assert_eq!(lines.recv().unwrap(), "Error (unknown command): frobnicate");
+    }
+
+    #[test]
+    fn xboard_abort() {
+        let (mut engine, lines) = engine();
+        for command in ["xboard", "protover 2", "new"] {
+            engine.handle(command);
+        }
+        wait_for(&lines, "feature");
+        //without a clock the search would go on to MAX_DEPTH, force ends it without a move
+        engine.handle("usermove e2e4");
+        thread::sleep(Duration::from_millis(100));
+        engine.handle("force");
+        assert!(engine.thinking.is_none());
+        assert_eq!(engine.game.history.len(), 1);
+        assert!(!lines.try_iter().any(|l| l.starts_with("move")));
+
+        //only ? makes it move
+        engine.handle("go");
+        thread::sleep(Duration::from_millis(100));
+        engine.handle("?");
+        let reply = wait_for(&lines, "move ");
+        assert_eq!(engine.game.history.len(), 2);
+        assert_eq!(format!("move {}", engine.game.history[1]), reply);
+    }
+
+    #[test]
+    fn xboard_mates() {
+        let (mut engine, lines) = engine();
+        engine.handle("xboard");
+        engine.handle("post");
+        engine.handle("setboard 6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1");
+        engine.handle("go");
+        //ply score time nodes pv, mate in one
+        assert!(wait_for(&lines, "1 ").starts_with("1 100001 "));
+        assert_eq!(wait_for(&lines, "move"), "move a1a8");
+        assert_eq!(lines.recv().unwrap(), "1-0 {White wins by checkmate}");
+
+        engine.handle("setboard 8/8/8/8/8/8/8/8 w - - 0 1");
+        assert!(wait_for(&lines, "tellusererror").starts_with("tellusererror Illegal position"));
+    }
+
+    #[test]
+    fn xboard_chess960() {
+        let (mut engine, lines) = engine();
+        engine.handle("xboard");
+        engine.handle("new");
+        engine.handle("variant fischerandom");
+        engine.handle("setboard 4k3/pppppppp/8/8/8/8/8/1R2K1R1 w GB - 0 1");
+        engine.handle("force");
+        engine.handle("usermove O-O");
+        assert_eq!(engine.game.board.placement_fen(), "4k3/pppppppp/8/8/8/8/8/1R3RK1");
+        assert!(lines.try_recv().is_err());
+
+        //the engine castles in xboard's notation as well
+        engine.handle("setboard 4k3/8/8/8/8/8/8/1R2K2R w B - 0 1");
+        let game = engine.game.clone();
+        let castle = game.clone().get_all_legal_moves().into_iter().find(|m| m.is_castle()).unwrap();
+        let result = SearchResult{best: Some(castle), score: 0, depth: 1, nodes: 1, pv: vec![castle], elapsed: Duration::ZERO, tt_probes: 0, tt_hits: 0, lines: vec![]};
+        assert_eq!(xboard_answer(&game, &result), vec!["move O-O-O"]);
+    }
+
+    #[test]
+    fn xboard_clock() {
+        let (mut engine, lines) = engine();
+        engine.handle("xboard");
+        engine.handle("level 40 5 0");
+        engine.handle("time 6000");
+        assert_eq!(engine.xboard_limits().movetime, Some(Duration::from_millis(1500)));
+        engine.handle("level 0 2:30 2");
+        assert_eq!(engine.xboard_limits().movetime, Some(Duration::from_millis(3500)));
+        engine.handle("st 0.5");
+        engine.handle("sd 4");
+        assert_eq!(engine.xboard_limits(), Limits{depth: 4, movetime: Some(Duration::from_millis(500))});
+
+        //values no clock can hold are refused, the settings stay as they were
+        engine.handle("st inf");
+        assert_eq!(lines.recv().unwrap(), "Error (bad value): st inf");
+        engine.handle("level 0 5 1e300");
+        assert_eq!(lines.recv().unwrap(), "Error (bad value): level 0 5 1e300");
+        assert_eq!(engine.xboard_limits(), Limits{depth: 4, movetime: Some(Duration::from_millis(500))});
+        engine.handle("time 1e300");
+        assert_eq!(engine.xboard.clock, Some(Duration::from_millis(u64::MAX)));
+    }
+
+    #[test]
+    fn time_control() {
+        let (limits, infinite) = go_limits(&["wtime", "60000", "btime", "1000", "winc", "1000"], Color::W);
+        assert_eq!(limits, Limits{depth: MAX_DEPTH, movetime: Some(Duration::from_millis(2750))});
+        assert!(!infinite);
+        let (limits, _) = go_limits(&["wtime", "60000", "btime", "1000", "movestogo", "1"], Color::B);
+        assert_eq!(limits.movetime, Some(Duration::from_millis(950)));
+        let (limits, _) = go_limits(&["wtime", "1000", "btime", "1000", "winc", "18446744073709551615", "movestogo", "4294967296"], Color::W);
+        assert_eq!(limits.movetime, Some(Duration::from_millis(950)));
+        let (limits, _) = go_limits(&["depth", "5", "movetime", "300"], Color::B);
+        assert_eq!(limits, Limits{depth: 5, movetime: Some(Duration::from_millis(300))});
+        let (limits, infinite) = go_limits(&["infinite"], Color::W);
+        assert_eq!(limits, Limits::depth(MAX_DEPTH));
+        assert!(infinite);
+    }
+}
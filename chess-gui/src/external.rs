@@ -0,0 +1,201 @@
+//engine programs the GUI can play against, speaking UCI (uci.rs) or xboard's Chess Engine Communication Protocol (cecp.rs).
+//the program's output is read on its own thread, poll picks up whatever arrived without waiting for it
+use std::io::{self, BufRead, BufReader, Write};
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, TryRecvError};
+use std::time::{Duration, Instant};
+
+use crate::cecp::CecpEngine;
+use crate::my_chess::Game;
+use crate::my_chess::Move;
+use crate::uci::UciEngine;
+
+//how long the engine may take to answer while it starts up
+pub const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+//after quit the engine gets this long to exit before it is killed
+const QUIT_TIMEOUT: Duration = Duration::from_millis(500);
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Protocol {
+    Uci,
+    Cecp,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Score {
+    //centipawns from the engine's point of view
+    Cp(i32),
+    //moves until mate, negative if the engine gets mated
+    Mate(i32),
+}
+
+impl std::fmt::Display for Score {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Score::Cp(cp) => write!(f, "{}", cp),
+            Score::Mate(moves) => write!(f, "mate {}", moves),
+        }
+    }
+}
+
+//the parts of the engine's thinking output the GUI shows, anything else it reports is skipped
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct Info {
+    pub depth: Option<usize>,
+    pub score: Option<Score>,
+    pub nodes: Option<u64>,
+    pub pv: Vec<Move>,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Event {
+    Info(Info),
+    //None when the engine has nothing to play
+    BestMove(Option<Move>),
+    //the engine gave up instead of moving
+    Resigned,
+    //the engine closed its output, usually because it crashed or quit
+    Exited,
+}
+
+//what the GUI needs from an engine program, whichever protocol it speaks
+pub trait ExternalEngine {
+    //name the engine gave itself
+    fn name(&self) -> &str;
+    //what the engine last said about the search the GUI waits for
+    fn info(&self) -> &Info;
+    fn new_game(&mut self) -> io::Result<()>;
+    //searches the game's current position for movetime, poll hands out the result
+    fn go(&mut self, game: &Game, movetime: Duration) -> io::Result<()>;
+    //the move of the running search is no longer wanted, it is dropped when it arrives
+    fn stop(&mut self) -> io::Result<()>;
+    //waiting for a move
+    fn is_thinking(&self) -> bool;
+    //next thing the engine said about the search the GUI waits for, None if there is nothing new
+    fn poll(&mut self) -> Option<Event>;
+}
+
+//starts the engine program and waits until it is ready
+pub fn spawn(path: &str, protocol: Protocol) -> io::Result<Box<dyn ExternalEngine>> {
+    let process = Process::spawn(path)?;
+    match protocol {
+        Protocol::Uci => return Ok(Box::new(UciEngine::new(process)?)),
+        Protocol::Cecp => return Ok(Box::new(CecpEngine::new(process)?)),
+    }
+}
+
+//the streams of an engine program, commands go in one line at a time and every line it prints comes out as a message
+pub struct Process {
+    input: Box<dyn Write + Send>,
+    output: Receiver<String>,
+    //None when the streams don't belong to a program
+    child: Option<Child>,
+}
+
+impl Process {
+    pub fn spawn(path: &str) -> io::Result<Process> {
+        let mut child = Command::new(path).stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::null()).spawn()?;
+        let input = child.stdin.take().unwrap();
+        let output = child.stdout.take().unwrap();
+        let (sender, lines) = mpsc::channel();
+        std::thread::spawn(move || {
+            for line in BufReader::new(output).lines() {
+                let Ok(line) = line else {break};
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        let mut process = Process::from_streams(Box::new(input), lines);
+        process.child = Some(child);
+        return Ok(process);
+    }
+
+    //an engine behind any pair of streams
+    pub fn from_streams(input: Box<dyn Write + Send>, output: Receiver<String>) -> Process {
+        return Process{input: input, output: output, child: None};
+    }
+
+    pub fn send(&mut self, command: &str) -> io::Result<()> {
+        writeln!(self.input, "{}", command)?;
+        return self.input.flush();
+    }
+
+    //next line within timeout, None once the engine has exited
+    pub fn wait_for_line(&mut self, timeout: Duration) -> io::Result<Option<String>> {
+        match self.output.recv_timeout(timeout) {
+            Ok(line) => return Ok(Some(line)),
+            Err(RecvTimeoutError::Disconnected) => return Ok(None),
+            Err(RecvTimeoutError::Timeout) => return Err(io::Error::new(io::ErrorKind::TimedOut, "engine did not answer")),
+        }
+    }
+
+    //next line if one has arrived
+    pub fn try_line(&mut self) -> Result<String, TryRecvError> {
+        return self.output.try_recv();
+    }
+}
+
+impl Drop for Process {
+    fn drop(&mut self) {
+        let _ = self.send("quit");
+        let Some(child) = &mut self.child else {return};
+        let deadline = Instant::now() + QUIT_TIMEOUT;
+        while Instant::now() < deadline {
+            if let Ok(Some(_)) = child.try_wait() {
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        let _ = child.kill();
+        let _ = child.wait();
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use std::sync::mpsc::Sender;
+    use std::sync::{Arc, Mutex};
+
+    //stand-in engine that answers every command it is sent from a script, and remembers the commands
+    struct Scripted {
+        replies: Sender<String>,
+        script: fn(&str) -> Vec<&'static str>,
+        received: Arc<Mutex<Vec<String>>>,
+        buffer: Vec<u8>,
+    }
+
+    impl Write for Scripted {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.buffer.extend_from_slice(buf);
+            while let Some(end) = self.buffer.iter().position(|b| *b == b'\n') {
+                let line: Vec<u8> = self.buffer.drain(..=end).collect();
+                let command = String::from_utf8(line).unwrap().trim().to_string();
+                for reply in (self.script)(&command) {
+                    let _ = self.replies.send(reply.to_string());
+                }
+                self.received.lock().unwrap().push(command);
+            }
+            return Ok(buf.len());
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            return Ok(());
+        }
+    }
+
+    //a process whose output comes from script, with the list of commands it got
+    pub fn scripted(script: fn(&str) -> Vec<&'static str>) -> (Process, Arc<Mutex<Vec<String>>>) {
+        let (replies, output) = mpsc::channel();
+        let received = Arc::new(Mutex::new(vec![]));
+        let input = Scripted{replies: replies, script: script, received: received.clone(), buffer: vec![]};
+        return (Process::from_streams(Box::new(input), output), received);
+    }
+
+    #[test]
+    fn missing_engine() {
+        assert!(spawn("./no-such-engine", Protocol::Uci).is_err());
+        assert!(spawn("./no-such-engine", Protocol::Cecp).is_err());
+    }
+}
@@ -4,18 +4,39 @@ use ggez::*;
 use graphics::{Canvas, Color, Image, Rect, Text};
 use mint::Point2;
 use std::{ops::Div, path,env};
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
 
 //Imports my original chess library (slighlty modified for the sake of compatibility with my GUI)
 //The GUI implementation uses a wrapper so that I can easily swap between my own backend and the one that I'm using for the assignment
-// #[path = "./my_chess.rs"]
-// mod my_chess;
+#[path = "./my_chess.rs"]
+mod my_chess;
+mod pgn;
+mod differential;
+mod search;
+mod external;
+mod uci;
+mod cecp;
+mod analysis;
 
 
 const GRID_SIZE: (usize,usize) = (8,8);
 const GRID_CELL_SIZE: (usize,usize) = (64,64);
 const BOARD_SIZE: (usize,usize) = ((GRID_SIZE.0*GRID_CELL_SIZE.0) ,(GRID_SIZE.1*GRID_CELL_SIZE.1));
 
-const SCREEN_SIZE: (f32,f32) = ((GRID_SIZE.0*GRID_CELL_SIZE.0) as f32 ,(GRID_SIZE.1*GRID_CELL_SIZE.1) as f32);
+//strip below the board holding the undo, redo, claim draw and resign buttons
+const TOOLBAR_HEIGHT: usize = 32;
+const TOOLBAR_BUTTONS: usize = 4;
+
+//evaluation bar right of the board, white's share grows from the bottom
+const EVAL_BAR_WIDTH: usize = 24;
+//analysis lines below the toolbar, a header and one row per line
+const ANALYSIS_ROW_HEIGHT: usize = 20;
+const ANALYSIS_HEIGHT: usize = (analysis::MAX_LINES+1)*ANALYSIS_ROW_HEIGHT + 8;
+const DEFAULT_ANALYSIS_LINES: usize = 3;
+
+const SCREEN_SIZE: (f32,f32) = ((BOARD_SIZE.0+EVAL_BAR_WIDTH) as f32 ,(BOARD_SIZE.1+TOOLBAR_HEIGHT+ANALYSIS_HEIGHT) as f32);
 const OFFSET_X_P:usize = 2;
 const OFFSET_Y_P:usize = 2;
 const OFFSET_X_H:usize = 32;
@@ -27,6 +48,22 @@ const OFFSET_Y_BT:usize = 50;
 
 const FPS: usize = 120;
 
+//rejected moves are explained after the title
+const WINDOW_TITLE: &str = "Chess";
+
+//finished and saved games are appended to this file
+const PGN_ARCHIVE: &str = "games.pgn";
+const PGN_TAGS: [(&str,&str); 2] = [("Event","Casual game"),("Site","chess-gui")];
+
+//how long the computer thinks about each move
+const COMPUTER_MOVE_TIME: std::time::Duration = std::time::Duration::from_millis(1000);
+
+//engine used unless --engine picks the other one
+#[cfg(feature = "my_chess_backend")]
+const DEFAULT_ENGINE: &str = "my_chess";
+#[cfg(not(feature = "my_chess_backend"))]
+const DEFAULT_ENGINE: &str = "chesslib";
+
 enum off {
     P,
     H,
@@ -35,143 +72,392 @@ enum off {
 }
 
 
-// struct GameWrapper {
-//     game: my_chess::Game,
-//     promo_from: Option<GridPosition>,
-//     promo_to: Option<GridPosition>,
-//     is_promotion: bool
-// }
+//everything the GUI needs from a chess engine, pieces, sides and states are given in chesslib's types
+trait ChessBackend {
+    fn new() -> Self where Self: Sized;
+    //chess960 start position number index (0-959), None if the engine only plays standard chess
+    fn new_chess960(index:usize) -> Option<Self> where Self: Sized;
+    fn name(&self) -> &'static str;
+    fn make_move(&mut self,from:GridPosition,to:GridPosition) -> Result<(),my_chess::MoveError>;
+    //finishes a move to the last rank, the state is State::Promotion until then
+    fn promote(&mut self,promotion:PieceType) -> bool;
+    fn get_state(&mut self) -> State;
+    //during a promotion this is the side promoting
+    fn get_turn(&mut self) -> Side;
+    fn get_all_pieces(&mut self) -> Vec<RendPiece>;
+    fn get_moves(&mut self, from:GridPosition) -> Vec<MoveHighlight>;
+    fn is_selectable(&mut self,from:GridPosition) -> bool;
+    fn undo(&mut self) -> bool;
+    fn redo(&mut self) -> bool;
+    //winner and reason, ONGOING while the game goes on
+    fn get_result(&mut self) -> my_chess::GameState;
+    fn can_claim_draw(&mut self) -> bool;
+    fn claim_draw(&mut self) -> Option<my_chess::DrawReason>;
+    //the side to move gives up
+    fn resign(&mut self) -> bool;
+    //both players agree on a draw
+    fn agree_draw(&mut self) -> bool;
+    fn to_pgn(&mut self) -> String;
+    //the position as a my_chess game, which the computer player searches
+    fn to_game(&mut self) -> my_chess::Game;
+}
 
-// impl GameWrapper {
-//     fn make_move(&mut self,from:GridPosition,to:GridPosition)->bool{
-//         if self.is_promotion {return false};
+//plays a move given as a my_chess::Move the way clicking from and to would, the promotion piece is picked right away
+fn play_move<B: ChessBackend>(wrap: &mut B, mv: my_chess::Move) -> Result<(), my_chess::MoveError> {
+    wrap.make_move(GridPosition::new(mv.from.x, mv.from.y), GridPosition::new(mv.to.x, mv.to.y))?;
+    if wrap.get_state() == State::Promotion {
+        wrap.promote(to_lib_piece(mv.promotion.unwrap_or(my_chess::PieceType::QUEEN)));
+    }
+    return Ok(());
+}
 
+fn to_my_piece(piece:PieceType) -> Option<my_chess::PieceType> {
+    match piece {
+        PieceType::Pawn => return Some(my_chess::PieceType::PAWN),
+        PieceType::King => return Some(my_chess::PieceType::KING),
+        PieceType::Queen => return Some(my_chess::PieceType::QUEEN),
+        PieceType::Rook => return Some(my_chess::PieceType::ROOK),
+        PieceType::Bishop => return Some(my_chess::PieceType::BISHIOP),
+        PieceType::Knight => return Some(my_chess::PieceType::KNIGHT),
+        PieceType::Empty => return None,
+    }
+}
 
-//         if (to.y == 7 || to.y == 0) && self.game.board.get_piece(from.to_tup()).variant == my_chess::PieceType::PAWN {
-//             self.is_promotion = true;
-//             self.promo_from = Some(from);
-//             self.promo_to = Some(to);
-//             return false;
-//         }
-        
-//         self.promo_from = None;
-//         self.promo_to = None;
-//         let enc_from = my_chess::encode_notation(from.to_tup());
-//         let enc_to = my_chess::encode_notation(to.to_tup());
-
-//         return self.game.move_piece(&enc_from, &enc_to, "X");
-//     }
-
-//     fn promote(&mut self,promotion:chesslib::PieceType)->bool{
-//         let promotion_str: &str = match promotion {
-//             PieceType::Pawn => "P",
-//             PieceType::King => "K",
-//             PieceType::Queen => "Q",
-//             PieceType::Rook => "R",
-//             PieceType::Bishop => "B",
-//             PieceType::Knight => "N",
-//             PieceType::Empty => "X",
-//         };
-//         let enc_from = my_chess::encode_notation(self.promo_from.unwrap().to_tup());
-//         let enc_to = my_chess::encode_notation(self.promo_to.unwrap().to_tup());
-//         self.game.move_piece(&enc_from, &enc_to, promotion_str);
-//         self.is_promotion = false;
-//         self.promo_from = None;
-//         self.promo_to = None;
-//         return true;
-//     }
-
-//     fn get_state(&mut self) -> State {
-//         if self.is_promotion {return State::Promotion};
-        
-//         match self.game.check_state() {
-//             my_chess::GameState::DRAW => {return State::Draw},
-//             my_chess::GameState::ONGOING => {return State::Playing;},
-//             my_chess::GameState::WIN_B | my_chess::GameState::WIN_W => {return State::Checkmate;}
-//         }
-//     }
-
-
-//     fn get_turn(&mut self) -> Side{
-//         let side = match self.game.turn_counter % 2 {
-//             0 => Side::Black,
-//             1 => Side::White,
-//             _ => panic!()
-//         };
-//         return side;
-//     }
-//     fn get_all_pieces(&mut self) -> Vec<RendPiece> {
-//         let mut unt_pieces = self.game.board.positions;
-        
-//         let mut pieces: Vec<RendPiece> = vec![];
-
-//         for i in 0..8 {
-//             for j in 0..8 {
-//                 let field: my_chess::PositionContent  = unt_pieces[j][i].content;
-//                 match field {
-//                     my_chess::PositionContent::NONE => {},
-//                     my_chess::PositionContent::PIECE_CONT(p) => {
-//                         let color = match p.color {
-//                             my_chess::Color::B => Side::Black,
-//                             my_chess::Color::W => Side::White
-//                         };
-//                         let variant = match p.variant {
-//                               my_chess::PieceType::PAWN => {chesslib::PieceType::Pawn},
-//                               my_chess::PieceType::KING => {chesslib::PieceType::King},
-//                               my_chess::PieceType::QUEEN => {chesslib::PieceType::Queen},
-//                               my_chess::PieceType::ROOK => {chesslib::PieceType::Rook},
-//                               my_chess::PieceType::BISHIOP => {chesslib::PieceType::Bishop},
-//                               my_chess::PieceType::KNIGHT => {chesslib::PieceType::Knight},
-//                               _=>panic!()
-//                         };
-
-//                         pieces.push(RendPiece::new(j, i, variant, color));
-//                     }
-//                 }
-//             }
-//         }
-
-//         return pieces;
-//     }
-//     fn get_moves(&mut self, from:GridPosition) -> Vec<MoveHighlight> {
-        
-//         let enc_from = my_chess::encode_notation(from.to_tup());
-//         let mut moves = self.game.get_legal_moves(&enc_from);
-
-//         let mut high = vec![];
-
-//         for lmove in moves {
-//             high.push(MoveHighlight::new(lmove.0, lmove.1));
-//         }
-
-//         return high;
-//     }
-//     fn is_selectable(&mut self,from:GridPosition) ->bool {
-//         let enc_from = my_chess::encode_notation(from.to_tup());
-//         return !self.game.get_legal_moves(&enc_from).is_empty();
-//     }
-//     fn new()->Self {
-//         return GameWrapper{game:my_chess::start(),promo_from:None,promo_to:None,is_promotion:false};
-//     }
-// }
+fn to_lib_piece(piece:my_chess::PieceType) -> PieceType {
+    match piece {
+        my_chess::PieceType::PAWN => return PieceType::Pawn,
+        my_chess::PieceType::KING => return PieceType::King,
+        my_chess::PieceType::QUEEN => return PieceType::Queen,
+        my_chess::PieceType::ROOK => return PieceType::Rook,
+        my_chess::PieceType::BISHIOP => return PieceType::Bishop,
+        my_chess::PieceType::KNIGHT => return PieceType::Knight,
+        my_chess::PieceType::NONE => return PieceType::Empty,
+    }
+}
+
+//my own engine, chess-gui --engine my_chess
+struct GameWrapper {
+    game: my_chess::Game,
+    promo_from: Option<GridPosition>,
+    promo_to: Option<GridPosition>,
+    is_promotion: bool
+}
 
+impl GameWrapper {
+    fn side_to_move(&self) -> my_chess::Color {
+        return if self.game.turn_counter % 2 == 1 {my_chess::Color::W} else {my_chess::Color::B};
+    }
+
+    fn cancel_promotion(&mut self) {
+        self.is_promotion = false;
+        self.promo_from = None;
+        self.promo_to = None;
+    }
+}
+
+impl ChessBackend for GameWrapper {
+    fn name(&self) -> &'static str {
+        return "my_chess";
+    }
+
+    fn make_move(&mut self,from:GridPosition,to:GridPosition)->Result<(),my_chess::MoveError>{
+        if self.is_promotion {return Err(my_chess::MoveError::MissingPromotion)};
+
+        let mv = my_chess::Move::new(from.to_square(),to.to_square());
+        let is_pawn = self.game.board.piece_at(mv.from).map_or(false, |p| p.variant == my_chess::PieceType::PAWN);
+        if (to.y == 7 || to.y == 0) && is_pawn {
+            //the piece is picked afterwards, a queen stands in for it to see if the move is legal
+            self.game.clone().move_piece(my_chess::Move::with_promotion(mv.from,mv.to,my_chess::PieceType::QUEEN))?;
+            self.is_promotion = true;
+            self.promo_from = Some(from);
+            self.promo_to = Some(to);
+            return Ok(());
+        }
+
+        self.game.move_piece(mv)?;
+        return Ok(());
+    }
+
+    fn promote(&mut self,promotion:chesslib::PieceType)->bool{
+        let (Some(from), Some(to)) = (self.promo_from, self.promo_to) else {return false};
+        let Some(piece) = to_my_piece(promotion) else {return false};
+        self.cancel_promotion();
+        return self.game.move_piece(my_chess::Move::with_promotion(from.to_square(),to.to_square(),piece)).is_ok();
+    }
+
+    fn get_state(&mut self) -> State {
+        if self.is_promotion {return State::Promotion};
+
+        match self.game.check_state() {
+            my_chess::GameState::DRAW(my_chess::DrawReason::STALEMATE) => return State::Stalemate,
+            my_chess::GameState::DRAW(_) => return State::Draw,
+            my_chess::GameState::ONGOING => return State::Playing,
+            my_chess::GameState::WIN_B(_) | my_chess::GameState::WIN_W(_) => return State::Checkmate,
+        }
+    }
+
+    fn get_turn(&mut self) -> Side{
+        return if self.side_to_move() == my_chess::Color::W {Side::White} else {Side::Black};
+    }
+
+    fn get_all_pieces(&mut self) -> Vec<RendPiece> {
+        let mut pieces: Vec<RendPiece> = vec![];
+
+        for i in 0..8 {
+            for j in 0..8 {
+                if let Some(p) = self.game.board.piece_at(my_chess::Square::new(j,i)) {
+                    let color = if p.color == my_chess::Color::W {Side::White} else {Side::Black};
+                    pieces.push(RendPiece::new(j, i, to_lib_piece(p.variant), color));
+                }
+            }
+        }
+
+        return pieces;
+    }
+
+    fn get_moves(&mut self, from:GridPosition) -> Vec<MoveHighlight> {
+        let mut high: Vec<MoveHighlight> = vec![];
+
+        //promotions give one move per piece but one highlight is enough
+        for lmove in self.game.get_legal_moves(from.to_square()) {
+            let highlight = MoveHighlight::new(lmove.to.x, lmove.to.y);
+            if !high.contains(&highlight) {
+                high.push(highlight);
+            }
+        }
+
+        return high;
+    }
+
+    fn is_selectable(&mut self,from:GridPosition) ->bool {
+        if self.is_promotion {return false};
+        return !self.game.get_legal_moves(from.to_square()).is_empty();
+    }
+
+    //a promotion still waiting for its piece is taken back first
+    fn undo(&mut self) -> bool {
+        if self.is_promotion {
+            self.cancel_promotion();
+            return true;
+        }
+        return self.game.undo().is_some();
+    }
+
+    fn redo(&mut self) -> bool {
+        if self.is_promotion {return false};
+        return self.game.redo().is_some();
+    }
+
+    fn get_result(&mut self) -> my_chess::GameState {
+        return self.game.check_state();
+    }
+
+    fn can_claim_draw(&mut self) -> bool {
+        return !self.is_promotion && self.game.claimable_draw().is_some();
+    }
+
+    fn claim_draw(&mut self) -> Option<my_chess::DrawReason> {
+        if self.is_promotion {return None};
+        return self.game.claim_draw();
+    }
+
+    fn resign(&mut self) -> bool {
+        if self.game.check_state().is_over() {return false};
+        self.cancel_promotion();
+        let side = self.side_to_move();
+        self.game.resign(side);
+        return true;
+    }
+
+    fn agree_draw(&mut self) -> bool {
+        if self.game.check_state().is_over() {return false};
+        self.cancel_promotion();
+        self.game.agree_draw();
+        return true;
+    }
+
+    fn to_pgn(&mut self) -> String {
+        return pgn::write_pgn(&mut self.game, &PGN_TAGS);
+    }
+
+    fn to_game(&mut self) -> my_chess::Game {
+        return self.game.clone();
+    }
+
+    fn new()->Self {
+        return GameWrapper{game:my_chess::start(),promo_from:None,promo_to:None,is_promotion:false};
+    }
+
+    fn new_chess960(index:usize) -> Option<Self> {
+        return Some(GameWrapper{game:my_chess::start_chess960(index),promo_from:None,promo_to:None,is_promotion:false});
+    }
+}
+
+//chesslib, the engine used for the assignment
 struct alt_GameWrapper {
-    game: Chess
+    game: Chess,
+    //chesslib does not keep the played moves, they are recorded here for the PGN export
+    history: Vec<my_chess::Move>,
+    //moves taken back with undo, the next one to redo is last
+    redo: Vec<my_chess::Move>,
+    //chesslib knows none of the move counting and repetition rules, my_chess decides about these draws
+    claimable: Option<my_chess::DrawReason>,
+    //result of the position according to my_chess
+    result: my_chess::GameState,
+    //a claimed or agreed draw or a resignation
+    declared: Option<my_chess::GameState>
 }
 
 impl alt_GameWrapper {
 
-    fn make_move(&mut self,from:GridPosition,to:GridPosition)->bool{
-        return self.game.make_move(from.map_to_bitboard(),to.map_to_bitboard());
+    //the recorded moves played on my_chess, the last one may still wait for its promotion piece.
+    //make_move only records moves my_chess takes, anything else it rejects is a bug and the replay ends there
+    fn replay(&self) -> my_chess::Game {
+        let mut replay = my_chess::start();
+        for (n, mv) in self.history.iter().enumerate() {
+            match replay.move_piece(*mv) {
+                Ok(_) => {},
+                Err(my_chess::MoveError::MissingPromotion) if n + 1 == self.history.len() => {},
+                Err(e) => {
+                    println!("my_chess rejects recorded move {}: {}", mv, e);
+                    break;
+                }
+            }
+        }
+        return replay;
+    }
+
+    //asks my_chess how the game stands and whether a draw may be claimed
+    fn update_result(&mut self) {
+        if self.game.get_state() == State::Promotion {
+            self.claimable = None;
+            return;
+        }
+        let mut replay = self.replay();
+        self.result = replay.check_state();
+        self.claimable = replay.claimable_draw();
+    }
+
+    //plays a recorded move on chesslib without touching the redo list
+    fn play(&mut self, mv: my_chess::Move) {
+        if !self.game.make_move(mv.from.index(), mv.to.index()) {return};
+        self.history.push(mv);
+        if let Some(promotion) = mv.promotion {
+            self.promote(to_lib_piece(promotion));
+        }
+    }
+}
+
+impl ChessBackend for alt_GameWrapper {
+
+    fn name(&self) -> &'static str {
+        return "chesslib";
+    }
+
+    fn make_move(&mut self,from:GridPosition,to:GridPosition)->Result<(),my_chess::MoveError>{
+        let mv = my_chess::Move::new(from.to_square(),to.to_square());
+        //my_chess keeps the recorded game, so a move it rejects isn't played on chesslib either.
+        //a promotion is only complete once the piece is picked
+        match self.replay().move_piece(mv) {
+            Err(my_chess::MoveError::MissingPromotion) | Ok(_) => {},
+            Err(e) => return Err(e),
+        }
+        if self.game.make_move(from.map_to_bitboard(),to.map_to_bitboard()) {
+            self.history.push(mv);
+            self.redo.clear();
+            self.update_result();
+            return Ok(());
+        }
+        //chesslib only says no
+        return Err(my_chess::MoveError::IllegalDestination(mv.to));
     }
 
     fn promote(&mut self,promotion:chesslib::PieceType)->bool{
         self.game.promote(promotion);
+        if let Some(last) = self.history.last_mut() {
+            last.promotion = to_my_piece(promotion);
+        }
+        self.update_result();
+        return true;
+    }
+
+    //chesslib can't take moves back, so the game is played again from the start without the last move
+    fn undo(&mut self) -> bool {
+        let Some(mv) = self.history.pop() else {return false};
+        let moves = std::mem::take(&mut self.history);
+        self.game = chesslib::Chess::new();
+        for m in moves {
+            self.play(m);
+        }
+        self.redo.push(mv);
+        self.declared = None;
+        self.update_result();
+        return true;
+    }
+
+    fn redo(&mut self) -> bool {
+        let Some(mv) = self.redo.pop() else {return false};
+        self.play(mv);
+        self.update_result();
+        return true;
+    }
+
+    fn get_result(&mut self) -> my_chess::GameState {
+        if let Some(declared) = self.declared {return declared};
+        if self.result.is_over() {return self.result};
+        //chesslib's own verdict, in case the two disagree
+        match self.game.get_state() {
+            State::Checkmate if self.game.get_playing_side() == Side::White => return my_chess::GameState::WIN_B(my_chess::WinReason::CHECKMATE),
+            State::Checkmate => return my_chess::GameState::WIN_W(my_chess::WinReason::CHECKMATE),
+            State::Stalemate => return my_chess::GameState::DRAW(my_chess::DrawReason::STALEMATE),
+            _ => return self.result,
+        }
+    }
+
+    fn can_claim_draw(&mut self) -> bool {
+        return self.claimable.is_some();
+    }
+
+    fn claim_draw(&mut self) -> Option<my_chess::DrawReason> {
+        let reason = self.claimable.take()?;
+        self.declared = Some(my_chess::GameState::DRAW(reason));
+        return Some(reason);
+    }
+
+    fn resign(&mut self) -> bool {
+        if self.get_result().is_over() {return false};
+        let mut replay = self.replay();
+        replay.resign(if self.get_turn() == Side::White {my_chess::Color::W} else {my_chess::Color::B});
+        self.declared = Some(replay.check_state());
+        self.claimable = None;
         return true;
     }
 
+    fn agree_draw(&mut self) -> bool {
+        if self.get_result().is_over() {return false};
+        self.declared = Some(my_chess::GameState::DRAW(my_chess::DrawReason::AGREEMENT));
+        self.claimable = None;
+        return true;
+    }
+
+    //replays the recorded moves with my_chess, which knows how to write them as PGN
+    fn to_pgn(&mut self) -> String {
+        let mut replay = self.replay();
+        replay.declared_result = self.declared;
+        return pgn::write_pgn(&mut replay, &PGN_TAGS);
+    }
+
+    fn to_game(&mut self) -> my_chess::Game {
+        return self.replay();
+    }
+
     fn get_state(&mut self) -> State {
-        return self.game.get_state();
+        //chesslib has no state for a game won other than by checkmate
+        match self.get_result() {
+            my_chess::GameState::DRAW(_) => return State::Draw,
+            my_chess::GameState::WIN_W(_) | my_chess::GameState::WIN_B(_) => return State::Checkmate,
+            my_chess::GameState::ONGOING => return self.game.get_state(),
+        }
     }
 
     fn get_turn(&mut self) -> Side{
@@ -197,7 +483,12 @@ impl alt_GameWrapper {
         return self.game.is_selectable(from.map_to_bitboard());
     }
     fn new()->Self {
-        return alt_GameWrapper{game:chesslib::Chess::new()};
+        return alt_GameWrapper{game:chesslib::Chess::new(),history:vec![],redo:vec![],claimable:None,result:my_chess::GameState::ONGOING,declared:None};
+    }
+
+    //chesslib always starts from the standard position
+    fn new_chess960(_index:usize) -> Option<Self> {
+        return None;
     }
 
 }
@@ -306,7 +597,11 @@ impl GridPosition {
     }
 
     fn map_to_bitboard(self)->usize{
-        return self.x + self.y*8; 
+        return self.to_square().index(); 
+    }
+
+    fn to_square(self) -> my_chess::Square {
+        return my_chess::Square::new(self.x,self.y);
     }
 
 }
@@ -357,8 +652,8 @@ impl RendPiece {
 
 }
 
-struct BoardState {
-    wrap:alt_GameWrapper,
+struct BoardState<B: ChessBackend> {
+    wrap:B,
     state:State,
     to_move:Side,
     pieces: Vec<RendPiece>,
@@ -366,21 +661,63 @@ struct BoardState {
     assets:Assets,
     from:Option<GridPosition>,
     in_restart_box:bool,
-    reset_flag:bool
-
+    reset_flag:bool,
+    //the next game is a chess960 one, only offered if the backend can play it
+    in_chess960_box:bool,
+    chess960_flag:bool,
+    has_chess960:bool,
+    //side played by the computer, None for two players on one board
+    computer:Option<Side>,
+    //search running on another thread, with the flag that cancels it. the searcher comes back with the move
+    thinking:Option<(mpsc::Receiver<(Option<my_chess::Move>, search::Searcher)>, Arc<AtomicBool>)>,
+    //the computer's searcher between moves, its transposition table carries over
+    searcher:Option<search::Searcher>,
+    //engine program that plays the computer's side instead of the searcher
+    program:Option<Box<dyn external::ExternalEngine>>,
+    //built-in search running on the position on the board, with the number of lines it shows when switched on
+    analysis:Option<analysis::Analysis>,
+    analysis_lines:usize,
 }
 
-impl BoardState {
+impl<B: ChessBackend> BoardState<B> {
 
-    fn new(ctx: &mut Context) -> Self {
-        let mut wrap = alt_GameWrapper::new();
+    fn new(ctx: &mut Context, computer: Option<Side>, program: Option<Box<dyn external::ExternalEngine>>, analysis_lines: Option<usize>) -> Self {
+        let mut wrap = B::new();
         let pieces = wrap.get_all_pieces();
         let assets = Assets::new(ctx);
-        return BoardState{assets:assets.unwrap(),wrap:wrap,state:State::Playing, to_move:Side::White,pieces:pieces,highlights:vec![],from:None,in_restart_box:false,reset_flag:false};
+        let has_chess960 = B::new_chess960(518).is_some();
+        let mut state = BoardState{assets:assets.unwrap(),wrap:wrap,state:State::Playing, to_move:Side::White,pieces:pieces,highlights:vec![],from:None,in_restart_box:false,reset_flag:false,in_chess960_box:false,chess960_flag:false,has_chess960:has_chess960,computer:computer,thinking:None,searcher:None,program:program,analysis:analysis_lines.map(analysis::Analysis::new),analysis_lines:analysis_lines.unwrap_or(DEFAULT_ANALYSIS_LINES)};
+        state.analyse_board();
+        return state;
     }
 
     fn reset(&mut self){
-        let mut wrap = alt_GameWrapper::new();
+        self.start_game(B::new());
+    }
+
+    //a random chess960 start position, the standard game if the backend can't play chess960
+    fn reset_chess960(&mut self){
+        let index = random_chess960_index();
+        match B::new_chess960(index) {
+            Some(wrap) => {
+                println!("Chess960 position {}", index);
+                self.start_game(wrap);
+            },
+            None => self.reset(),
+        }
+    }
+
+    fn start_game(&mut self, mut wrap: B){
+        self.stop_thinking();
+        if let Some(searcher) = &mut self.searcher {
+            searcher.clear();
+        }
+        if let Some(engine) = &mut self.program {
+            if let Err(e) = engine.new_game() {
+                println!("{} stopped working: {}", engine.name(), e);
+                self.program = None;
+            }
+        }
         let pieces = wrap.get_all_pieces();
         self.highlights = vec![];
         self.wrap = wrap;
@@ -389,6 +726,237 @@ impl BoardState {
         self.pieces=pieces;
         self.from=None;
         self.in_restart_box=false;
+        self.in_chess960_box=false;
+        self.analyse_board();
+    }
+
+    //the analysis is only told when the position changes, with chesslib to_game replays the whole game
+    fn analyse_board(&mut self) {
+        if let Some(analysis) = &mut self.analysis {
+            analysis.follow(&self.wrap.to_game());
+        }
+    }
+
+    //after undo or redo the board is redrawn and any selection dropped
+    fn refresh(&mut self, ctx: &mut Context) {
+        self.pieces = self.wrap.get_all_pieces();
+        self.state = self.wrap.get_state();
+        self.to_move = self.wrap.get_turn();
+        self.highlights = vec![];
+        self.from = None;
+        ctx.gfx.set_window_title(WINDOW_TITLE);
+        self.analyse_board();
+    }
+
+    //against the computer its reply is taken back as well, so it is the player's turn again
+    fn undo(&mut self, ctx: &mut Context) {
+        self.stop_thinking();
+        if self.wrap.undo() {
+            if self.computer == Some(self.wrap.get_turn()) {
+                self.wrap.undo();
+            }
+            self.refresh(ctx);
+        }
+    }
+
+    fn redo(&mut self, ctx: &mut Context) {
+        self.stop_thinking();
+        if self.wrap.redo() {
+            self.refresh(ctx);
+        }
+    }
+
+    fn claim_draw(&mut self, ctx: &mut Context) {
+        self.stop_thinking();
+        if self.wrap.claim_draw().is_some() {
+            self.refresh(ctx);
+        }
+    }
+
+    fn resign(&mut self, ctx: &mut Context) {
+        self.stop_thinking();
+        if self.wrap.resign() {
+            self.refresh(ctx);
+        }
+    }
+
+    fn agree_draw(&mut self, ctx: &mut Context) {
+        self.stop_thinking();
+        if self.wrap.agree_draw() {
+            self.refresh(ctx);
+        }
+    }
+
+    //the game goes on and the computer has the move
+    fn computer_to_move(&mut self) -> bool {
+        let state = self.wrap.get_state();
+        return self.computer == Some(self.wrap.get_turn()) && (state == State::Playing || state == State::Check);
+    }
+
+    fn is_thinking(&self) -> bool {
+        return self.thinking.is_some() || self.program.as_ref().map_or(false, |engine| engine.is_thinking());
+    }
+
+    //searches the current position on another thread or in the engine program, update picks up the move
+    fn start_thinking(&mut self) {
+        let game = self.wrap.to_game();
+        if let Some(engine) = &mut self.program {
+            if let Err(e) = engine.go(&game, COMPUTER_MOVE_TIME) {
+                //the built-in search takes over
+                println!("{} stopped working: {}", engine.name(), e);
+                self.program = None;
+            }
+            return;
+        }
+        let mut searcher = self.searcher.take().unwrap_or_else(search::Searcher::new);
+        searcher.stop.store(false, Ordering::Relaxed);
+        let stop = searcher.stop.clone();
+        let (sender, receiver) = mpsc::channel();
+        std::thread::spawn(move || {
+            let result = searcher.search(&game, search::Limits::movetime(COMPUTER_MOVE_TIME), |_| {});
+            println!("depth {} score {} nodes {} ({} nps), tt hits {:.0}%", result.depth, result.score, result.nodes, result.nps(), result.tt_hit_rate() * 100.0);
+            //nobody listens if the position changed in the meantime
+            let _ = sender.send((result.best, searcher));
+        });
+        self.thinking = Some((receiver, stop));
+    }
+
+    fn stop_thinking(&mut self) {
+        if let Some((_, stop)) = self.thinking.take() {
+            stop.store(true, Ordering::Relaxed);
+        }
+        if let Some(engine) = &mut self.program {
+            let _ = engine.stop();
+        }
+    }
+
+    //plays the computer's move once the search is done, without waiting for it
+    fn poll_thinking(&mut self, ctx: &mut Context) {
+        if self.program.is_some() {
+            self.poll_engine(ctx);
+            return;
+        }
+        let Some((receiver, _)) = &self.thinking else {return};
+        match receiver.try_recv() {
+            Ok((best, searcher)) => {
+                self.thinking = None;
+                self.searcher = Some(searcher);
+                if let Some(mv) = best {
+                    if let Err(e) = play_move(&mut self.wrap, mv) {
+                        println!("Computer move {} was rejected: {}", mv, e);
+                    }
+                }
+                self.refresh(ctx);
+            },
+            Err(mpsc::TryRecvError::Empty) => {},
+            Err(mpsc::TryRecvError::Disconnected) => self.thinking = None,
+        }
+    }
+
+    //the engine program's move goes through play_move just like the searcher's
+    fn poll_engine(&mut self, ctx: &mut Context) {
+        let Some(engine) = &mut self.program else {return};
+        while let Some(event) = engine.poll() {
+            match event {
+                external::Event::Info(_) => {},
+                external::Event::BestMove(Some(mv)) => {
+                    let info = engine.info();
+                    let score = info.score.map_or("?".to_string(), |s| s.to_string());
+                    println!("{}: depth {} score {} nodes {}", engine.name(), info.depth.unwrap_or(0), score, info.nodes.unwrap_or(0));
+                    if let Err(e) = play_move(&mut self.wrap, mv) {
+                        println!("{} move {} was rejected: {}", engine.name(), mv, e);
+                    }
+                    self.refresh(ctx);
+                    return;
+                },
+                external::Event::Resigned => {
+                    println!("{} resigns", engine.name());
+                    self.resign(ctx);
+                    return;
+                },
+                //the built-in search takes over
+                external::Event::BestMove(None) => {
+                    println!("{} had no move to play, the built-in search takes over", engine.name());
+                    self.program = None;
+                    return;
+                },
+                external::Event::Exited => {
+                    println!("{} exited, the built-in search takes over", engine.name());
+                    self.program = None;
+                    return;
+                },
+            }
+        }
+    }
+
+    //nobody, black, white and back to nobody
+    fn toggle_computer(&mut self) {
+        self.stop_thinking();
+        self.computer = match self.computer {
+            None => Some(Side::Black),
+            Some(Side::Black) => Some(Side::White),
+            Some(Side::White) => None,
+        };
+        match self.computer {
+            Some(side) => println!("Computer plays {:?}", side),
+            None => println!("Computer off"),
+        }
+    }
+
+    //dropping the analysis stops its search
+    fn toggle_analysis(&mut self) {
+        self.analysis = match self.analysis {
+            Some(_) => None,
+            None => Some(analysis::Analysis::new(self.analysis_lines)),
+        };
+        self.analyse_board();
+    }
+
+    //one more or one fewer line, between 1 and analysis::MAX_LINES
+    fn change_analysis_lines(&mut self, more: bool) {
+        self.analysis_lines = if more {self.analysis_lines+1} else {self.analysis_lines.saturating_sub(1)};
+        self.analysis_lines = self.analysis_lines.clamp(1, analysis::MAX_LINES);
+        if let Some(analysis) = &mut self.analysis {
+            analysis.set_lines(self.analysis_lines);
+        }
+    }
+
+    //evaluation bar and the best lines in SAN with depth and nodes, or how to switch the analysis on
+    fn draw_analysis(&self, canvas: &mut Canvas) {
+        let bar_x = BOARD_SIZE.0 as f32;
+        let bar_h = BOARD_SIZE.1 as f32;
+        let panel_y = (BOARD_SIZE.1 + TOOLBAR_HEIGHT) as f32;
+        canvas.draw(&graphics::Quad, graphics::DrawParam::new().dest_rect(Rect::new(0.0, panel_y, SCREEN_SIZE.0, ANALYSIS_HEIGHT as f32)).color(Color::from([0.15,0.15,0.15,1.0])));
+        let row = |n: usize| Point2{x:8.0, y:panel_y + 4.0 + (n*ANALYSIS_ROW_HEIGHT) as f32};
+
+        let Some(analysis) = &self.analysis else {
+            canvas.draw(&graphics::Quad, graphics::DrawParam::new().dest_rect(Rect::new(bar_x, 0.0, EVAL_BAR_WIDTH as f32, bar_h)).color(Color::from([0.3,0.3,0.3,1.0])));
+            canvas.draw(&Text::new("E starts the analysis"), graphics::DrawParam::default().dest(row(0)).color(Color::from([0.6,0.6,0.6,1.0])));
+            return;
+        };
+
+        //black's share on top, white's from the bottom, even until the first depth is done
+        let white = analysis.white_score().map_or(0.5, analysis::white_share);
+        canvas.draw(&graphics::Quad, graphics::DrawParam::new().dest_rect(Rect::new(bar_x, 0.0, EVAL_BAR_WIDTH as f32, bar_h)).color(Color::from([0.1,0.1,0.1,1.0])));
+        canvas.draw(&graphics::Quad, graphics::DrawParam::new().dest_rect(Rect::new(bar_x, bar_h*(1.0-white), EVAL_BAR_WIDTH as f32, bar_h*white)).color(Color::WHITE));
+        canvas.draw(&graphics::Quad, graphics::DrawParam::new().dest_rect(Rect::new(bar_x, bar_h/2.0-1.0, EVAL_BAR_WIDTH as f32, 2.0)).color(Color::RED));
+
+        let header = match analysis.result() {
+            Some(result) => format!("Depth {}, {} nodes, {} lines (Up/Down)", result.depth, result.nodes, analysis.lines()),
+            None if analysis.is_searching() => "Searching...".to_string(),
+            None => "No moves to analyse".to_string(),
+        };
+        canvas.draw(&Text::new(header), graphics::DrawParam::default().dest(row(0)).color(Color::from([0.6,0.6,0.6,1.0])));
+        for (n, line) in analysis.lines_text().into_iter().enumerate() {
+            canvas.draw(&Text::new(line), graphics::DrawParam::default().dest(row(n+1)).color(Color::WHITE));
+        }
+    }
+
+    //appends the current game to the archive file
+    fn save_pgn(&mut self) -> std::io::Result<()> {
+        let mut file = std::fs::OpenOptions::new().create(true).append(true).open(PGN_ARCHIVE)?;
+        writeln!(file, "{}", self.wrap.to_pgn())?;
+        return Ok(());
     }
 }
 
@@ -428,17 +996,36 @@ impl MoveHighlight {
 
 
 
-impl ggez::event::EventHandler<GameError> for BoardState {
+impl<B: ChessBackend> ggez::event::EventHandler<GameError> for BoardState<B> {
     fn update(&mut self, ctx: &mut Context) -> GameResult {
         while ctx.time.check_update_time(FPS as u32) {
             let state = self.wrap.get_state();
             
-            if self.reset_flag {
-                self.reset();
+            if self.reset_flag || self.chess960_flag {
+                //every finished game ends up in the archive
+                if let Err(e) = self.save_pgn() {
+                    println!("Could not save game: {}", e);
+                }
+                if self.chess960_flag {
+                    self.reset_chess960();
+                } else {
+                    self.reset();
+                }
                 self.reset_flag = false;
+                self.chess960_flag = false;
                 return Ok(());
             }
 
+            if self.is_thinking() {
+                self.poll_thinking(ctx);
+            } else if self.computer_to_move() {
+                self.start_thinking();
+            }
+
+            if let Some(analysis) = &mut self.analysis {
+                analysis.poll();
+            }
+
             //possibly not needed
             //TODO update last move after playing
             match state {
@@ -512,39 +1099,48 @@ impl ggez::event::EventHandler<GameError> for BoardState {
         }
         
         match self.wrap.get_state() {
-            State::Checkmate => {
-                draw_rectangle(&mut canvas, 3, 3, 2, 2, Color::BLACK);
-                let won = if self.wrap.get_turn() == Side::Black {"White "} else {"Black "};
-                let mut won_str = won.to_string();
-                won_str.push_str("Wins");
+            State::Checkmate | State::Draw | State::Stalemate => {
+                //e.g. "Black wins" over "by resignation", chesslib draws my_chess can't explain come without a reason
+                let result = self.wrap.get_result();
+                let text = if result.is_over() {format!("{}\nby {}", result.headline(), result.reason())} else {"Draw".to_string()};
+                //a third row offers a chess960 game
+                draw_rectangle(&mut canvas, 2, 3, 4, if self.has_chess960 {3} else {2}, Color::BLACK);
 
                 if self.in_restart_box { 
                     draw_rectangle(&mut canvas, 3, 2, 2, 1, Color::from([0.4,0.4,0.4,1.0]));
                 }
-                
-                draw_text(GridPosition::new(3, 2), "Play again?".to_string(), &mut canvas, off::P,Color::WHITE);
-
-                draw_text(GridPosition::new(3, 3), won_str, &mut canvas, off::P,Color::WHITE);         
-                canvas.finish(ctx)?;    
-                return  Ok(());
-            },
-            State::Draw | State::Stalemate => {
-                draw_rectangle(&mut canvas, 3, 3, 2, 2, Color::BLACK);
-                draw_text(GridPosition::new(3, 3), "Draw".to_string(), &mut canvas, off::P,Color::WHITE);
-
-                if self.in_restart_box { 
-                    draw_rectangle(&mut canvas, 3, 2, 2, 1, Color::from([0.4,0.4,0.4,1.0]));
+                if self.in_chess960_box && self.has_chess960 {
+                    draw_rectangle(&mut canvas, 3, 1, 2, 1, Color::from([0.4,0.4,0.4,1.0]));
                 }
 
                 draw_text(GridPosition::new(3, 2), "Play again?".to_string(), &mut canvas, off::P,Color::WHITE);
-                
-                canvas.finish(ctx)?;
-                return  Ok(());
-            }, 
+                if self.has_chess960 {
+                    draw_text(GridPosition::new(3, 1), "Chess960?".to_string(), &mut canvas, off::P,Color::WHITE);
+                }
+
+                draw_text(GridPosition::new(2, 3), text, &mut canvas, off::P,Color::WHITE);         
+            },
             _ => {}
         }  
 
-        
+        //toolbar: undo, redo, claim draw, which is greyed out unless a claim is possible, and resign
+        let button_w = (BOARD_SIZE.0/TOOLBAR_BUTTONS) as f32;
+        let bar_y = BOARD_SIZE.1 as f32;
+        canvas.draw(&graphics::Quad, graphics::DrawParam::new().dest_rect(Rect::new(0.0, bar_y, BOARD_SIZE.0 as f32, TOOLBAR_HEIGHT as f32)).color(Color::from([0.3,0.3,0.3,1.0])));
+        let can_claim = self.wrap.can_claim_draw();
+        let playing = !self.wrap.get_result().is_over();
+        let labels = [("Undo", true), ("Redo", true), ("Claim draw", can_claim), ("Resign", playing)];
+        for (n, (label, enabled)) in labels.iter().enumerate() {
+            let x = n as f32 * button_w;
+            if n > 0 {
+                canvas.draw(&graphics::Quad, graphics::DrawParam::new().dest_rect(Rect::new(x-1.0, bar_y, 2.0, TOOLBAR_HEIGHT as f32)).color(Color::BLACK));
+            }
+            let color = if *enabled {Color::WHITE} else {Color::from([0.6,0.6,0.6,1.0])};
+            canvas.draw(&Text::new(*label), graphics::DrawParam::default().dest(Point2{x:x+8.0, y:bar_y+8.0}).color(color));
+        }
+
+        self.draw_analysis(&mut canvas);
+
         canvas.finish(ctx)?;
         Ok(())
     }
@@ -553,13 +1149,32 @@ impl ggez::event::EventHandler<GameError> for BoardState {
 
     fn mouse_button_up_event(&mut self,ctx: &mut Context,button: input::mouse::MouseButton,x: f32,y: f32,) -> GameResult {
 
-        if is_in_box(x, y, 3, 2, 2, 1) && self.wrap.get_state() != State::Playing && self.wrap.get_state() != State::Promotion {
+        //the evaluation bar and the analysis lines don't take clicks
+        if x >= BOARD_SIZE.0 as f32 || y >= (BOARD_SIZE.1+TOOLBAR_HEIGHT) as f32 {
+            return Ok(());
+        }
+        if y >= BOARD_SIZE.1 as f32 {
+            match (x as usize*TOOLBAR_BUTTONS)/BOARD_SIZE.0 {
+                0 => self.undo(ctx),
+                1 => self.redo(ctx),
+                2 => self.claim_draw(ctx),
+                _ => self.resign(ctx),
+            }
+            return Ok(());
+        }
+
+        let state = self.wrap.get_state();
+        if is_in_box(x, y, 3, 2, 2, 1) && (state == State::Checkmate || state == State::Draw || state == State::Stalemate) {
             self.reset_flag = true;
             return Ok(());
         } 
+        if is_in_box(x, y, 3, 1, 2, 1) && self.has_chess960 && (state == State::Checkmate || state == State::Draw || state == State::Stalemate) {
+            self.chess960_flag = true;
+            return Ok(());
+        }
 
-        let x_coord = (8*x as usize).div(SCREEN_SIZE.0 as usize);
-        let y_coord = 7-(8*y as usize).div(SCREEN_SIZE.1 as usize);
+        let x_coord = (8*x as usize).div(BOARD_SIZE.0);
+        let y_coord = 7-(8*y as usize).div(BOARD_SIZE.1);
 
         let grid_pos = GridPosition::new(x_coord, y_coord);
 
@@ -572,11 +1187,16 @@ impl ggez::event::EventHandler<GameError> for BoardState {
                 (5,4) => {self.wrap.promote(PieceType::Knight);},
                 _=>{return Ok(());}
             }
-            self.pieces = self.wrap.get_all_pieces();       
+            self.pieces = self.wrap.get_all_pieces();
+            self.analyse_board();
             return Ok(());
         }
 
-        if self.wrap.get_state() != State::Playing {
+        if self.wrap.get_state() != State::Playing && self.wrap.get_state() != State::Check {
+            return Ok(());
+        }
+        //the board is the computer's while it thinks
+        if self.computer_to_move() {
             return Ok(());
         }
 
@@ -591,9 +1211,14 @@ impl ggez::event::EventHandler<GameError> for BoardState {
         
         match self.from {
             Some(res) => {
-                let made_move =self.wrap.make_move(res, grid_pos);
-                if made_move {
-                    self.pieces = self.wrap.get_all_pieces();
+                match self.wrap.make_move(res, grid_pos) {
+                    Ok(()) => {
+                        self.pieces = self.wrap.get_all_pieces();
+                        ctx.gfx.set_window_title(WINDOW_TITLE);
+                        self.analyse_board();
+                    },
+                    //e.g. "Chess - your king would be in check"
+                    Err(e) => ctx.gfx.set_window_title(&format!("{} - {}", WINDOW_TITLE, e)),
                 }
                 self.highlights = vec![];
                 self.from = None;
@@ -605,6 +1230,39 @@ impl ggez::event::EventHandler<GameError> for BoardState {
         Ok(())
     }
 
+    fn key_down_event(&mut self, ctx: &mut Context, input: input::keyboard::KeyInput, _repeated: bool) -> GameResult {
+        match input.keycode {
+            Some(input::keyboard::KeyCode::Escape) => ctx.request_quit(),
+            //Ctrl+Z and Ctrl+Y, the arrow keys step through the game as well
+            Some(input::keyboard::KeyCode::Z) if input.mods.contains(input::keyboard::KeyMods::CTRL) => self.undo(ctx),
+            Some(input::keyboard::KeyCode::Y) if input.mods.contains(input::keyboard::KeyMods::CTRL) => self.redo(ctx),
+            Some(input::keyboard::KeyCode::Left) => self.undo(ctx),
+            Some(input::keyboard::KeyCode::Right) => self.redo(ctx),
+            Some(input::keyboard::KeyCode::D) => self.claim_draw(ctx),
+            Some(input::keyboard::KeyCode::R) => self.resign(ctx),
+            //A for a draw both players agreed on
+            Some(input::keyboard::KeyCode::A) => self.agree_draw(ctx),
+            //N starts a new game and F a chess960 one, the game so far is archived first
+            Some(input::keyboard::KeyCode::N) => self.reset_flag = true,
+            Some(input::keyboard::KeyCode::F) if self.has_chess960 => self.chess960_flag = true,
+            //C lets the computer play black, then white, then nobody
+            Some(input::keyboard::KeyCode::C) => self.toggle_computer(),
+            //E switches the analysis on and off, Up and Down change how many lines it shows
+            Some(input::keyboard::KeyCode::E) => self.toggle_analysis(),
+            Some(input::keyboard::KeyCode::Up) => self.change_analysis_lines(true),
+            Some(input::keyboard::KeyCode::Down) => self.change_analysis_lines(false),
+            //S saves the game so far
+            Some(input::keyboard::KeyCode::S) => {
+                match self.save_pgn() {
+                    Ok(()) => println!("Saved game to {}", PGN_ARCHIVE),
+                    Err(e) => println!("Could not save game: {}", e),
+                }
+            },
+            _ => {}
+        }
+        Ok(())
+    }
+
     fn mouse_motion_event(&mut self,_ctx: &mut Context,x: f32,y: f32,_dx: f32,_dy: f32,) -> Result<(), GameError> {
         
         //check if in box
@@ -613,6 +1271,7 @@ impl ggez::event::EventHandler<GameError> for BoardState {
         } else {
             self.in_restart_box = false;
         }
+        self.in_chess960_box = is_in_box(x, y, 3, 1, 2, 1);
 
         Ok(())
     }
@@ -643,6 +1302,12 @@ fn is_in_box(x:f32,y:f32,bx:usize,by:usize,bwidth:usize,bheight:usize) -> bool {
     false
 }
 
+//no rand crate, the clock is random enough to pick a start position
+fn random_chess960_index() -> usize {
+    let nanos = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map_or(0, |d| d.subsec_nanos());
+    return nanos as usize % 960;
+}
+
 fn draw_text(mut coord:GridPosition,text:String,canvas: &mut Canvas,txt_type:off,color:Color) {
     let mut txt = Text::new(text);
     canvas.draw(&txt, graphics::DrawParam::default().dest(coord.map_to_coords(txt_type)).color(color));
@@ -663,16 +1328,160 @@ fn main() -> GameResult {
     
     print!("DIRDIRDIR: {:?}",resource_dir);
 
+    //chess-gui --diff [games] [seed] checks my_chess against chesslib instead of opening the window
+    let args: Vec<String> = env::args().collect();
+    if args.get(1).map(|a| a.as_str()) == Some("--diff") {
+        let games = args.get(2).and_then(|a| a.parse().ok()).unwrap_or(100);
+        let seed = args.get(3).and_then(|a| a.parse().ok()).unwrap_or(1);
+        match differential::run(games, seed) {
+            Ok(plies) => println!("{} games, {} plies, no disagreements", games, plies),
+            Err(found) => {
+                println!("{}", found);
+                std::process::exit(1);
+            }
+        }
+        return Ok(());
+    }
+
+    //chess-gui --bench [depth] searches a few positions and prints node counts and transposition table hits
+    if args.get(1).map(|a| a.as_str()) == Some("--bench") {
+        let depth = args.get(2).and_then(|a| a.parse().ok()).unwrap_or(6);
+        search::bench(depth);
+        return Ok(());
+    }
+
+    //chess-gui --engine my_chess|chesslib, the my_chess_backend feature makes my_chess the default
+    let engine = match args.iter().position(|a| a == "--engine") {
+        Some(n) => args.get(n+1).map(|a| a.as_str()).unwrap_or(""),
+        None => DEFAULT_ENGINE
+    };
+    if engine != "my_chess" && engine != "chesslib" {
+        println!("Unknown engine '{}', use my_chess or chesslib", engine);
+        std::process::exit(2);
+    }
+
+    //chess-gui --computer white|black to play against the computer, C switches sides in the game
+    let computer = match args.iter().position(|a| a == "--computer") {
+        Some(n) => match args.get(n+1).map(|a| a.as_str()) {
+            Some("white") => Some(Side::White),
+            Some("black") => Some(Side::Black),
+            other => {
+                println!("Unknown side '{}', use white or black", other.unwrap_or(""));
+                std::process::exit(2);
+            }
+        },
+        None => None
+    };
+
+    //chess-gui --uci <path> or --cecp <path> lets an engine program play the computer's side, black unless --computer says otherwise
+    let program = match args.iter().position(|a| a == "--uci" || a == "--cecp") {
+        Some(n) => {
+            let path = args.get(n+1).map(|a| a.as_str()).unwrap_or("");
+            let protocol = if args[n] == "--uci" {external::Protocol::Uci} else {external::Protocol::Cecp};
+            match external::spawn(path, protocol) {
+                Ok(engine) => {
+                    println!("Playing against {}", engine.name());
+                    Some(engine)
+                },
+                Err(e) => {
+                    println!("Could not start engine '{}': {}", path, e);
+                    std::process::exit(2);
+                }
+            }
+        },
+        None => None
+    };
+    let computer = if program.is_some() {computer.or(Some(Side::Black))} else {computer};
+
+    //chess-gui --analysis [lines] starts with the analysis on, E switches it in the game. 1 to analysis::MAX_LINES lines
+    let analysis_lines = args.iter().position(|a| a == "--analysis").map(|n| args.get(n+1).and_then(|a| a.parse().ok()).unwrap_or(DEFAULT_ANALYSIS_LINES).clamp(1, analysis::MAX_LINES));
+
     let c = conf::Conf::new();
     let (mut ctx, event_loop) = ggez::ContextBuilder::new("chess_gui", "pechmann")
-        .window_setup(ggez::conf::WindowSetup::default().title("Chess"))
+        .window_setup(ggez::conf::WindowSetup::default().title(WINDOW_TITLE))
         .window_mode(ggez::conf::WindowMode::default().dimensions(SCREEN_SIZE.0, SCREEN_SIZE.1))
         .add_resource_path(resource_dir)
         .build()?;
 
-    let state = BoardState::new(&mut ctx);
-    
-    event::run(ctx, event_loop, state);
+    if engine == "my_chess" {
+        let state = BoardState::<GameWrapper>::new(&mut ctx, computer, program, analysis_lines);
+        event::run(ctx, event_loop, state)
+    } else {
+        let state = BoardState::<alt_GameWrapper>::new(&mut ctx, computer, program, analysis_lines);
+        event::run(ctx, event_loop, state)
+    }
 }
 
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(sq: &str) -> GridPosition {
+        let sq = my_chess::Square::parse(sq).unwrap();
+        return GridPosition::new(sq.x, sq.y);
+    }
+
+    #[test]
+    fn my_chess_backend(){
+        let mut wrap = GameWrapper::new();
+        wrap.game = my_chess::Game::from_fen("4k3/1P6/8/8/8/8/7P/4K3 w - - 0 1").unwrap();
+        assert_eq!(wrap.get_turn(), Side::White);
+        assert!(wrap.is_selectable(at("b7")));
+        assert_eq!(wrap.get_moves(at("b7")), vec![MoveHighlight::new(1, 7)]);
+        assert_eq!(wrap.make_move(at("b7"), at("c8")), Err(my_chess::MoveError::IllegalDestination(my_chess::Square::new(2,7))));
+
+        //the pawn waits for its piece, undo takes the half made move back
+        assert_eq!(wrap.make_move(at("b7"), at("b8")), Ok(()));
+        assert_eq!(wrap.get_state(), State::Promotion);
+        assert_eq!(wrap.get_turn(), Side::White);
+        assert!(wrap.undo());
+        assert_eq!(wrap.get_state(), State::Playing);
+
+        assert_eq!(wrap.make_move(at("b7"), at("b8")), Ok(()));
+        assert!(wrap.promote(PieceType::Knight));
+        assert_eq!(wrap.get_turn(), Side::Black);
+        assert!(wrap.get_all_pieces().contains(&RendPiece::new(1, 7, PieceType::Knight, Side::White)));
+
+        assert!(wrap.resign());
+        assert_eq!(wrap.get_state(), State::Checkmate);
+        assert_eq!(wrap.get_result(), my_chess::GameState::WIN_W(my_chess::WinReason::RESIGNATION));
+        assert!(wrap.to_pgn().contains("1. b8=N 1-0"));
+    }
+
+    #[test]
+    fn chess960_backend(){
+        assert!(alt_GameWrapper::new_chess960(0).is_none());
+        let mut wrap = GameWrapper::new_chess960(0).unwrap();
+        //BBQNNRKR, clearing f1 lets the king castle by clicking its own rook
+        assert!(wrap.get_all_pieces().contains(&RendPiece::new(6, 0, PieceType::King, Side::White)));
+        for (from, to) in [("f2", "f4"), ("f7", "f5"), ("f1", "f3"), ("f8", "f6")] {
+            assert_eq!(wrap.make_move(at(from), at(to)), Ok(()), "{}{}", from, to);
+        }
+        assert!(wrap.get_moves(at("g1")).contains(&MoveHighlight::new(7, 0)));
+        assert_eq!(wrap.make_move(at("g1"), at("h1")), Ok(()));
+        assert!(wrap.get_all_pieces().contains(&RendPiece::new(5, 0, PieceType::Rook, Side::White)));
+        assert!(wrap.to_pgn().contains("3. O-O *"));
+    }
+
+    #[test]
+    fn computer_moves(){
+        let mut wrap = GameWrapper::new();
+        wrap.game = my_chess::Game::from_fen("4k3/1P6/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        //the search sees the promotion and the move goes through like a click would, piece included.
+        //deeper searches know the pawn can't be stopped and are in no hurry
+        let game = wrap.to_game();
+        let best = search::Searcher::new().search(&game, search::Limits::depth(1), |_| {}).best.unwrap();
+        assert!(best.matches(my_chess::Move::parse("b7b8q").unwrap()));
+        assert_eq!(play_move(&mut wrap, best), Ok(()));
+        assert_eq!(wrap.get_turn(), Side::Black);
+        assert!(wrap.get_all_pieces().contains(&RendPiece::new(1, 7, PieceType::Queen, Side::White)));
+
+        //an underpromotion keeps its piece
+        wrap.game = my_chess::Game::from_fen("4k3/1P6/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        assert_eq!(play_move(&mut wrap, my_chess::Move::parse("b7b8n").unwrap()), Ok(()));
+        assert!(wrap.get_all_pieces().contains(&RendPiece::new(1, 7, PieceType::Knight, Side::White)));
+        assert!(play_move(&mut wrap, my_chess::Move::parse("e1e2").unwrap()).is_err());
+    }
+}
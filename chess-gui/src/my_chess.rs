@@ -7,6 +7,7 @@ use std::collections::HashMap;
 
 use std::io::{self, BufRead};
 
+#[derive(Clone)]
 pub struct Game {
     pub turn_counter: usize,
     pub board: Board,
@@ -14,63 +15,311 @@ pub struct Game {
     pub old_state: Board,
     pub last_capture: usize,
     pub max_repeated: usize,
-    pub repeat_map:HashMap<[[Position; 8]; 8],usize>   
+    //zobrist key of every position reached so far and how often it occurred
+    pub repeat_map:HashMap<u64,usize>,
+    //position the game started from and every move played since, used to export the game
+    pub start_fen: String,
+    pub history: Vec<Move>,
+    //one entry per move in history, holding what undo has to put back
+    pub undo_stack: Vec<UndoRecord>,
+    //moves taken back by undo, the next one to redo is last
+    pub redo_stack: Vec<Move>,
+    //result decided off the board: a claimed or agreed draw, a resignation or a timeout
+    pub declared_result: Option<GameState>,
+    //chess960 game, castling moves are written as the king taking its own rook
+    pub chess960: bool
 }
 
-pub struct Board {
-    pub positions: [[Position; 8]; 8],
-    pub bk_pos:(usize,usize),
-    pub wk_pos:(usize,usize),
-    pub last_pass: (usize,usize,usize) //to-x,to-y,turn
+//state of the game before a move, the board record puts back the pieces, castling rights, en passant square and clock
+#[derive(Clone)]
+pub struct UndoRecord {
+    pub board: BoardUndo,
+    pub last_capture: usize,
+    pub max_repeated: usize
 }
-#[derive(Copy, Clone,Debug,Hash,PartialEq,Eq)]
-pub struct Position {
-    pub content : PositionContent
+
+//what Board::unmake_move needs to take a move back, everything else follows from the move itself
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct BoardUndo {
+    pub mv: Move,
+    //the piece the move took, for en passant the pawn next to the destination
+    pub captured: Option<Piece>,
+    pub castling: u64,
+    pub ep: Option<Square>,
+    pub halfmove_clock: usize,
+    pub hash: u64
 }
 
-#[derive(Copy, Clone,PartialEq,Debug,Hash,Eq)]
-pub enum PositionContent {
-    PIECE_CONT(Piece),
-    NONE
+#[derive(Clone,PartialEq,Eq,Debug)]
+pub struct Board {
+    //one bitboard per color and piece type (PieceType::index), bit n is set if Square::from_index(n) holds such a piece
+    pub pieces: [[u64; 6]; 2],
+    //all pieces of each color
+    pub occupied: [u64; 2],
+    //the same pieces by square index, to look up what stands where
+    pub squares: [Option<Piece>; 64],
+    pub bk_pos:Square,
+    pub wk_pos:Square,
+    //rooks that can still castle as a bitboard, a king move takes away the ones of its side
+    pub castling: u64,
+    //square a pawn can capture en passant on, only set right after a double move
+    pub ep: Option<Square>,
+    //plies since the last capture or pawn move, as written in the fifth FEN field
+    pub halfmove_clock: usize,
+    //zobrist key of the position, kept up to date by force_move
+    pub hash: u64
 }
 
+//squares a knight, king or pawn attacks from each square, and the rays sliding pieces move along.
+//generated at compile time like the zobrist keys
+struct AttackTables {
+    knight: [u64; 64],
+    king: [u64; 64],
+    //captures of white and black pawns
+    pawn: [[u64; 64]; 2],
+    //one ray per entry of DIRECTIONS, without the square itself
+    rays: [[u64; 64]; 8]
+}
 
-impl PositionContent {
-    fn print(self)-> char{
-        match self {
-            PositionContent::NONE => return ' ',
-            PositionContent::PIECE_CONT(mut p) => return p.print()
+//north, east, north east, north west, south, west, south west, south east.
+//the first four lead to higher square indices, the others to lower ones
+const DIRECTIONS: [(i32, i32); 8] = [(0,1),(1,0),(1,1),(-1,1),(0,-1),(-1,0),(-1,-1),(1,-1)];
+const KNIGHT_STEPS: [(i32, i32); 8] = [(1,2),(2,1),(-1,2),(2,-1),(-1,-2),(-2,-1),(1,-2),(-2,1)];
+
+//a1, c1 ... the squares with x + y even
+const DARK_SQUARES: u64 = 0xAA55AA55AA55AA55;
+
+impl AttackTables {
+    const fn generate() -> AttackTables {
+        let mut tables = AttackTables{knight:[0; 64], king:[0; 64], pawn:[[0; 64]; 2], rays:[[0; 64]; 8]};
+        let mut sq = 0;
+        while sq < 64 {
+            let x = (sq % 8) as i32;
+            let y = (sq / 8) as i32;
+            let mut i = 0;
+            while i < 8 {
+                tables.knight[sq] |= square_bit(x + KNIGHT_STEPS[i].0, y + KNIGHT_STEPS[i].1);
+                tables.king[sq] |= square_bit(x + DIRECTIONS[i].0, y + DIRECTIONS[i].1);
+                let mut n = 1;
+                while n < 8 {
+                    tables.rays[i][sq] |= square_bit(x + n*DIRECTIONS[i].0, y + n*DIRECTIONS[i].1);
+                    n += 1;
+                }
+                i += 1;
+            }
+            tables.pawn[0][sq] = square_bit(x - 1, y + 1) | square_bit(x + 1, y + 1);
+            tables.pawn[1][sq] = square_bit(x - 1, y - 1) | square_bit(x + 1, y - 1);
+            sq += 1;
         }
+        return tables;
     }
+}
 
-    fn is_empty(self)-> bool{
-        match self {
-            PositionContent::NONE => return true,
-            _ => return false
+//bit of the square (x,y), 0 if it is off the board
+const fn square_bit(x: i32, y: i32) -> u64 {
+    if x < 0 || x > 7 || y < 0 || y > 7 {
+        return 0;
+    }
+    return 1 << (x + 8*y);
+}
+
+static ATTACKS: AttackTables = AttackTables::generate();
+
+//squares reached from sq in direction dir, up to and including the first piece in the way
+fn ray_attacks(sq: usize, dir: usize, occupied: u64) -> u64 {
+    let ray = ATTACKS.rays[dir][sq];
+    let blockers = ray & occupied;
+    if blockers == 0 {
+        return ray;
+    }
+    //the nearest blocker is the lowest bit on rays going up and the highest on rays going down
+    let first = if dir < 4 {blockers.trailing_zeros()} else {63 - blockers.leading_zeros()};
+    return ray ^ ATTACKS.rays[dir][first as usize];
+}
+
+fn bishop_attacks(sq: usize, occupied: u64) -> u64 {
+    return ray_attacks(sq, 2, occupied) | ray_attacks(sq, 3, occupied) | ray_attacks(sq, 6, occupied) | ray_attacks(sq, 7, occupied);
+}
+
+fn rook_attacks(sq: usize, occupied: u64) -> u64 {
+    return ray_attacks(sq, 0, occupied) | ray_attacks(sq, 1, occupied) | ray_attacks(sq, 4, occupied) | ray_attacks(sq, 5, occupied);
+}
+
+//iterates over the square indices set in a bitboard, lowest first
+pub struct Bits(u64);
+
+impl Iterator for Bits {
+    type Item = usize;
+    fn next(&mut self) -> Option<usize> {
+        if self.0 == 0 {
+            return None;
         }
+        let sq = self.0.trailing_zeros() as usize;
+        self.0 &= self.0 - 1;
+        return Some(sq);
     }
+}
 
-    pub fn get_piece(&mut self) -> Piece {
-        match self {
-            PositionContent::NONE => panic!("NO PIECES PRESENT"),
-            PositionContent::PIECE_CONT(mut p) => return p
+pub fn bits(bitboard: u64) -> Bits {
+    return Bits(bitboard);
+}
+
+//random numbers for the zobrist keys, generated at compile time so every run hashes the same way
+struct ZobristKeys {
+    pieces: [[u64; 64]; 12],
+    //white king side, white queen side, black king side, black queen side
+    castling: [u64; 4],
+    en_passant: [u64; 8],
+    black_to_move: u64
+}
+
+impl ZobristKeys {
+    const fn generate() -> ZobristKeys {
+        let mut keys = ZobristKeys{pieces:[[0; 64]; 12], castling:[0; 4], en_passant:[0; 8], black_to_move:0};
+        let mut seed: u64 = 0x2545F4914F6CDD1D;
+        let mut i = 0;
+        while i < 12 {
+            let mut j = 0;
+            while j < 64 {
+                seed = splitmix64(seed);
+                keys.pieces[i][j] = mix(seed);
+                j += 1;
+            }
+            i += 1;
+        }
+        i = 0;
+        while i < 4 {
+            seed = splitmix64(seed);
+            keys.castling[i] = mix(seed);
+            i += 1;
         }
-    }   
+        i = 0;
+        while i < 8 {
+            seed = splitmix64(seed);
+            keys.en_passant[i] = mix(seed);
+            i += 1;
+        }
+        seed = splitmix64(seed);
+        keys.black_to_move = mix(seed);
+        return keys;
+    }
+}
+
+const fn splitmix64(state: u64) -> u64 {
+    return state.wrapping_add(0x9E3779B97F4A7C15);
+}
+
+const fn mix(state: u64) -> u64 {
+    let mut z = state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+    return z ^ (z >> 31);
 }
 
+const ZOBRIST: ZobristKeys = ZobristKeys::generate();
+
+//key of a piece standing on a square
+fn zobrist_piece(piece: Piece, sq: Square) -> u64 {
+    return ZOBRIST.pieces[6*piece.color.index() + piece.variant.index()][sq.index()];
+}
 #[derive(Copy, Clone,PartialEq,Debug,Eq,Hash)]
 pub struct Piece {
     pub variant : PieceType,
-    pub color : Color,
-    pub has_moved : bool
+    pub color : Color
 }
 
 #[derive(Clone, Copy,PartialEq,Debug)]
 pub enum GameState {
     ONGOING,
-    WIN_W,
-    WIN_B,
-    DRAW
+    WIN_W(WinReason),
+    WIN_B(WinReason),
+    DRAW(DrawReason)
+}
+
+impl GameState {
+    pub fn is_over(self) -> bool {
+        return self != GameState::ONGOING;
+    }
+
+    //None while the game goes on and for draws
+    pub fn winner(self) -> Option<Color> {
+        match self {
+            GameState::WIN_W(_) => return Some(Color::W),
+            GameState::WIN_B(_) => return Some(Color::B),
+            _ => return None
+        }
+    }
+
+    //e.g. "White wins" or "Draw"
+    pub fn headline(self) -> &'static str {
+        match self {
+            GameState::ONGOING => return "Playing",
+            GameState::WIN_W(_) => return "White wins",
+            GameState::WIN_B(_) => return "Black wins",
+            GameState::DRAW(_) => return "Draw"
+        }
+    }
+
+    //e.g. "checkmate" or "insufficient material", empty while the game goes on
+    pub fn reason(self) -> String {
+        match self {
+            GameState::ONGOING => return String::new(),
+            GameState::WIN_W(r) | GameState::WIN_B(r) => return r.to_string(),
+            GameState::DRAW(r) => return r.to_string()
+        }
+    }
+}
+
+impl std::fmt::Display for GameState {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            GameState::ONGOING => write!(f, "{}", self.headline()),
+            _ => write!(f, "{} by {}", self.headline(), self.reason()),
+        }
+    }
+}
+
+#[derive(Clone, Copy,PartialEq,Eq,Debug)]
+pub enum WinReason {
+    CHECKMATE,
+    RESIGNATION,
+    TIMEOUT
+}
+
+impl std::fmt::Display for WinReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            WinReason::CHECKMATE => write!(f, "checkmate"),
+            WinReason::RESIGNATION => write!(f, "resignation"),
+            WinReason::TIMEOUT => write!(f, "timeout"),
+        }
+    }
+}
+
+//the 75 move rule and fivefold repetition end the game on their own, the 50 move rule and threefold repetition have to be claimed
+#[derive(Clone, Copy,PartialEq,Eq,Debug)]
+pub enum DrawReason {
+    STALEMATE,
+    FIFTY_MOVES,
+    THREEFOLD_REPETITION,
+    SEVENTY_FIVE_MOVES,
+    FIVEFOLD_REPETITION,
+    INSUFFICIENT_MATERIAL,
+    AGREEMENT
+}
+
+impl std::fmt::Display for DrawReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            DrawReason::STALEMATE => write!(f, "stalemate"),
+            DrawReason::FIFTY_MOVES => write!(f, "50 move rule"),
+            DrawReason::THREEFOLD_REPETITION => write!(f, "threefold repetition"),
+            DrawReason::SEVENTY_FIVE_MOVES => write!(f, "75 move rule"),
+            DrawReason::FIVEFOLD_REPETITION => write!(f, "fivefold repetition"),
+            DrawReason::INSUFFICIENT_MATERIAL => write!(f, "insufficient material"),
+            DrawReason::AGREEMENT => write!(f, "agreement"),
+        }
+    }
 }
 
 impl Piece {
@@ -87,18 +336,6 @@ impl Piece {
 
         return ret_char;
     }
-    //provides the longest possible path that each piece can take in an ideal scenario
-    fn get_paths(self) -> Vec<(i16,i16)> {
-        match self.variant {
-            PieceType::PAWN => if self.color == Color::W {vec![(0,1)]} else {vec![(0,-1)]} ,
-            PieceType::KNIGHT => vec![(1,2),(2,1),(-1,2),(2,-1),(-1,-2),(-2,-1),(1,-2),(-2,1)], 
-            PieceType::BISHIOP => vec![(-1,1),(1,1),(-1,-1),(1,-1)],
-            PieceType::ROOK => vec![(0,1),(-1,0),(1,0),(0,-1)],
-            PieceType::QUEEN => vec![(-1,1),(0,1),(1,1),(-1,0),(1,0),(-1,-1),(0,-1),(1,-1)],
-            PieceType::KING => vec![(-1,1),(0,1),(1,1),(-1,0),(1,0),(-1,-1),(0,-1),(1,-1)],
-            _ => return vec![]
-        }
-    }
 }
 #[derive(Copy, Clone,PartialEq,Debug,Eq,Hash)]
 pub enum PieceType {
@@ -111,6 +348,33 @@ pub enum PieceType {
     NONE
 }
 
+impl PieceType {
+    //which of a color's bitboards holds this piece, can panic
+    pub fn index(self) -> usize {
+        match self {
+            PieceType::PAWN => return 0,
+            PieceType::KNIGHT => return 1,
+            PieceType::BISHIOP => return 2,
+            PieceType::ROOK => return 3,
+            PieceType::QUEEN => return 4,
+            PieceType::KING => return 5,
+            PieceType::NONE => panic!("NONE has no bitboard")
+        }
+    }
+
+    //material value in centipawns, kings are never traded so theirs does not count
+    pub fn value(self) -> i32 {
+        match self {
+            PieceType::PAWN => return 100,
+            PieceType::KNIGHT => return 320,
+            PieceType::BISHIOP => return 330,
+            PieceType::ROOK => return 500,
+            PieceType::QUEEN => return 900,
+            _ => return 0
+        }
+    }
+}
+
 
 #[derive(Copy, Clone,PartialEq,Debug,Eq,Hash)]
 pub enum Color {
@@ -125,73 +389,197 @@ impl Color {
             Color::B => Color::W
         }
     }
+
+    pub fn index(self) -> usize {
+        return if self == Color::W {0} else {1};
+    }
 }
 
-pub fn decode_notation(chess_not: &str)-> (usize,usize){
-    let mut chars = chess_not.chars();
-    let x_coord:char = chars.next().unwrap();
-    let y_coord:char = chars.next().unwrap();
-
-    let  x_converted:usize = match x_coord {
-        'a'| 'A' =>  0,
-        'b'| 'B' =>  1,
-        'c'| 'C' =>  2,
-        'd'| 'D' =>  3,
-        'e'| 'E' =>  4,
-        'f'| 'F' =>  5,
-        'g'| 'G' =>  6,
-        'h'| 'H' =>  7,
-        _ => panic!()
-    };
+//a square on the board, x is the file (a = 0) and y is the rank (1 = 0)
+#[derive(Copy, Clone,PartialEq,Debug,Eq,Hash)]
+pub struct Square {
+    pub x: usize,
+    pub y: usize
+}
+
+impl Square {
+    pub fn new(x:usize, y:usize) -> Square {
+        debug_assert!(x < 8 && y < 8);
+        return Square{x:x, y:y};
+    }
+
+    //index used by bitboard libraries, a1 = 0, b1 = 1 ... h8 = 63
+    pub fn from_index(index:usize) -> Square {
+        return Square::new(index % 8, index / 8);
+    }
+
+    pub fn index(self) -> usize {
+        return self.x + self.y*8;
+    }
+
+    //the square moved to by (dx,dy), None if that is off the board
+    pub fn offset(self, dx:i16, dy:i16) -> Option<Square> {
+        let x = self.x as i16 + dx;
+        let y = self.y as i16 + dy;
+        if x < 0 || x > 7 || y < 0 || y > 7 {
+            return None;
+        }
+        return Some(Square::new(x as usize, y as usize));
+    }
 
-    let y_converted:usize = y_coord.to_digit(32).unwrap() as usize - 1;
+    //parses the chess notation <letter><number>, e.g. "e4"
+    pub fn parse(chess_not: &str) -> Result<Square,NotationError> {
+        let bytes = chess_not.as_bytes();
+        if bytes.len() != 2 {
+            return Err(NotationError::InvalidSquare(chess_not.to_string()));
+        }
+        let x = bytes[0].to_ascii_lowercase();
+        let y = bytes[1];
+        if !(b'a'..=b'h').contains(&x) || !(b'1'..=b'8').contains(&y) {
+            return Err(NotationError::InvalidSquare(chess_not.to_string()));
+        }
+        return Ok(Square::new((x - b'a') as usize, (y - b'1') as usize));
+    }
+}
 
+impl std::str::FromStr for Square {
+    type Err = NotationError;
+    fn from_str(s: &str) -> Result<Square,NotationError> {
+        return Square::parse(s);
+    }
+}
 
-    return (x_converted,y_converted);
+impl std::fmt::Display for Square {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}{}", (b'a' + self.x as u8) as char, self.y + 1)
+    }
+}
+
+//a move as produced by the move generator, flags describe what the move does on the board
+//moves built by hand (Move::new, Move::parse) have no flags, Game::move_piece only compares from, to and promotion
+#[derive(Copy, Clone,PartialEq,Debug,Eq,Hash)]
+pub struct Move {
+    pub from: Square,
+    pub to: Square,
+    pub promotion: Option<PieceType>,
+    pub flags: u8
 }
 
-pub fn decode_promotion(chess_not: &str)-> PieceType{
-    let mut chars = chess_not.chars();
-    let c_char:char = chars.next().unwrap();
+impl Move {
+    pub const QUIET: u8 = 0;
+    pub const CAPTURE: u8 = 1;
+    pub const EN_PASSANT: u8 = 2;
+    pub const CASTLE: u8 = 4;
+    pub const DOUBLE_PUSH: u8 = 8;
+    pub const PROMOTION: u8 = 16;
+
+    pub fn new(from:Square, to:Square) -> Move {
+        return Move{from:from, to:to, promotion:None, flags:Move::QUIET};
+    }
 
-    match c_char {
-        'b'| 'B' => PieceType::BISHIOP,
-        'r'| 'R' => PieceType::ROOK,
-        'n'| 'N' => PieceType::KNIGHT,
-        'q'| 'Q' => PieceType::QUEEN,
-        'x'| 'X' => PieceType::NONE,
-        _ => PieceType::NONE
+    pub fn with_promotion(from:Square, to:Square, promotion:PieceType) -> Move {
+        return Move{from:from, to:to, promotion:Some(promotion), flags:Move::QUIET};
+    }
+
+    //parses long algebraic notation as used by UCI, e.g. "e2e4" or "e7e8q"
+    pub fn parse(chess_not: &str) -> Result<Move,NotationError> {
+        if !chess_not.is_ascii() || (chess_not.len() != 4 && chess_not.len() != 5) {
+            return Err(NotationError::InvalidMove(chess_not.to_string()));
+        }
+        let from = Square::parse(&chess_not[0..2])?;
+        let to = Square::parse(&chess_not[2..4])?;
+        match chess_not[4..].chars().next() {
+            None => return Ok(Move::new(from, to)),
+            Some(c) => match promotion_piece(c) {
+                Some(p) => return Ok(Move::with_promotion(from, to, p)),
+                None => return Err(NotationError::InvalidPromotion(c))
+            }
+        }
+    }
+
+    //same move, ignoring the flags
+    pub fn matches(self, other:Move) -> bool {
+        return self.from == other.from && self.to == other.to && self.promotion == other.promotion;
+    }
+
+    pub fn is_capture(self) -> bool {
+        return self.flags & Move::CAPTURE != 0;
+    }
+
+    pub fn is_en_passant(self) -> bool {
+        return self.flags & Move::EN_PASSANT != 0;
+    }
+
+    pub fn is_castle(self) -> bool {
+        return self.flags & Move::CASTLE != 0;
     }
 }
 
-pub fn encode_notation(pos:(usize,usize)) -> String {
-
-    let  ycoord: String = (pos.1+1).to_string();
-    let xcoord = match  pos.0 {
-        0 =>  "a",
-        1 =>  "b",
-        2 =>  "c",
-        3 =>  "d",
-        4 =>  "e",
-        5 =>  "f",
-        6 =>  "g",
-        7 =>  "h",
-        _ => panic!()
-    };
-    
-    let mut encoded: String = String::from(xcoord);
-    encoded.push_str(&ycoord);
-    return encoded.to_string();
+impl std::str::FromStr for Move {
+    type Err = NotationError;
+    fn from_str(s: &str) -> Result<Move,NotationError> {
+        return Move::parse(s);
+    }
+}
+
+impl std::fmt::Display for Move {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}{}", self.from, self.to)?;
+        if let Some(p) = self.promotion {
+            write!(f, "{}", san_letter(p).to_ascii_lowercase())?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone,PartialEq,Eq,Debug)]
+pub enum NotationError {
+    InvalidSquare(String),
+    InvalidMove(String),
+    InvalidPromotion(char),
+}
+
+impl std::fmt::Display for NotationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            NotationError::InvalidSquare(s) => write!(f, "'{}' is not a square", s),
+            NotationError::InvalidMove(s) => write!(f, "'{}' is not a move", s),
+            NotationError::InvalidPromotion(c) => write!(f, "cannot promote to '{}'", c),
+        }
+    }
+}
+
+//pieces a pawn can promote to, by their letter
+pub fn promotion_piece(c:char) -> Option<PieceType> {
+    match c {
+        'b'| 'B' => Some(PieceType::BISHIOP),
+        'r'| 'R' => Some(PieceType::ROOK),
+        'n'| 'N' => Some(PieceType::KNIGHT),
+        'q'| 'Q' => Some(PieceType::QUEEN),
+        _ => None
+    }
+}
+
+//piece letter used by SAN and FEN
+pub fn san_letter(variant:PieceType) -> char {
+    match variant {
+        PieceType::KING => 'K',
+        PieceType::QUEEN => 'Q',
+        PieceType::ROOK => 'R',
+        PieceType::BISHIOP => 'B',
+        PieceType::KNIGHT => 'N',
+        PieceType::PAWN => 'P',
+        PieceType::NONE => panic!("NONE has no letter")
+    }
 }
 
 //modified fen, for testing
-pub fn map_fen(fen:char)-> Position{
-    let pos_c = PositionContent::NONE;
+pub fn map_fen(fen:char)-> Option<Piece>{
     let mut col = Color::W;
     let mut pc:PieceType = PieceType::PAWN;
 
     match fen {
-        '.' => {return Position{content:pos_c}},
+        '.' => {return None},
         'p' => {col=Color::B; pc=PieceType::PAWN},
         'k' => {col=Color::B; pc=PieceType::KING},
         'q' => {col=Color::B; pc=PieceType::QUEEN},
@@ -206,14 +594,13 @@ pub fn map_fen(fen:char)-> Position{
         'R' => {pc=PieceType::ROOK}, 
         _=>panic!()
     }
-    let pos_c = PositionContent::PIECE_CONT(Piece{variant:pc,color:col,has_moved:false});
-    return Position{content:pos_c};
+    return Some(Piece{variant:pc,color:col});
 }
 
-pub fn demap_fen(pos:Position)-> char {
-    match pos.content {
-        PositionContent::NONE => {return  '.'},
-        PositionContent::PIECE_CONT(p) => {
+pub fn demap_fen(pos:Option<Piece>)-> char {
+    match pos {
+        None => {return  '.'},
+        Some(p) => {
             let mut c = 'l';
             match p.variant {
                 PieceType::PAWN => {c='p'},
@@ -230,24 +617,513 @@ pub fn demap_fen(pos:Position)-> char {
     }
 }
 
+//reasons a FEN string can be rejected by Game::from_fen
+#[derive(Clone,PartialEq,Eq,Debug)]
+pub enum FenError {
+    //a FEN record has exactly 6 space separated fields
+    FieldCount(usize),
+    RankCount(usize),
+    //the rank (1-8) does not describe exactly 8 squares
+    RankLength(usize),
+    InvalidPiece(char),
+    //each side needs exactly one king
+    KingCount(Color),
+    PawnOnBackRank,
+    //the side that just moved has left its king in check
+    OpponentInCheck,
+    InvalidSideToMove(String),
+    InvalidCastling(String),
+    InvalidEnPassant(String),
+    InvalidHalfmoveClock(String),
+    InvalidFullmoveNumber(String),
+}
+
+impl std::fmt::Display for FenError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            FenError::FieldCount(n) => write!(f, "expected 6 fields, found {}", n),
+            FenError::RankCount(n) => write!(f, "expected 8 ranks, found {}", n),
+            FenError::RankLength(r) => write!(f, "rank {} does not have 8 squares", r),
+            FenError::InvalidPiece(c) => write!(f, "invalid piece '{}'", c),
+            FenError::KingCount(c) => write!(f, "{:?} must have exactly one king", c),
+            FenError::PawnOnBackRank => write!(f, "pawns cannot stand on the first or last rank"),
+            FenError::OpponentInCheck => write!(f, "the side not to move is in check"),
+            FenError::InvalidSideToMove(s) => write!(f, "invalid side to move '{}'", s),
+            FenError::InvalidCastling(s) => write!(f, "invalid castling rights '{}'", s),
+            FenError::InvalidEnPassant(s) => write!(f, "invalid en passant square '{}'", s),
+            FenError::InvalidHalfmoveClock(s) => write!(f, "invalid halfmove clock '{}'", s),
+            FenError::InvalidFullmoveNumber(s) => write!(f, "invalid fullmove number '{}'", s),
+        }
+    }
+}
+
+//reasons a SAN move can be rejected by Game::parse_san, each carries the move as it was given
+#[derive(Clone,PartialEq,Eq,Debug)]
+pub enum SanError {
+    Malformed(String),
+    //no legal move matches
+    IllegalMove(String),
+    //more than one legal move matches, the move needs a file or rank
+    Ambiguous(String),
+    MissingPromotion(String),
+    UnexpectedPromotion(String),
+    //the move was understood but could not be played, e.g. because the game is over
+    Rejected(MoveError),
+}
+
+impl std::fmt::Display for SanError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SanError::Malformed(s) => write!(f, "'{}' is not a SAN move", s),
+            SanError::IllegalMove(s) => write!(f, "'{}' is not a legal move", s),
+            SanError::Ambiguous(s) => write!(f, "'{}' matches more than one move", s),
+            SanError::MissingPromotion(s) => write!(f, "'{}' needs a promotion piece", s),
+            SanError::UnexpectedPromotion(s) => write!(f, "'{}' cannot promote", s),
+            SanError::Rejected(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+//reasons Game::move_piece can reject a move
+#[derive(Clone,Copy,PartialEq,Eq,Debug)]
+pub enum MoveError {
+    GameOver,
+    NoPiece(Square),
+    //the piece belongs to the other side, carries the side to move
+    WrongSide(Color),
+    //the piece cannot reach that square at all
+    IllegalDestination(Square),
+    //the move would leave (or put) the own king in check
+    KingInCheck,
+    //a pawn reaching the last rank needs a piece to promote to
+    MissingPromotion,
+    //the move cannot promote, or not to that piece
+    InvalidPromotion(PieceType),
+}
+
+impl std::fmt::Display for MoveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            MoveError::GameOver => write!(f, "the game is already over"),
+            MoveError::NoPiece(sq) => write!(f, "there is no piece on {}", sq),
+            MoveError::WrongSide(c) => write!(f, "it is {} to move", if *c == Color::W {"white"} else {"black"}),
+            MoveError::IllegalDestination(sq) => write!(f, "that piece cannot move to {}", sq),
+            MoveError::KingInCheck => write!(f, "your king would be in check"),
+            MoveError::MissingPromotion => write!(f, "choose a piece to promote to"),
+            MoveError::InvalidPromotion(p) => write!(f, "cannot promote to {:?}", p),
+        }
+    }
+}
+
+pub const START_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+const STANDARD_BACK_RANK: [PieceType; 8] = [PieceType::ROOK,PieceType::KNIGHT,PieceType::BISHIOP,PieceType::QUEEN,PieceType::KING,PieceType::BISHIOP,PieceType::KNIGHT,PieceType::ROOK];
+
+//same as map_fen but does not panic, '.' is not a valid FEN character
+fn fen_piece(fen:char) -> Option<Piece> {
+    let color = if fen.is_ascii_uppercase() {Color::W} else {Color::B};
+    let variant = match fen.to_ascii_lowercase() {
+        'p' => PieceType::PAWN,
+        'n' => PieceType::KNIGHT,
+        'b' => PieceType::BISHIOP,
+        'r' => PieceType::ROOK,
+        'q' => PieceType::QUEEN,
+        'k' => PieceType::KING,
+        _ => return None
+    };
+    return Some(Piece{variant:variant,color:color});
+}
+
 
 
 impl Game {
+    //creates a game from a standard FEN record, e.g. START_FEN. castling may also be given as X-FEN or Shredder-FEN,
+    //rights that only make sense in chess960 make it a chess960 game
+    pub fn from_fen(fen: &str) -> Result<Game,FenError> {
+        let fields: Vec<&str> = fen.split_whitespace().collect();
+        if fields.len() != 6 {
+            return Err(FenError::FieldCount(fields.len()));
+        }
+
+        let mut game = start();
+        game.board = Board::empty();
+
+        //piece placement, starting from rank 8
+        let ranks: Vec<&str> = fields[0].split('/').collect();
+        if ranks.len() != 8 {
+            return Err(FenError::RankCount(ranks.len()));
+        }
+        let mut kings = (0,0);
+        for (n, rank) in ranks.iter().enumerate() {
+            let y = 7 - n;
+            let mut x = 0;
+            for c in rank.chars() {
+                if let Some(empty) = c.to_digit(10) {
+                    if empty == 0 || empty > 8 {
+                        return Err(FenError::RankLength(y+1));
+                    }
+                    x += empty as usize;
+                    continue;
+                }
+                let piece = match fen_piece(c) {
+                    Some(p) => p,
+                    None => return Err(FenError::InvalidPiece(c))
+                };
+                if x > 7 {
+                    return Err(FenError::RankLength(y+1));
+                }
+                match piece.variant {
+                    PieceType::KING => {
+                        if piece.color == Color::W {
+                            kings.0 += 1;
+                        } else {
+                            kings.1 += 1;
+                        }
+                    },
+                    PieceType::PAWN => {
+                        if y == 0 || y == 7 {
+                            return Err(FenError::PawnOnBackRank);
+                        }
+                    },
+                    _ => {}
+                }
+                game.board.set_content(Square::new(x,y), Some(piece));
+                x += 1;
+            }
+            if x != 8 {
+                return Err(FenError::RankLength(y+1));
+            }
+        }
+        if kings.0 != 1 {
+            return Err(FenError::KingCount(Color::W));
+        }
+        if kings.1 != 1 {
+            return Err(FenError::KingCount(Color::B));
+        }
+
+        //side to move
+        let white_to_move = match fields[1] {
+            "w" => true,
+            "b" => false,
+            s => return Err(FenError::InvalidSideToMove(s.to_string()))
+        };
+
+        //castling, each right needs the king and a rook on their back rank.
+        //K and Q stand for the outermost rook on that side of the king, file letters (Shredder-FEN, or X-FEN when that rook is not meant) name the rook
+        if fields[2] != "-" {
+            let castling_err = FenError::InvalidCastling(fields[2].to_string());
+            let mut seen = vec![];
+            for c in fields[2].chars() {
+                let color = if c.is_ascii_uppercase() {Color::W} else {Color::B};
+                let y = if color == Color::W {0} else {7};
+                let king = if color == Color::W {game.board.wk_pos} else {game.board.bk_pos};
+                let is_rook = |x: &usize| match game.board.piece_at(Square::new(*x,y)) {
+                    Some(p) => p.variant == PieceType::ROOK && p.color == color,
+                    None => false
+                };
+                let rook_x = match c.to_ascii_lowercase() {
+                    'k' => (king.x+1..8).rev().find(is_rook),
+                    'q' => (0..king.x).find(is_rook),
+                    f @ 'a'..='h' => Some(f as usize - 'a' as usize).filter(is_rook),
+                    _ => None
+                };
+                let Some(rook_x) = rook_x else {return Err(castling_err)};
+                if king.y != y || seen.contains(&(color, rook_x > king.x)) {
+                    return Err(castling_err);
+                }
+                seen.push((color, rook_x > king.x));
+
+                game.board.castling |= 1 << Square::new(rook_x,y).index();
+                if king.x != 4 || (rook_x != 0 && rook_x != 7) {
+                    game.chess960 = true;
+                }
+            }
+        }
+
+        //halfmove clock and fullmove number, the turn counter is odd when white is to move
+        let halfmove: usize = match fields[4].parse() {
+            Ok(n) => n,
+            Err(_) => return Err(FenError::InvalidHalfmoveClock(fields[4].to_string()))
+        };
+        //a fullmove number too large to count plies in is rejected like one that doesn't parse
+        let plies = match fields[5].parse::<usize>() {
+            Ok(n) if n > 0 => n.checked_mul(2),
+            _ => None
+        };
+        let Some(plies) = plies else {return Err(FenError::InvalidFullmoveNumber(fields[5].to_string()))};
+        game.turn_counter = plies - if white_to_move {1} else {0};
+        game.board.halfmove_clock = halfmove;
+        game.last_capture = game.turn_counter.saturating_sub(halfmove);
+
+        //en passant target square, the pawn that double moved stands one rank closer to the side to move
+        if fields[3] != "-" {
+            let ep_err = FenError::InvalidEnPassant(fields[3].to_string());
+            let target = match Square::parse(fields[3]) {
+                Ok(sq) => sq,
+                Err(_) => return Err(ep_err)
+            };
+            let (target_y, pawn_y, pawn_color) = if white_to_move {(5,4,Color::B)} else {(2,3,Color::W)};
+            if target.y != target_y {
+                return Err(ep_err);
+            }
+            let pawn_ok = match game.board.piece_at(Square::new(target.x, pawn_y)) {
+                Some(p) => p.variant == PieceType::PAWN && p.color == pawn_color,
+                None => false
+            };
+            let origin_y = if white_to_move {6} else {1};
+            if !pawn_ok || !game.board.is_empty(target) || !game.board.is_empty(Square::new(target.x,origin_y)) {
+                return Err(ep_err);
+            }
+            game.board.ep = Some(target);
+        }
+        game.board.hash = game.board.compute_hash(game.turn_counter);
+        game.repeat_map.clear();
+        game.repeat_map.insert(game.board.hash, 1);
+
+        let waiting_king = if white_to_move {game.board.bk_pos} else {game.board.wk_pos};
+        if game.board.is_in_check(waiting_king) {
+            return Err(FenError::OpponentInCheck);
+        }
+        game.start_fen = fen.split_whitespace().collect::<Vec<&str>>().join(" ");
+
+        return Ok(game);
+    }
+
+    //writes the current position as a standard FEN record.
+    //castling is written as X-FEN, which only differs when a chess960 castling rook is not the outermost one on its side
+    pub fn to_fen(&mut self) -> String {
+        let mut fen = self.board.placement_fen();
+
+        fen.push(' ');
+        fen.push(if self.turn_counter % 2 == 1 {'w'} else {'b'});
+
+        fen.push(' ');
+        let mut castling = String::new();
+        for (c, color, kingside) in [('K',Color::W,true),('Q',Color::W,false),('k',Color::B,true),('q',Color::B,false)] {
+            let Some(rook_x) = self.board.castling_rook(color, kingside) else {continue};
+            let y = if color == Color::W {0} else {7};
+            //another rook further out would be the one K or Q stands for
+            let outer = if kingside {rook_x+1..8} else {0..rook_x};
+            let shadowed = outer.into_iter().any(|x| match self.board.piece_at(Square::new(x,y)) {
+                Some(p) => p.variant == PieceType::ROOK && p.color == color,
+                None => false
+            });
+            if shadowed {
+                let file = (b'a' + rook_x as u8) as char;
+                castling.push(if color == Color::W {file.to_ascii_uppercase()} else {file});
+            } else {
+                castling.push(c);
+            }
+        }
+        if castling.is_empty() {
+            castling.push('-');
+        }
+        fen.push_str(&castling);
+
+        fen.push(' ');
+        match self.board.ep {
+            Some(sq) => fen.push_str(&sq.to_string()),
+            None => fen.push('-')
+        }
+
+        fen.push_str(&format!(" {} {}", self.board.halfmove_clock, (self.turn_counter + 1) / 2));
+        return fen;
+    }
+
+    //writes a legal move in standard algebraic notation, e.g. "Nbd7", "exd6", "e8=Q+" or "O-O-O#"
+    pub fn to_san(&mut self, mv:Move) -> String {
+        //the generated move carries the flags, e.g. whether a king move castles
+        let mv = self.get_legal_moves(mv.from).into_iter().find(|m| m.matches(mv)).unwrap_or(mv);
+        let piece = self.board.get_piece(mv.from);
+        let mut san = String::new();
+
+        if mv.is_castle() {
+            //the rook stands to the right of the king for O-O, whether to is the king's or the rook's square
+            san.push_str(if mv.to.x > mv.from.x {"O-O"} else {"O-O-O"});
+        } else {
+            let is_capture = !self.board.is_empty(mv.to) || (piece.variant == PieceType::PAWN && mv.from.x != mv.to.x);
+            if piece.variant == PieceType::PAWN {
+                if is_capture {
+                    san.push_str(&mv.from.to_string()[..1]);
+                }
+            } else {
+                san.push(san_letter(piece.variant));
+                //other pieces of the same kind that could also go to the destination
+                let mut others = vec![];
+                for x in 0..8 {
+                    for y in 0..8 {
+                        let sq = Square::new(x,y);
+                        if sq == mv.from || self.board.is_empty(sq) {
+                            continue;
+                        }
+                        let other = self.board.get_piece(sq);
+                        if other.variant == piece.variant && other.color == piece.color && self.get_legal_moves(sq).iter().any(|m| m.to == mv.to) {
+                            others.push(sq);
+                        }
+                    }
+                }
+                let square = mv.from.to_string();
+                if !others.is_empty() {
+                    if others.iter().all(|o| o.x != mv.from.x) {
+                        san.push_str(&square[..1]);
+                    } else if others.iter().all(|o| o.y != mv.from.y) {
+                        san.push_str(&square[1..]);
+                    } else {
+                        san.push_str(&square);
+                    }
+                }
+            }
+            if is_capture {
+                san.push('x');
+            }
+            san.push_str(&mv.to.to_string());
+            if let Some(promotion) = mv.promotion {
+                san.push('=');
+                san.push(san_letter(promotion));
+            }
+        }
+
+        //play the move on a copy to see if it gives check or mate
+        let mut next = self.clone();
+        let king = if piece.color == Color::W {next.board.bk_pos} else {next.board.wk_pos};
+        if next.move_piece(mv).is_ok() && next.board.is_in_check(king) {
+            san.push(if next.get_all_legal_moves().is_empty() {'#'} else {'+'});
+        }
+        return san;
+    }
+
+    //finds the legal move described by a SAN string
+    //check marks and annotations (+#!?) are ignored, "0-0" and promotions without '=' ("e8Q") are accepted
+    pub fn parse_san(&mut self, san:&str) -> Result<Move,SanError> {
+        let malformed = SanError::Malformed(san.to_string());
+        let body = san.trim_end_matches(|c| "+#!?".contains(c));
+        let color = if self.turn_counter % 2 == 1 {Color::W} else {Color::B};
+        let king = if color == Color::W {self.board.wk_pos} else {self.board.bk_pos};
+
+        if body == "O-O" || body == "0-0" || body == "O-O-O" || body == "0-0-0" {
+            let kingside = body.len() == 3;
+            match self.get_legal_moves(king).into_iter().find(|m| m.is_castle() && (m.to.x > m.from.x) == kingside) {
+                Some(m) => return Ok(m),
+                None => return Err(SanError::IllegalMove(san.to_string()))
+            }
+        }
+
+        let mut chars: Vec<char> = body.chars().collect();
+        let variant = match chars.first() {
+            Some('K') => PieceType::KING,
+            Some('Q') => PieceType::QUEEN,
+            Some('R') => PieceType::ROOK,
+            Some('B') => PieceType::BISHIOP,
+            Some('N') => PieceType::KNIGHT,
+            Some('a'..='h') => PieceType::PAWN,
+            _ => return Err(malformed)
+        };
+        if variant != PieceType::PAWN {
+            chars.remove(0);
+        }
+
+        let mut promotion = None;
+        if variant == PieceType::PAWN && chars.len() > 2 && "QRBN".contains(chars[chars.len()-1]) {
+            promotion = promotion_piece(chars.pop().unwrap());
+            if chars.last() == Some(&'=') {
+                chars.pop();
+            }
+        }
+
+        //what is left is [file][rank][x]<square>
+        if chars.len() < 2 {
+            return Err(malformed);
+        }
+        let dest: String = chars[chars.len()-2..].iter().collect();
+        let to = match Square::parse(&dest) {
+            Ok(sq) => sq,
+            Err(_) => return Err(malformed)
+        };
+        let mut prefix = &chars[..chars.len()-2];
+        let is_capture = prefix.last() == Some(&'x');
+        if is_capture {
+            prefix = &prefix[..prefix.len()-1];
+        }
+        let mut from_file = None;
+        let mut from_rank = None;
+        match prefix {
+            [] => {},
+            [f @ 'a'..='h'] => from_file = Some(*f as usize - 'a' as usize),
+            [r @ '1'..='8'] => from_rank = Some(*r as usize - '1' as usize),
+            [f @ 'a'..='h', r @ '1'..='8'] => {
+                from_file = Some(*f as usize - 'a' as usize);
+                from_rank = Some(*r as usize - '1' as usize);
+            },
+            _ => return Err(malformed)
+        }
+        //pawns only name their file, and only when capturing
+        if variant == PieceType::PAWN && (from_rank.is_some() || from_file.is_some() != is_capture) {
+            return Err(malformed);
+        }
+        if variant == PieceType::PAWN && !is_capture {
+            from_file = Some(to.x);
+        }
+
+        let mut found = None;
+        for x in 0..8 {
+            for y in 0..8 {
+                let sq = Square::new(x,y);
+                if self.board.is_empty(sq) || from_file.map_or(false, |f| f != x) || from_rank.map_or(false, |r| r != y) {
+                    continue;
+                }
+                let p = self.board.get_piece(sq);
+                if p.variant != variant || p.color != color {
+                    continue;
+                }
+                if let Some(m) = self.get_legal_moves(sq).into_iter().find(|m| m.to == to) {
+                    if found.is_some() {
+                        return Err(SanError::Ambiguous(san.to_string()));
+                    }
+                    found = Some(m);
+                }
+            }
+        }
+        let mut mv = match found {
+            Some(m) => m,
+            None => return Err(SanError::IllegalMove(san.to_string()))
+        };
+
+        //a pawn reaching the last rank has to say what it becomes, other moves cannot promote
+        let reaches_end = mv.promotion.is_some();
+        if reaches_end && promotion.is_none() {
+            return Err(SanError::MissingPromotion(san.to_string()));
+        }
+        if !reaches_end && promotion.is_some() {
+            return Err(SanError::UnexpectedPromotion(san.to_string()));
+        }
+        mv.promotion = promotion;
+        return Ok(mv);
+    }
+
+    //plays a move given in SAN
+    pub fn move_piece_san(&mut self, san:&str) -> Result<Move,SanError> {
+        let mv = self.parse_san(san)?;
+        return self.move_piece(mv).map_err(SanError::Rejected);
+    }
+
+    //same moves as get_all_legal_moves, written in SAN
+    pub fn get_all_legal_moves_san(&mut self) -> Vec<String> {
+        let mut all_moves = vec![];
+        for mv in self.get_all_legal_moves() {
+            all_moves.push(self.to_san(mv));
+        }
+        return all_moves;
+    }
+
     pub fn load_state(&mut self,state:String){
         let rows:Vec<String> =state.split('\n').map(|s| s.to_string()).collect();
 
         let mut j = 0;
         let mut i = 7;
 
+        self.board = Board::empty();
         for row in rows {
             for place in row.chars() {
-                if place =='k' {
-                    self.board.bk_pos = (j,i);
-                } else if place == 'K' {
-                    self.board.wk_pos = (j,i);
-                    
-                }
-                self.board.positions[j][i] = map_fen(place);
+                self.board.set_content(Square::new(j,i), map_fen(place));
                 j+=1;
             }
             j=0;
@@ -255,6 +1131,9 @@ impl Game {
                 i-=1;
             }
         }
+        //nothing has moved yet, every rook on its own back rank may castle
+        self.board.castling = (self.board.pieces[0][PieceType::ROOK.index()] & 0xFF) | (self.board.pieces[1][PieceType::ROOK.index()] & (0xFF << 56));
+        self.board.hash = self.board.compute_hash(self.turn_counter);
 
     }
 
@@ -264,234 +1143,250 @@ impl Game {
         self.last_capture = 0;
         self.max_repeated = 0;
         self.repeat_map.clear();
+        self.repeat_map.insert(self.board.hash, 1);
         self.old_state.reset();
+        self.start_fen = START_FEN.to_string();
+        self.history.clear();
+        self.undo_stack.clear();
+        self.redo_stack.clear();
+        self.declared_result = None;
+        self.chess960 = false;
+    }
+
+    //starts over from chess960 position number index (0-959)
+    pub fn restart_chess960(&mut self, index: usize) {
+        self.restart();
+        self.board.reset_chess960(index);
+        self.old_state = self.board.clone();
+        self.repeat_map.clear();
+        self.repeat_map.insert(self.board.hash, 1);
+        self.chess960 = true;
+        self.start_fen = self.to_fen();
+    }
+
+    //takes back the last move, returns it or None if there is nothing to undo
+    pub fn undo(&mut self) -> Option<Move> {
+        let mv = self.history.pop()?;
+        let record = self.undo_stack.pop().expect("undo record missing");
+
+        //the position after the move no longer happened
+        if let Some(count) = self.repeat_map.get_mut(&self.board.hash) {
+            *count -= 1;
+            if *count == 0 {
+                self.repeat_map.remove(&self.board.hash);
+            }
+        }
+
+        self.board.unmake_move(record.board);
+        self.last_capture = record.last_capture;
+        self.max_repeated = record.max_repeated;
+        self.declared_result = None;
+        self.turn_counter -= 1;
+        self.redo_stack.push(mv);
+        return Some(mv);
+    }
+
+    //plays the last undone move again, None if there is nothing to redo
+    pub fn redo(&mut self) -> Option<Move> {
+        let mv = self.redo_stack.pop()?;
+        //move_piece forgets the redo stack, as any new move would
+        let rest = std::mem::take(&mut self.redo_stack);
+        let played = self.move_piece(mv).ok();
+        self.redo_stack = rest;
+        return played;
     }
 
-    //moves a piece from a position to a postion, returns true if successful, false if not.
-    //from is the initial position, to is the destionation, promotion is required as it is used when promoting a pawn
-    //valid values for promotion are: "Q","B","N","R","X" - "X" is a placeholder and should be used when moving but not promoting 
-    pub fn move_piece(&mut self, from: &str, to: &str,promotion:&str) -> bool {
+    //plays a move and returns it with its flags filled in, or the reason it was rejected.
+    //the move only has to name from, to and the promotion piece (None unless a pawn reaches the last rank)
+    pub fn move_piece(&mut self, mv: Move) -> Result<Move,MoveError> {
 
         if self.check_state() != GameState::ONGOING {
-            return false
+            return Err(MoveError::GameOver)
         } 
 
-        let f_coords = decode_notation(from);
-        let t_coords = decode_notation(to);
+        let piece = match self.board.piece_at(mv.from) {
+            Some(p) => p,
+            None => return Err(MoveError::NoPiece(mv.from))
+        };
+        let to_move = if self.turn_counter % 2 == 1 {Color::W} else {Color::B};
+        if piece.color != to_move {
+            return Err(MoveError::WrongSide(to_move));
+        }
 
-        let p_type = decode_promotion(promotion);
+        //find out why the move is not legal by looking at the moves the piece could make if checks were ignored
+        let candidates: Vec<Move> = self.get_pseudo_legal_moves(mv.from).into_iter().filter(|m| m.to == mv.to).collect();
+        if candidates.is_empty() {
+            return Err(MoveError::IllegalDestination(mv.to));
+        }
+        let legal = match candidates.iter().find(|m| m.promotion == mv.promotion) {
+            Some(m) => *m,
+            None if mv.promotion.is_none() => return Err(MoveError::MissingPromotion),
+            None => return Err(MoveError::InvalidPromotion(mv.promotion.unwrap()))
+        };
+        if !self.board.is_legal(legal,self.turn_counter) {
+            return Err(MoveError::KingInCheck);
+        }
 
-        //custom logic for pawn/king
+        let (last_capture, max_repeated) = (self.last_capture, self.max_repeated);
+        let undo = self.board.make_move(legal,self.turn_counter);
+        self.history.push(legal);
+        self.undo_stack.push(UndoRecord{board:undo, last_capture:last_capture, max_repeated:max_repeated});
+        self.redo_stack.clear();
 
-        
-        //get legal moves from that place
-        let lg_moves = self.get_legal_moves(from);
-        
-        if lg_moves.contains(&t_coords) {
+        if undo.captured.is_some() {
+            self.last_capture = self.turn_counter; 
+        }
+        self.turn_counter += 1;
+        //inserts the position hash into the map of positions and their amount
+        let ret = self.repeat_map.insert(self.board.hash, 1);
+        match ret {
+            None => {},
+            Some(val) => {
+                self.repeat_map.insert(self.board.hash, val+1);
+                if self.max_repeated < val +1 {
+                    self.max_repeated = val + 1;
+                }
+            }
+        }
 
-            let has_captured = self.board.force_move(f_coords, t_coords,p_type,self.turn_counter);
+        return Ok(legal);
+    }
 
-            if has_captured {
-                self.last_capture = self.turn_counter; 
-            }
+    
+    //returns all legal moves for the side to move
+    pub fn get_all_legal_moves(&mut self) -> Vec<Move> {
+        return self.legal_moves_from(!0);
+    }
+
+    //legal moves of the side to move for the pieces on from_mask
+    fn legal_moves_from(&mut self, from_mask: u64) -> Vec<Move> {
+        let mut moves = Vec::with_capacity(48);
+        self.board.generate(self.turn_counter, from_mask, self.chess960, false, &mut moves);
+        return moves;
+    }
+
+    //number of leaf positions depth plies ahead, used to check the move generator against known counts
+    pub fn perft(&mut self, depth: usize) -> u64 {
+        if depth == 0 {
+            return 1;
+        }
+        let moves = self.get_all_legal_moves();
+        if depth == 1 {
+            return moves.len() as u64;
+        }
+
+        let mut nodes = 0;
+        for mv in moves {
+            let undo = self.board.make_move(mv, self.turn_counter);
             self.turn_counter += 1;
-            //inserts the position hash into the map of positions and their amount
-            let ret = self.repeat_map.insert(self.board.positions, 1);
-            match ret {
-                None => {},
-                Some(val) => {
-                    self.repeat_map.insert(self.board.positions, val+1);
-                    if self.max_repeated < val +1 {
-                        self.max_repeated = val + 1;
-                    }
-                }
-            }
+            nodes += self.perft(depth - 1);
+            self.turn_counter -= 1;
+            self.board.unmake_move(undo);
+        }
+        return nodes;
+    }
 
-            return true
+    //perft split by the first move, to find which move a wrong count comes from
+    pub fn perft_divide(&mut self, depth: usize) -> Vec<(Move, u64)> {
+        let mut divide = vec![];
+        for mv in self.get_all_legal_moves() {
+            let undo = self.board.make_move(mv, self.turn_counter);
+            self.turn_counter += 1;
+            let nodes = if depth > 0 {self.perft(depth - 1)} else {0};
+            divide.push((mv, nodes));
+            self.turn_counter -= 1;
+            self.board.unmake_move(undo);
         }
+        return divide;
+    }
 
-        
-        //else return false
-            false
+    //prints the divide in the usual "e2e4: 20" form followed by the total, returns the total
+    pub fn print_divide(&mut self, depth: usize) -> u64 {
+        let divide = self.perft_divide(depth);
+        let mut total = 0;
+        for (mv, nodes) in &divide {
+            println!("{}: {}", mv, nodes);
+            total += nodes;
+        }
+        println!();
+        println!("Nodes searched: {}", total);
+        return total;
+    }
+
+    //gives a vec of legal moves for the piece on from, empty if there is no piece of the side to move there.
+    //a pawn reaching the last rank gives one move for each piece it can promote to
+    pub fn get_legal_moves(&mut self, from: Square) -> Vec<Move> {
+        return self.legal_moves_from(1 << from.index());
+    }
+
+    //moves the piece on from could make if it did not have to care about its own king being in check
+    fn get_pseudo_legal_moves(&mut self, from: Square) -> Vec<Move> {
+        let mut moves = vec![];
+        self.board.pseudo_legal_moves(self.turn_counter, 1 << from.index(), self.chess960, &mut moves);
+        return moves;
     }
 
     
-    //returns all legal moves in the standard chess notation <letter><number>. First part is from, second is to. 
-    pub fn get_all_legal_moves(&mut self) -> Vec<(String,String)> {
-        let mut all_moves:Vec<(String,String)> = vec![];
-        //for all PLACES on the board call get_legal_moves on pieces for the right color
-        //yeah, not the best thing ever
+    //how often the current position has occurred, itself included
+    pub fn repetitions(&self) -> usize {
+        return *self.repeat_map.get(&self.board.hash).unwrap_or(&0);
+    }
 
-        
+    //draw the side to move may claim right now, if any
+    pub fn claimable_draw(&mut self) -> Option<DrawReason> {
+        if self.check_state() != GameState::ONGOING {
+            return None;
+        }
+        if self.repetitions() >= 3 {
+            return Some(DrawReason::THREEFOLD_REPETITION);
+        }
+        //50 moves by each player
+        if self.board.halfmove_clock >= 100 {
+            return Some(DrawReason::FIFTY_MOVES);
+        }
+        return None;
+    }
 
-        for i in 0..8 {
-            for j in 0..8 {
-                let mut pos = self.board.positions[j][i];
-
-                if pos.content != PositionContent::NONE {
-                    if pos.content.get_piece().color == Color::B && self.turn_counter % 2 == 0 {
-                        let l_moves = self.get_legal_moves(encode_notation((j,i)).as_str());
-                        let j_val = j;
-                        let i_val = i;
-                        let enc_from: &str = &encode_notation((j_val,i_val))[..];
-                        let temp:Vec<(String,String)> = l_moves.iter().map(|m| (enc_from.to_string(),encode_notation(*m))).collect();
-                        all_moves.extend(temp);
-
-                    } else if pos.content.get_piece().color == Color::W && self.turn_counter % 2 == 1 {
-                        let l_moves =self.get_legal_moves(encode_notation((j,i)).as_str());
-                        let j_val = j;
-                        let i_val = i; 
-                        let enc_from: &str = &encode_notation((j_val,i_val))[..];
-                        let temp:Vec<(String,String)> = l_moves.iter().map(|m| (enc_from.to_string(),encode_notation(*m))).collect();
-                        all_moves.extend(temp);
-                    };
-                }
-            }
-        } 
+    //ends the game as a draw if a claim is possible, returns the reason
+    pub fn claim_draw(&mut self) -> Option<DrawReason> {
+        let reason = self.claimable_draw()?;
+        self.declared_result = Some(GameState::DRAW(reason));
+        return Some(reason);
+    }
 
-        return all_moves;
+    //the losing side gives up, has no effect once the game is over
+    pub fn resign(&mut self, color:Color) {
+        let result = if color == Color::W {GameState::WIN_B(WinReason::RESIGNATION)} else {GameState::WIN_W(WinReason::RESIGNATION)};
+        self.declare(result);
     }
 
-    //gives a vec of legal moves notation is (usize,usize) where the first place is the x-coordinate, and the other one the y. Can be converted
-    //to the chess board notation by using encode_notation function.
-    
-    pub fn get_legal_moves(&mut self, from: &str) -> Vec<(usize,usize)> {
-        let f_coords: (usize, usize) = decode_notation(from);
-      
-        let f_content: PositionContent = self.board.positions[f_coords.0][f_coords.1].content;
-    
-        match f_content {
-            PositionContent::NONE => return vec![],
-            PositionContent::PIECE_CONT( p) => {
-                if p.color == Color::W && self.turn_counter % 2 == 0 {
-                   
-                    return vec![]  
-                } else if p.color == Color::B && self.turn_counter % 2 == 1 {
-                 
-                    return vec![] 
-                } else {
-                    let paths: Vec<(i16, i16)> = p.get_paths();
-    
-                    let mut moves: Vec<(usize,usize)> = vec![];
-                    match p.variant {
-           
-                        PieceType::BISHIOP|PieceType::ROOK|PieceType::QUEEN => {
-                            for path in paths {
-                                moves.append(&mut self.board.traverse_path(p, f_coords, path,10));
-                            }
-                        },
-                        PieceType::KNIGHT => {
-                            for path in paths {
-                                moves.append(&mut self.board.traverse_path(p, f_coords, path,1));
-                            } 
-                        }
-                        PieceType::KING => {
-                            //normal moves
-                            for path in paths {
-                                moves.append(&mut self.board.traverse_path(p, f_coords, path,1));
-                            }
-
-                            //castle
-                            if p.has_moved == false && self.board.is_in_check(f_coords) == false {
-                                let mut rook_l: PositionContent = self.board.positions[0][f_coords.1].content;
-                                let mut rook_r: PositionContent = self.board.positions[7][f_coords.1].content;
-                                //check if the spot has a rook that has yet to have moved
-                                if !rook_l.is_empty() && rook_l.get_piece().variant == PieceType::ROOK && rook_l.get_piece().has_moved == false {
-                                   
-                                    //check if the next 3 spots to the left are empty 
-                                    if self.board.positions[f_coords.0-1][f_coords.1].content.is_empty() && self.board.positions[f_coords.0-2][f_coords.1].content.is_empty() && self.board.positions[f_coords.0-3][f_coords.1].content.is_empty() {
-                                        //check if the next 1 spot to the left are valid (king cannot cross if any of that spots would give a check) 
-                                       if self.board.is_legal(f_coords, (f_coords.0-1,f_coords.1),PieceType::QUEEN,self.turn_counter) {
-                                            //can castle to the left
-                                            moves.push((f_coords.0-2,f_coords.1));
-                                       }
-
-                                    }
-                                }
-                                if !rook_r.is_empty() && rook_r.get_piece().variant == PieceType::ROOK && rook_r.get_piece().has_moved == false {
-                                   
-                                    //check if the next 2 spots to the right are empty 
-                                    if self.board.positions[f_coords.0+1][f_coords.1].content.is_empty() && self.board.positions[f_coords.0+2][f_coords.1].content.is_empty() {
-                                        //check if the next 1 spot to the right are valid (king cannot cross if any of that spots would give a check) 
-                                       if self.board.is_legal(f_coords, (f_coords.0+1,f_coords.1),PieceType::QUEEN,self.turn_counter) {
-                                            //can castle to the right
-                                            moves.push((f_coords.0+2,f_coords.1));
-                                       }
-
-                                    }
-                                }
-                            }
-                        },
-                        PieceType::PAWN => {
-                            let dir:i16= if p.color == Color::W {1} else {-1};
-                            if self.board.is_empty((f_coords.0,(f_coords.1 as i16 + dir) as usize)) {
-                                moves.push((f_coords.0,(f_coords.1 as i16 + dir) as usize));
-                            }
-                            //can move forward, check if double move is possible
-                            let dir:i16= if p.color == Color::W {2} else {-2};
-                            if !moves.is_empty() && p.has_moved == false && self.board.is_empty((f_coords.0,(f_coords.1 as i16 + dir) as usize))  {
-                                moves.push((f_coords.0,(f_coords.1 as i16 + dir) as usize));
-                            }
-
-                            //check diagonal capture
-                            let dir = if p.color==Color::W {1} else {-1};
-
-                            //check left/right side of pawn
-                            for n in vec![-1,1] {
-                                let c_coord: (i32, i32) = ((f_coords.0 as i32 + n),(f_coords.1 as i32 + dir));
-                                //check if in bounds of board
-                                if c_coord.0 >= 0 && c_coord.1 >= 0 && c_coord.0 <= 7 && c_coord.1 <= 7 {
-                                    // if both non-empty&enemy, we can capture
-                                    if !self.board.is_empty((c_coord.0 as usize, c_coord.1 as usize)) && self.board.get_piece((c_coord.0 as usize, c_coord.1 as usize)).color == p.color.get_inverted(){
-                                        moves.push((c_coord.0 as usize,c_coord.1 as usize));
-                                    }
-                                }
-                            }
-
-                            //check NPASS
-                            //if the last 2-p-push happened 1 turn ago, there is a possiblility
-                            if self.turn_counter.abs_diff(self.board.last_pass.2) == 1 {
-                                for n in vec![-1,1] {
-                                    let c_coord: (i32, i32) = ((f_coords.0 as i32 + n),(f_coords.1 as i32));
-                                    //check if in bounds of board
-                                    if c_coord.0 >= 0 && c_coord.1 >= 0 && c_coord.0 <= 7 && c_coord.1 <= 7 {
-                                        // if the coordinates of the landing match the field to the either side of the pawn, we have an NPASS 
-                                        if self.board.last_pass.0 == c_coord.0 as usize && self.board.last_pass.1 == c_coord.1 as usize {
-                                            
-                                            let dir = if p.color==Color::W {1} else {-1};
-                                            moves.push((c_coord.0 as usize,(c_coord.1 +dir) as usize));
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                        _ => panic!()
-                    } 
-                    let mut to_retain = vec![];
-
-                    for n in 0..moves.len() {
-                        
-                        //the queen is a placeholder in this case
-                        if self.board.is_legal(f_coords, moves[n],PieceType::QUEEN,self.turn_counter) {
-                            to_retain.push(moves[n]);
-                        }
-                        
-                    }
-                    
-                    return to_retain;
-                }
-            }
+    //color ran out of time, which is a draw if the opponent has nothing but the king left to mate with
+    pub fn timeout(&mut self, color:Color) {
+        let winner = color.get_inverted();
+        let result = if self.board.has_only_king(winner) {
+            GameState::DRAW(DrawReason::INSUFFICIENT_MATERIAL)
+        } else if winner == Color::W {
+            GameState::WIN_W(WinReason::TIMEOUT)
+        } else {
+            GameState::WIN_B(WinReason::TIMEOUT)
+        };
+        self.declare(result);
+    }
+
+    pub fn agree_draw(&mut self) {
+        self.declare(GameState::DRAW(DrawReason::AGREEMENT));
+    }
+
+    fn declare(&mut self, result:GameState) {
+        if !self.check_state().is_over() {
+            self.declared_result = Some(result);
         }
     }
 
-    
     pub fn check_state(&mut self) -> GameState {
-         //if 50moves since last capture -> draw
-        //if pos repeated 3 times -> draw
-        //else false
-        if self.turn_counter - self.last_capture > 50 || self.max_repeated >= 3 {
-            return GameState::DRAW
-        } 
+        if let Some(result) = self.declared_result {
+            return result;
+        }
 
         //check who is to move
         //if !draw and color who is moving does not have any legal moves -> opposite color wins 
@@ -499,20 +1394,32 @@ impl Game {
             if self.turn_counter % 2 == 0 {
                 //black moves 
                 if self.board.is_in_check(self.board.bk_pos) {
-                    return GameState::WIN_W;
+                    return GameState::WIN_W(WinReason::CHECKMATE);
                 } else {
-                    return GameState::DRAW;
+                    return GameState::DRAW(DrawReason::STALEMATE);
                 }
 
             } else {
                 //white moves
                 if self.board.is_in_check(self.board.wk_pos) {
-                    return GameState::WIN_B;
+                    return GameState::WIN_B(WinReason::CHECKMATE);
                 } else {
-                    return GameState::DRAW;
+                    return GameState::DRAW(DrawReason::STALEMATE);
                 }
             }
         }
+
+        if self.board.has_insufficient_material() {
+            return GameState::DRAW(DrawReason::INSUFFICIENT_MATERIAL);
+        }
+
+        //checked after mate, a mating move ends the game even when it is the 75th
+        if self.repetitions() >= 5 {
+            return GameState::DRAW(DrawReason::FIVEFOLD_REPETITION);
+        }
+        if self.board.halfmove_clock >= 150 {
+            return GameState::DRAW(DrawReason::SEVENTY_FIVE_MOVES);
+        }
         return GameState::ONGOING;
     }
 
@@ -526,377 +1433,629 @@ impl Game {
 }
 
 impl Board {
+    //a board without any pieces on it
+    pub fn empty() -> Board {
+        return Board{pieces:[[0; 6]; 2], occupied:[0; 2], squares:[None; 64], wk_pos:Square::new(4,0), bk_pos:Square::new(4,7), castling:0, ep:None, halfmove_clock:0, hash:0};
+    }
+
     //add back pieces to the vec
     pub fn reset(&mut self) {
-        self.wk_pos=(4,0);
-        self.bk_pos=(4,7);
-        for i in 0..8 {
-            for j in 0..8 {
-                match j {
-                    //white figures
-                    0 => {
-                        let figure = match i {
-                            0 =>  PieceType::ROOK,
-                            1 =>  PieceType::KNIGHT,
-                            2 =>  PieceType::BISHIOP,
-                            3 =>  PieceType::QUEEN,
-                            4 =>  PieceType::KING,
-                            5 =>  PieceType::BISHIOP,
-                            6 =>  PieceType::KNIGHT,
-                            7 =>  PieceType::ROOK,
-                            _ => panic!()
-                        };
-
-                        self.positions[i][j] = Position {
-                            content : PositionContent::PIECE_CONT(Piece { 
-                                variant : figure,
-                                color : Color::W,
-                                has_moved: false
-                            })
-                        };
+        self.setup(STANDARD_BACK_RANK);
+    }
 
-                    },
-                    //pawns white
-                    1 => {
-                        self.positions[i][j] = Position {
-                            content : PositionContent::PIECE_CONT(Piece { 
-                                variant : PieceType::PAWN,
-                                color : Color::W,
-                                has_moved: false
-                            })
-                        };
-                    },
-                    
-                    //pawns black
-                    6 => {
-                        self.positions[i][j] = Position {
-                            content : PositionContent::PIECE_CONT(Piece { 
-                                variant : PieceType::PAWN,
-                                color : Color::B,
-                                has_moved: false
-                            })
-                        };
-                    },
-                    
-                    //black figures
-                    7 => {  
-                        let figure = match i {
-                            0 =>  PieceType::ROOK,
-                            1 =>  PieceType::KNIGHT,
-                            2 =>  PieceType::BISHIOP,
-                            3 =>  PieceType::QUEEN,
-                            4 =>  PieceType::KING,
-                            5 =>  PieceType::BISHIOP,
-                            6 =>  PieceType::KNIGHT,
-                            7 =>  PieceType::ROOK,
-                            _ => panic!()
-                        };
-
-                        self.positions[i][j] = Position {
-                            content : PositionContent::PIECE_CONT(Piece { 
-                                variant : figure,
-                                color : Color::B,
-                                has_moved: false
-                            })
-                        };
-                    },
+    //chess960 start position number index (0-959), see chess960_back_rank
+    pub fn reset_chess960(&mut self, index: usize) {
+        self.setup(chess960_back_rank(index));
+    }
 
-                    _ => {
-                        self.positions[i][j] = Position {
-                            content : PositionContent::NONE
-                        };
-                    }
-                }
+    //start position with the given pieces on the first and last rank, pawns in front of them
+    fn setup(&mut self, back_rank: [PieceType; 8]) {
+        *self = Board::empty();
+        for x in 0..8 {
+            self.set_content(Square::new(x,0), Some(Piece{variant:back_rank[x], color:Color::W}));
+            self.set_content(Square::new(x,1), Some(Piece{variant:PieceType::PAWN, color:Color::W}));
+            self.set_content(Square::new(x,6), Some(Piece{variant:PieceType::PAWN, color:Color::B}));
+            self.set_content(Square::new(x,7), Some(Piece{variant:back_rank[x], color:Color::B}));
+        }
+        //both rooks of each side can castle
+        self.castling = self.pieces[0][PieceType::ROOK.index()] | self.pieces[1][PieceType::ROOK.index()];
+        self.hash = self.compute_hash(1);
+    }
+
+    pub fn is_in_check(&self, king:Square) -> bool {
+        //color of the king
+        let c: Color = self.get_piece(king).color;
+        return self.attackers(king.index(), c.get_inverted(), self.occupancy()) != 0;
+    }
+
+    //pieces of color by attacking the square with index sq, occupied are the pieces sliding pieces can't pass
+    fn attackers(&self, sq:usize, by:Color, occupied:u64) -> u64 {
+        let p = &self.pieces[by.index()];
+        let diagonal = p[PieceType::BISHIOP.index()] | p[PieceType::QUEEN.index()];
+        let straight = p[PieceType::ROOK.index()] | p[PieceType::QUEEN.index()];
+        //a pawn attacks sq from where a pawn of the other color on sq would attack
+        return (ATTACKS.knight[sq] & p[PieceType::KNIGHT.index()])
+            | (ATTACKS.king[sq] & p[PieceType::KING.index()])
+            | (ATTACKS.pawn[by.get_inverted().index()][sq] & p[PieceType::PAWN.index()])
+            | (bishop_attacks(sq, occupied) & diagonal)
+            | (rook_attacks(sq, occupied) & straight);
+    }
+
+    //pieces of color by that attack sq, as a bitboard (see squares_of)
+    pub fn attackers_of(&self, sq:Square, by:Color) -> u64 {
+        return self.attackers(sq.index(), by, self.occupancy());
+    }
+
+    //every square a piece of color by attacks, whatever stands there
+    pub fn attacked_by(&self, by:Color) -> u64 {
+        let p = &self.pieces[by.index()];
+        let occupied = self.occupancy();
+        let mut attacked = 0;
+        for sq in bits(p[PieceType::PAWN.index()]) {
+            attacked |= ATTACKS.pawn[by.index()][sq];
+        }
+        for sq in bits(p[PieceType::KNIGHT.index()]) {
+            attacked |= ATTACKS.knight[sq];
+        }
+        for sq in bits(p[PieceType::BISHIOP.index()] | p[PieceType::QUEEN.index()]) {
+            attacked |= bishop_attacks(sq, occupied);
+        }
+        for sq in bits(p[PieceType::ROOK.index()] | p[PieceType::QUEEN.index()]) {
+            attacked |= rook_attacks(sq, occupied);
+        }
+        for sq in bits(p[PieceType::KING.index()]) {
+            attacked |= ATTACKS.king[sq];
+        }
+        return attacked;
+    }
+
+    //a piece of color by could take back on sq, the piece on sq itself does not count
+    pub fn is_defended(&self, sq:Square, by:Color) -> bool {
+        return self.attackers_of(sq, by) != 0;
+    }
+
+    //static exchange evaluation: what the side making mv wins in centipawns if both sides keep taking back
+    //on the destination with their least valuable piece, and stop once that would lose material.
+    //sliders lined up behind the capturing pieces join in as they are uncovered
+    pub fn see(&self, mv:Move) -> i32 {
+        if mv.is_castle() {
+            return 0;
+        }
+        let to = mv.to.index();
+        let mover = self.get_piece(mv.from);
+        let mut occupied = self.occupancy() & !(1u64 << mv.from.index());
+        //gain[n] is what the side making the nth capture wins if the exchange stops there
+        let mut gain = [0i32; 32];
+        gain[0] = match self.squares[to] {
+            Some(p) => p.variant.value(),
+            None if mv.is_en_passant() => PieceType::PAWN.value(),
+            None => 0
+        };
+        if mv.is_en_passant() {
+            occupied &= !(1u64 << Square::new(mv.to.x, mv.from.y).index());
+        }
+        //the piece standing on the square, which the next capture takes
+        let mut on_square = mover.variant;
+        if let Some(promotion) = mv.promotion {
+            gain[0] += promotion.value() - PieceType::PAWN.value();
+            on_square = promotion;
+        }
+
+        let mut side = mover.color.get_inverted();
+        let mut n = 0;
+        loop {
+            let attackers = self.attackers(to, side, occupied) & occupied;
+            let Some((from, variant)) = self.least_valuable(attackers, side) else {break};
+            //the king only takes back if nothing can take it in turn
+            if variant == PieceType::KING && self.attackers(to, side.get_inverted(), occupied & !(1u64 << from)) & occupied != 0 {
+                break;
+            }
+            n += 1;
+            gain[n] = on_square.value() - gain[n-1];
+            on_square = variant;
+            occupied &= !(1u64 << from);
+            side = side.get_inverted();
+        }
+        //either side may stop taking back when that is better for it
+        while n > 0 {
+            gain[n-1] = -(-gain[n-1]).max(gain[n]);
+            n -= 1;
+        }
+        return gain[0];
+    }
+
+    //square and type of the cheapest piece of color among attackers
+    fn least_valuable(&self, attackers:u64, color:Color) -> Option<(usize, PieceType)> {
+        for variant in [PieceType::PAWN, PieceType::KNIGHT, PieceType::BISHIOP, PieceType::ROOK, PieceType::QUEEN, PieceType::KING] {
+            let pieces = attackers & self.pieces[color.index()][variant.index()];
+            if pieces != 0 {
+                return Some((pieces.trailing_zeros() as usize, variant));
             }
         }
-        
-      
+        return None;
+    }
+
+    //the piece on sq can be won, some capture of it gains material for the other side
+    pub fn is_hanging(&self, sq:Square) -> bool {
+        let Some(piece) = self.piece_at(sq) else {return false};
+        let them = piece.color.get_inverted();
+        return bits(self.attackers_of(sq, them)).any(|from| {
+            let capture = Move{from:Square::from_index(from), to:sq, promotion:None, flags:Move::CAPTURE};
+            return self.see(capture) > 0;
+        });
     }
     
-    // fn print_state(&mut self) {
-    //     let nums = vec!["1","2","3","4","5","6","7","8"];
-    //     let lettrs = vec!["a","b","c","d","e","f","g","h"];
-        
-    //     for i in (0..8).rev() {
-    //         print!("{} ",nums[i]);
-    //         for j in 0..8 {
-    //                             let c_piece = self.positions[j][i].content.print();
-    //             let mut prt_str:String = c_piece.to_string();
-    //             prt_str.push(' ');
-    //             match (j+i)%2 {
-    //                 0 => print!("{}",prt_str.on_color("cyan")),
-    //                 1 => print!("{}",prt_str.on_color("white")),
-    //                 _ => panic!()
-    //             }
-                
-    //         }
-    //         println!("");
-    //     }
-    //     print!(" ");
-    //     for lettr in lettrs{
-    //         print!(" {}",lettr);
-    //     }
-    //     println!();
-    // }
-    
-    pub fn is_in_check(&mut self,king:(usize,usize))->bool{
-        //color of the king
-        let c: Color = self.positions[king.0][king.1].content.get_piece().color;
+    //helper function for my sanity, can panic
+    pub fn get_piece(&self, from:Square)->Piece{
+        return self.squares[from.index()].expect("NO PIECES PRESENT");
+    }
+
+    //same as get_piece, but None for an empty square
+    pub fn piece_at(&self, sq:Square) -> Option<Piece> {
+        return self.squares[sq.index()];
+    }
 
-        //diagonal checks
-        let mut pc: Piece = Piece{variant:PieceType::BISHIOP,color:c.get_inverted(),has_moved:true};
-        let paths: Vec<(i16, i16)> = pc.get_paths();
+    //bitboard of all pieces of both colors
+    pub fn occupancy(&self) -> u64 {
+        return self.occupied[0] | self.occupied[1];
+    }
 
-        for b_path in paths {
-            let res = self.trace_ray(c, king, b_path, 8);
-            //if not empty and queen or bishop (enemy) then the king must be in check
-            if !res.is_empty() && (self.get_piece(res[0]).variant == PieceType::QUEEN || self.get_piece(res[0]).variant == PieceType::BISHIOP)   {
-                return true
+    //checks that the move does not leave the own king attacked, by looking at the occupancy after the move instead of playing it
+    pub fn is_legal(&self, mv:Move,t_count:usize)-> bool{
+        let c = self.get_piece(mv.from).color;
+        let them = c.get_inverted();
+        let from = 1u64 << mv.from.index();
+        let to = 1u64 << mv.to.index();
+        let mut king = if c == Color::B {self.bk_pos} else {self.wk_pos}.index();
+        let mut captured = 0;
+        let mut occupied = self.occupancy();
+
+        if mv.is_castle() {
+            let (king_to, rook, rook_to) = self.castle_squares(mv);
+            occupied = (occupied & !from & !(1u64 << rook.index())) | (1u64 << king_to.index()) | (1u64 << rook_to.index());
+            king = king_to.index();
+        } else {
+            if mv.from.index() == king {
+                king = mv.to.index();
             }
-        }        
+            //the pawn taken en passant stands next to the moving one
+            captured = if mv.is_en_passant() {1u64 << Square::new(mv.to.x, mv.from.y).index()} else {to & self.occupied[them.index()]};
+            occupied = (occupied & !from & !captured) | to;
+        }
+
+        //check legality of the move by checking for a check
+        return self.attackers(king, them, occupied) & !captured == 0;
+    }
 
-        //horizontal checks
-        pc.variant = PieceType::ROOK;
-        let paths: Vec<(i16, i16)> = pc.get_paths();
+    //helper functions
+    fn is_empty(&self, coords:Square)-> bool{
+        return self.squares[coords.index()].is_none();
+    }
 
-        for r_path in paths {
-            let res = self.trace_ray(c, king, r_path, 8);
-            //if not empty and queen or rook (enemy) then the king must be in check
-            if !res.is_empty() && (self.get_piece(res[0]).variant == PieceType::QUEEN || self.get_piece(res[0]).variant == PieceType::ROOK)   {
-                return true
-            }
+    //zobrist key computed from scratch, force_move keeps it up to date afterwards
+    pub fn compute_hash(&self, t_count:usize) -> u64 {
+        let mut hash = 0;
+        for sq in bits(self.occupancy()) {
+            hash ^= zobrist_piece(self.squares[sq].unwrap(), Square::from_index(sq));
         }
+        return hash ^ self.state_key(t_count);
+    }
 
-        //knight checks
-        pc.variant = PieceType::KNIGHT;
-        let paths: Vec<(i16, i16)> = pc.get_paths();
+    //the part of the key that is not about pieces: side to move, castling rights and en passant file.
+    //the file only counts when a pawn stands next to the one that double moved, otherwise the positions are the same
+    fn state_key(&self, t_count:usize) -> u64 {
+        let mut key = if t_count % 2 == 0 {ZOBRIST.black_to_move} else {0};
+        for (n, (color, kingside)) in [(Color::W,true),(Color::W,false),(Color::B,true),(Color::B,false)].into_iter().enumerate() {
+            if self.castling_rook(color, kingside).is_some() {
+                key ^= ZOBRIST.castling[n];
+            }
+        }
+        if let Some(target) = self.ep {
+            let capturer = if t_count % 2 == 1 {Color::W} else {Color::B};
+            let pawns = self.pieces[capturer.index()][PieceType::PAWN.index()];
+            if ATTACKS.pawn[capturer.get_inverted().index()][target.index()] & pawns != 0 {
+                key ^= ZOBRIST.en_passant[target.x];
+            }
+        }
+        return key;
+    }
 
-        for r_path in paths {
-            let res = self.trace_ray(c, king, r_path, 1);
-            //if not empty and knight (enemy) then the king must be in check
-            if !res.is_empty() && self.get_piece(res[0]).variant == PieceType::KNIGHT   {
-                return true
+    //replaces what stands on sq and updates the bitboards and the hash accordingly
+    fn set_content(&mut self, sq:Square, content:Option<Piece>) {
+        let bit = 1u64 << sq.index();
+        if let Some(p) = self.squares[sq.index()] {
+            self.hash ^= zobrist_piece(p, sq);
+            self.pieces[p.color.index()][p.variant.index()] &= !bit;
+            self.occupied[p.color.index()] &= !bit;
+        }
+        if let Some(p) = content {
+            self.hash ^= zobrist_piece(p, sq);
+            self.pieces[p.color.index()][p.variant.index()] |= bit;
+            self.occupied[p.color.index()] |= bit;
+            if p.variant == PieceType::KING {
+                if p.color == Color::W {
+                    self.wk_pos = sq;
+                } else {
+                    self.bk_pos = sq;
+                }
             }
         }
+        self.squares[sq.index()] = content;
+    }
+
+    //where the king lands, where the rook starts and where it lands for a castling move
+    fn castle_squares(&self, mv:Move) -> (Square, Square, Square) {
+        let color = self.get_piece(mv.from).color;
+        //to is the rook in chess960 and the king's destination otherwise, either way it is on the side castled to
+        let kingside = mv.to.x > mv.from.x;
+        let rook_x = self.castling_rook(color, kingside).expect("castling without the right to");
+        let (king_x, rook_to) = if kingside {(6,5)} else {(2,3)};
+        return (Square::new(king_x,mv.from.y), Square::new(rook_x,mv.from.y), Square::new(rook_to,mv.from.y));
+    }
 
-        //king checks
-        pc.variant = PieceType::KING;
-        let paths: Vec<(i16, i16)> = pc.get_paths();
+    //moves pieces, it is assumed that the move is viable but check has to be verified afterwards
+    fn force_move(&mut self,mv:Move,t_count:usize) -> bool{
+        let (from, to) = (mv.from, mv.to);
+        //en passant captures land on an empty square, chess960 castling lands on the own rook
+        let has_captured:bool = (!self.is_empty(to) && !mv.is_castle()) || mv.is_en_passant();
+        //castling and en passant rights of the position being left
+        self.hash ^= self.state_key(t_count);
 
-        for r_path in paths {
-            let res = self.trace_ray(c, king, r_path, 1);
-            //if not empty and knight (enemy) then the king must be in check
-            if !res.is_empty() &&  self.get_piece(res[0]).variant == PieceType::KING   {
-                return true
+        //will panic if trying to move NONE
+        let moved_piece = self.get_piece(from);
+
+        if mv.is_castle() {
+            let (king_to, rook, rook_to) = self.castle_squares(mv);
+            //both are taken off first, in chess960 they can land on each other's squares
+            self.set_content(from, None);
+            self.set_content(rook, None);
+            self.set_content(king_to, Some(moved_piece));
+            self.set_content(rook_to, Some(Piece{variant:PieceType::ROOK,color:moved_piece.color}));
+        } else {
+            //eliminates the pawn that was to the side of the capturing one when starting the move
+            if mv.is_en_passant() {
+                self.set_content(Square::new(to.x,from.y), None);
             }
+            let placed = match mv.promotion {
+                Some(promotion) => Piece{variant:promotion,color:moved_piece.color},
+                None => moved_piece
+            };
+            self.set_content(from, None);
+            self.set_content(to, Some(placed));
         }
 
-        //check for pawns
-        //if white then the pawn must be 1 below the king, else 1 above
-        let dir = if c==Color::B {-1} else {1};
+        //the clock is reset by pawn moves and captures
+        if has_captured || moved_piece.variant == PieceType::PAWN {
+            self.halfmove_clock = 0;
+        } else {
+            self.halfmove_clock += 1;
+        }
 
-        //check left/right side of king
-        for n in vec![-1,1] {
-            let c_coord: (i32, i32) = ((king.0 as i32 + n),(king.1 as i32 + dir));
-            //check if in bounds of board
-            if c_coord.0 >= 0 && c_coord.1 >= 0 && c_coord.0 <= 7 && c_coord.1 <= 7 {
-                // if both non-empty,enemy and PAWN then king is in check
-                if !self.is_empty((c_coord.0 as usize, c_coord.1 as usize)) && self.get_piece((c_coord.0 as usize, c_coord.1 as usize)).color == c.get_inverted()  && self.get_piece((c_coord.0 as usize, c_coord.1 as usize)).variant == PieceType::PAWN{
-                    return true;
-                }
-            }
+        //moved two spaces, keep track to see if en passant is viable next turn
+        self.ep = None;
+        if moved_piece.variant == PieceType::PAWN && from.y.abs_diff(to.y) == 2 {
+            self.ep = Some(Square::new(from.x, (from.y + to.y) / 2));
+        }
+        //a king move gives up both castling rights, a rook that moves or is taken its own
+        if moved_piece.variant == PieceType::KING {
+            self.castling &= !(0xFFu64 << (56 * moved_piece.color.index()));
         }
+        self.castling &= !((1u64 << from.index()) | (1u64 << to.index()));
 
-            
-        return false;
-    }
-    
-    //helper function for my sanity, can panic
-    pub fn get_piece(&mut self, from:(usize,usize))->Piece{
-        return self.positions[from.0][from.1].content.get_piece();
+        self.hash ^= self.state_key(t_count+1);
+        return has_captured;
     }
 
-    pub fn is_legal(&mut self, from:(usize,usize),to:(usize,usize),promotion:PieceType,t_count:usize)-> bool{
-        let pos_copy: [[Position; 8]; 8] = self.positions;
-        let mut cpy_board: Board = Board {positions:pos_copy, bk_pos:self.bk_pos,wk_pos:self.wk_pos,last_pass:self.last_pass};
-        let c = self.positions[from.0][from.1].content.get_piece().color;
-        
-        cpy_board.force_move(from, to, promotion,t_count);
+    //plays a legal move and returns what unmake_move needs to take it back
+    pub fn make_move(&mut self, mv:Move, t_count:usize) -> BoardUndo {
+        let captured = if mv.is_en_passant() {
+            self.squares[Square::new(mv.to.x, mv.from.y).index()]
+        } else if mv.is_castle() {
+            None
+        } else {
+            self.squares[mv.to.index()]
+        };
+        let undo = BoardUndo{mv:mv, captured:captured, castling:self.castling, ep:self.ep, halfmove_clock:self.halfmove_clock, hash:self.hash};
+        self.force_move(mv, t_count);
+        return undo;
+    }
 
-        //check legality of the move by checking for a check
+    //takes back the move make_move returned undo for, it has to be the last move made on the board
+    pub fn unmake_move(&mut self, undo:BoardUndo) {
+        let (mv, from, to) = (undo.mv, undo.mv.from, undo.mv.to);
+        //castle_squares looks up the rook with the rights from before the move
+        self.castling = undo.castling;
+
+        if mv.is_castle() {
+            let (king_x, rook_x) = if to.x > from.x {(6,5)} else {(2,3)};
+            let king = self.get_piece(Square::new(king_x,from.y));
+            self.set_content(Square::new(king_x,from.y), None);
+            self.set_content(Square::new(rook_x,from.y), None);
+            //castle_squares finds the rook from the king's starting square
+            self.set_content(from, Some(king));
+            let (_, rook, _) = self.castle_squares(mv);
+            self.set_content(rook, Some(Piece{variant:PieceType::ROOK,color:king.color}));
+        } else {
+            let moved = self.get_piece(to);
+            let restored = if mv.promotion.is_some() {Piece{variant:PieceType::PAWN,color:moved.color}} else {moved};
+            if mv.is_en_passant() {
+                self.set_content(to, None);
+                self.set_content(Square::new(to.x,from.y), undo.captured);
+            } else {
+                self.set_content(to, undo.captured);
+            }
+            self.set_content(from, Some(restored));
+        }
 
-        return !cpy_board.is_in_check(if c == Color::B {cpy_board.bk_pos} else {cpy_board.wk_pos});
+        self.ep = undo.ep;
+        self.halfmove_clock = undo.halfmove_clock;
+        self.hash = undo.hash;
     }
 
-    //helper functions
-    fn is_empty(&mut self, coords:(usize,usize))-> bool{
-        let pos: Position = self.positions[coords.0][coords.1];
-        match pos.content {
-            PositionContent::NONE => return true,
-            _ => return false
+    //moves the side to move could make if it did not have to care about its own king, for the pieces on from_mask.
+    //chess960 castling is written as the king taking its own rook
+    fn pseudo_legal_moves(&self, t_count:usize, from_mask:u64, chess960:bool, moves:&mut Vec<Move>) {
+        let us = if t_count % 2 == 1 {Color::W} else {Color::B};
+        let them = us.get_inverted();
+        let own = self.occupied[us.index()];
+        let enemy = self.occupied[them.index()];
+        let all = own | enemy;
+
+        for from in bits(own & from_mask) {
+            let piece = self.squares[from].unwrap();
+            let targets = match piece.variant {
+                PieceType::PAWN => {
+                    self.pawn_moves(from, us, all, enemy, !0, true, moves);
+                    continue;
+                },
+                PieceType::KNIGHT => ATTACKS.knight[from],
+                PieceType::BISHIOP => bishop_attacks(from, all),
+                PieceType::ROOK => rook_attacks(from, all),
+                PieceType::QUEEN => bishop_attacks(from, all) | rook_attacks(from, all),
+                PieceType::KING => {
+                    self.castling_moves(from, us, all, chess960, moves);
+                    ATTACKS.king[from]
+                },
+                PieceType::NONE => 0
+            };
+            for to in bits(targets & !own) {
+                let flags = if enemy & (1u64 << to) != 0 {Move::CAPTURE} else {Move::QUIET};
+                moves.push(Move{from:Square::from_index(from), to:Square::from_index(to), promotion:None, flags:flags});
+            }
         }
     }
 
-    fn is_enemy(&mut self, coords:(usize,usize),team_col:Color)-> bool{
-        let pos: Position = self.positions[coords.0][coords.1];
-        match pos.content {
-            PositionContent::NONE => panic!("This is not meant to be used this way"),
-            PositionContent::PIECE_CONT(p) => {
-                return p.color!=team_col;
+    //pawn moves landing on allowed, without quiet the only pushes are promotions.
+    //en passant is left out of allowed since the pawn it takes is not on the destination
+    fn pawn_moves(&self, from:usize, us:Color, all:u64, enemy:u64, allowed:u64, quiet:bool, moves:&mut Vec<Move>) {
+        let sq = Square::from_index(from);
+        //pawns never stand on the last rank, so one step forward stays on the board
+        let (one, start_rank) = if us == Color::W {(from + 8, 1)} else {(from - 8, 6)};
+        let promotes = one / 8 == 0 || one / 8 == 7;
+        if all & (1u64 << one) == 0 && (quiet || promotes) {
+            if allowed & (1u64 << one) != 0 {
+                push_pawn_move(sq, one, Move::QUIET, moves);
+            }
+            //can move forward, check if double move is possible
+            if sq.y == start_rank {
+                let two = if us == Color::W {one + 8} else {one - 8};
+                if all & (1u64 << two) == 0 && allowed & (1u64 << two) != 0 {
+                    moves.push(Move{from:sq, to:Square::from_index(two), promotion:None, flags:Move::DOUBLE_PUSH});
+                }
+            }
+        }
+        for to in bits(ATTACKS.pawn[us.index()][from] & enemy & allowed) {
+            push_pawn_move(sq, to, Move::CAPTURE, moves);
+        }
+        if let Some(target) = self.ep {
+            if ATTACKS.pawn[us.index()][from] & (1u64 << target.index()) != 0 {
+                moves.push(Move{from:sq, to:target, promotion:None, flags:Move::CAPTURE | Move::EN_PASSANT});
             }
         }
     }
-    //moves pieces, it is assumed that the move is viable but check has to be verified afterwards
-    fn force_move(&mut self,from:(usize,usize),to:(usize,usize),promotion:PieceType,t_count:usize) -> bool{
-        let has_captured:bool = self.is_empty(to);
-
-        //will panic if trying to move NONE
-        let moved_piece = self.positions[from.0][from.1].content.get_piece();
-
-        match moved_piece.variant {
-            PieceType::KING => {
-                //castle attempt
-                if (to.0 as i16 - from.0 as i16).abs() > 1 {
-                    self.positions[to.0][to.1].content = PositionContent::PIECE_CONT(Piece{variant:moved_piece.variant,color:moved_piece.color,has_moved:true});
-                    //left or right
-                    if to.0 > from.0 {
-                        //right,move rook to the left of king
-                        self.positions[to.0-1][to.1].content = PositionContent::PIECE_CONT(Piece{variant:PieceType::ROOK,color:moved_piece.color,has_moved:true});
-                        self.positions[7][to.1].content = PositionContent::NONE;  
-                        self.positions[from.0][from.1].content = PositionContent::NONE;
-
-                    } else {
-                        //left
-                        self.positions[to.0+1][to.1].content = PositionContent::PIECE_CONT(Piece{variant:PieceType::ROOK,color:moved_piece.color,has_moved:true});
-                        self.positions[0][to.1].content = PositionContent::NONE; 
-                        self.positions[from.0][from.1].content = PositionContent::NONE; 
-
-                    }
 
-                } else {
-                    self.positions[to.0][to.1].content = PositionContent::PIECE_CONT(Piece{variant:moved_piece.variant,color:moved_piece.color,has_moved:true});
-                    self.positions[from.0][from.1].content = PositionContent::NONE;  
+    //legal moves of the side to move for the pieces on from_mask. checkers and pins are found once,
+    //in double check only the king moves. castling and en passant are rare enough to go through is_legal
+    fn generate(&self, t_count:usize, from_mask:u64, chess960:bool, captures_only:bool, moves:&mut Vec<Move>) {
+        let us = if t_count % 2 == 1 {Color::W} else {Color::B};
+        let them = us.get_inverted();
+        let own = self.occupied[us.index()];
+        let enemy = self.occupied[them.index()];
+        let all = own | enemy;
+        let king = if us == Color::W {self.wk_pos} else {self.bk_pos}.index();
+        let checkers = self.attackers(king, them, all);
+        let landing = if captures_only {enemy} else {!own};
+
+        if from_mask & (1u64 << king) != 0 {
+            //without the king in the way, so it can't step back along the ray of a slider checking it
+            let behind_king = all & !(1u64 << king);
+            for to in bits(ATTACKS.king[king] & landing) {
+                if self.attackers(to, them, behind_king) == 0 {
+                    let flags = if enemy & (1u64 << to) != 0 {Move::CAPTURE} else {Move::QUIET};
+                    moves.push(Move{from:Square::from_index(king), to:Square::from_index(to), promotion:None, flags:flags});
                 }
-                //update king tracker
-                if self.get_piece(to).color == Color::W {
-                    self.wk_pos = (to.0,to.1);
-                } else {
-                    self.bk_pos = (to.0,to.1);
+            }
+            if checkers == 0 && !captures_only {
+                let start = moves.len();
+                self.castling_moves(king, us, all, chess960, moves);
+                let mut n = start;
+                while n < moves.len() {
+                    if self.is_legal(moves[n], t_count) {n += 1} else {moves.swap_remove(n);}
                 }
-            },
-            PieceType::PAWN => {
-                //attempts to move diagonally to an empty square => NPASS
-                if from.0 != to.0 && self.positions[to.0][to.1].content == PositionContent::NONE {
-                    self.positions[to.0][to.1].content = PositionContent::PIECE_CONT(Piece{variant:moved_piece.variant,color:moved_piece.color,has_moved:true});
-                    //eliminates the piece (PAWN) that was to its side when starting the move
-                    self.positions[to.0][from.1].content = PositionContent::NONE;
-                    self.positions[from.0][from.1].content = PositionContent::NONE;
-
-                    //goes to the end of the board in y-axis => PROMO
-                } else if to.1 == 0 || to.1 == 7 {
-                    if promotion == PieceType::NONE {panic!()}
-
-                    self.positions[to.0][to.1].content = PositionContent::PIECE_CONT(Piece{variant:promotion,color:moved_piece.color,has_moved:true});
-                    self.positions[from.0][from.1].content = PositionContent::NONE;
-                    //normal movement
-                } else {
-                    self.positions[to.0][to.1].content = PositionContent::PIECE_CONT(Piece{variant:moved_piece.variant,color:moved_piece.color,has_moved:true});
-                    self.positions[from.0][from.1].content = PositionContent::NONE;
+            }
+        }
+        if checkers.count_ones() > 1 {
+            return;
+        }
 
-                    //moved two spaces, keep track to see if NPASS is viable next turn
-                    if from.1.abs_diff(to.1) > 1 {
-                        self.last_pass = (to.0,to.1,t_count);
-                    }
+        //out of a single check the other pieces have to take the checker or step in between
+        let mut check_mask = !0u64;
+        if checkers != 0 {
+            check_mask = checkers;
+            for dir in 0..8 {
+                let ray = ray_attacks(king, dir, all);
+                if ray & checkers != 0 {
+                    check_mask |= ray;
                 }
-            },
-            _ => {
-                self.positions[to.0][to.1].content = PositionContent::PIECE_CONT(Piece{variant:moved_piece.variant,color:moved_piece.color,has_moved:true});
-                self.positions[from.0][from.1].content = PositionContent::NONE;  
-            },
+            }
         }
-        return has_captured;
-    }
+        let pinned = self.pinned(king, us, all);
 
-    fn traverse_path(&mut self, by:Piece, from:(usize,usize),path:(i16,i16), length:usize) -> Vec<(usize,usize)>{     
-        //list of all viable moves
-        let mut possible_moves:Vec<(usize,usize)> = Vec::new(); 
+        for from in bits(own & from_mask & !(1u64 << king)) {
+            let piece = self.squares[from].unwrap();
+            let mut allowed = check_mask;
+            if pinned & (1u64 << from) != 0 {
+                allowed &= pin_ray(king, from);
+            }
+            let targets = match piece.variant {
+                PieceType::PAWN => {
+                    self.pawn_moves(from, us, all, enemy, allowed, !captures_only, moves);
+                    //the en passant capture was added without looking at allowed
+                    if let Some(last) = moves.last() {
+                        if last.is_en_passant() && last.from.index() == from && !self.is_legal(*last, t_count) {
+                            moves.pop();
+                        }
+                    }
+                    continue;
+                },
+                PieceType::KNIGHT => ATTACKS.knight[from],
+                PieceType::BISHIOP => bishop_attacks(from, all),
+                PieceType::ROOK => rook_attacks(from, all),
+                PieceType::QUEEN => bishop_attacks(from, all) | rook_attacks(from, all),
+                _ => 0
+            };
+            for to in bits(targets & landing & allowed) {
+                let flags = if enemy & (1u64 << to) != 0 {Move::CAPTURE} else {Move::QUIET};
+                moves.push(Move{from:Square::from_index(from), to:Square::from_index(to), promotion:None, flags:flags});
+            }
+        }
+    }
 
-        
+    //all legal moves of the side to move
+    pub fn generate_moves(&self, t_count:usize, chess960:bool, moves:&mut Vec<Move>) {
+        self.generate(t_count, !0, chess960, false, moves);
+    }
 
-        let mut curr_x =from.0 as i16;
-        let mut curr_y =from.1 as i16;
-        let mut p_travelled =0;
+    //legal captures and promotions of the side to move, for quiescence search
+    pub fn generate_captures(&self, t_count:usize, moves:&mut Vec<Move>) {
+        self.generate(t_count, !0, false, true, moves);
+    }
 
-        while p_travelled<length{
-            curr_x += path.0;
-            curr_y += path.1;
-            //out of bounds on x
-            if curr_x<0 ||  curr_x>7 {
-                break;
+    //own pieces that are the only thing between the king and an enemy rook, bishop or queen
+    fn pinned(&self, king:usize, us:Color, all:u64) -> u64 {
+        let p = &self.pieces[us.get_inverted().index()];
+        let diagonal = p[PieceType::BISHIOP.index()] | p[PieceType::QUEEN.index()];
+        let straight = p[PieceType::ROOK.index()] | p[PieceType::QUEEN.index()];
+        let mut pinned = 0;
+        for dir in 0..8 {
+            let sliders = if dir % 4 < 2 {straight} else {diagonal};
+            if ATTACKS.rays[dir][king] & sliders == 0 {
+                continue;
             }
-            //out of bounds on y
-            if curr_y<0 || curr_y>7 {
-                break;
+            let blocker = ray_attacks(king, dir, all) & self.occupied[us.index()];
+            if blocker != 0 && ray_attacks(king, dir, all & !blocker) & sliders != 0 {
+                pinned |= blocker;
             }
+        }
+        return pinned;
+    }
 
-            if self.is_empty((curr_x as usize,curr_y as usize)){
-                possible_moves.push((curr_x as usize,curr_y as usize));
-            } else {
-                if self.is_enemy((curr_x as usize,curr_y as usize),by.color){
-                    possible_moves.push((curr_x as usize,curr_y as usize));
-                }
-                break;
-                
+    //castle, the king ends on the g or c file with the rook next to it on the inside, wherever both started
+    fn castling_moves(&self, from:usize, us:Color, all:u64, chess960:bool, moves:&mut Vec<Move>) {
+        let king = Square::from_index(from);
+        if self.castling == 0 {
+            return;
+        }
+        let attacked = self.attacked_by(us.get_inverted());
+        if attacked & (1u64 << from) != 0 {
+            return;
+        }
+        for kingside in [true, false] {
+            let Some(rook_x) = self.castling_rook(us, kingside) else {continue};
+            let (king_to, rook_to) = if kingside {(6,5)} else {(2,3)};
+            //every square either piece crosses or lands on is empty, apart from the king and the rook themselves
+            let lo = king.x.min(rook_x).min(king_to).min(rook_to);
+            let hi = king.x.max(rook_x).max(king_to).max(rook_to);
+            let span = ((0xFFu64 >> (7 - hi + lo)) << lo) << (king.y * 8);
+            let pieces = (1u64 << from) | (1u64 << Square::new(rook_x,king.y).index());
+            if span & all & !pieces != 0 {
+                continue;
             }
-            p_travelled +=1;
-
+            //the king cannot cross a square that would give a check, landing in check is left to is_legal
+            let crossed = if king_to > king.x {king.x+1..king_to} else {king_to+1..king.x};
+            if crossed.into_iter().any(|x| attacked & (1u64 << Square::new(x,king.y).index()) != 0) {
+                continue;
+            }
+            //chess960 castling takes the own rook, so it can't be mixed up with a king move to the same square
+            let to_x = if chess960 {rook_x} else {king_to};
+            moves.push(Move{from:king, to:Square::new(to_x,king.y), promotion:None, flags:Move::CASTLE});
         }
-        return possible_moves;
     }
 
-    fn trace_ray(&mut self, by:Color, from:(usize,usize),path:(i16,i16),length:usize) -> Vec<(usize,usize)>{
-
-        //first obstacle hit by ray
-        let mut first_obstacle:Vec<(usize,usize)> = Vec::new(); 
+    //neither side can ever mate: bare kings, a single minor piece, or only bishops that all stand on one square colour
+    pub fn has_insufficient_material(&self) -> bool {
+        let [w, b] = &self.pieces;
+        let majors_or_pawns = [PieceType::PAWN, PieceType::ROOK, PieceType::QUEEN].iter().any(|t| (w[t.index()] | b[t.index()]) != 0);
+        if majors_or_pawns {
+            return false;
+        }
+        let knights = w[PieceType::KNIGHT.index()] | b[PieceType::KNIGHT.index()];
+        let bishops = w[PieceType::BISHIOP.index()] | b[PieceType::BISHIOP.index()];
+        if (knights | bishops).count_ones() <= 1 {
+            return true;
+        }
+        return knights == 0 && (bishops & DARK_SQUARES == 0 || bishops & !DARK_SQUARES == 0);
+    }
 
-        
+    pub fn has_only_king(&self, color:Color) -> bool {
+        return self.occupied[color.index()] == self.pieces[color.index()][PieceType::KING.index()];
+    }
 
-        let mut curr_x =from.0 as i16;
-        let mut curr_y =from.1 as i16;
-        let mut p_travelled =0;
+    //file of the rook color can still castle with on the king or queen side.
+    //the king and rooks may stand anywhere on the back rank, as in chess960
+    pub fn castling_rook(&self, color:Color, kingside:bool) -> Option<usize> {
+        let y = if color == Color::W {0} else {7};
+        let king = if color == Color::W {self.wk_pos} else {self.bk_pos};
+        if king.y != y {
+            return None;
+        }
+        let rights = (self.castling >> (y * 8)) & 0xFF;
+        let mut files: Vec<usize> = if kingside {(king.x+1..8).collect()} else {(0..king.x).rev().collect()};
+        return files.into_iter().find(|x| rights & (1 << x) != 0);
+    }
 
-        while p_travelled<length{
-            curr_y += path.0;
-            curr_x += path.1;
-            //out of bounds on x
-            if curr_x<0 ||  curr_x>7 {
-                break;
+    //first field of a FEN record, rank 8 first
+    pub fn placement_fen(&self) -> String {
+        let mut fen = String::new();
+        for y in (0..8).rev() {
+            let mut empty = 0;
+            for x in 0..8 {
+                match self.piece_at(Square::new(x,y)) {
+                    None => empty += 1,
+                    Some(p) => {
+                        if empty > 0 {
+                            fen.push_str(&empty.to_string());
+                            empty = 0;
+                        }
+                        fen.push(demap_fen(Some(p)));
+                    }
+                }
             }
-            //out of bounds on y
-            if curr_y<0 || curr_y>7 {
-                break;
+            if empty > 0 {
+                fen.push_str(&empty.to_string());
             }
-
-            //if the field is not empty and not an ally then we have hit an enemy
-            if !self.is_empty((curr_x as usize,curr_y as usize)){
-                if self.is_enemy((curr_x as usize,curr_y as usize),by){
-                    first_obstacle.push((curr_x as usize,curr_y as usize));
-                }
-                break;
+            if y > 0 {
+                fen.push('/');
             }
-            
-            p_travelled +=1;
         }
-        return first_obstacle;
+        return fen;
     }
 
-    
-    fn to_string(&mut self) -> String {
+    fn to_string(&self) -> String {
         let mut str = String::new();
         for i in 0..8 {
             for j in 0..8 {
-                let pos = self.positions[j][i];
-                let c:char = demap_fen(pos);
+                let c:char = demap_fen(self.piece_at(Square::new(j,i)));
                 str.push(c);
             }
             str.push('/');
@@ -907,21 +2066,86 @@ impl Board {
 
 }
 
+//the squares set in a bitboard, a1 first
+pub fn squares_of(bitboard:u64) -> Vec<Square> {
+    return bits(bitboard).map(Square::from_index).collect();
+}
+
+//the ray from the king through a pinned piece, which the piece can't leave
+fn pin_ray(king:usize, pinned:usize) -> u64 {
+    for dir in 0..8 {
+        if ATTACKS.rays[dir][king] & (1u64 << pinned) != 0 {
+            return ATTACKS.rays[dir][king];
+        }
+    }
+    return 0;
+}
+
+//adds the move, or one move for each piece if the pawn reaches the last rank
+fn push_pawn_move(from:Square, to:usize, flags:u8, moves:&mut Vec<Move>) {
+    let to = Square::from_index(to);
+    if to.y == 0 || to.y == 7 {
+        for promotion in [PieceType::QUEEN, PieceType::ROOK, PieceType::BISHIOP, PieceType::KNIGHT] {
+            moves.push(Move{from:from, to:to, promotion:Some(promotion), flags:flags | Move::PROMOTION});
+        }
+    } else {
+        moves.push(Move{from:from, to:to, promotion:None, flags:flags});
+    }
+}
+
 //creates the game object that is used to interact with the game
 pub fn start() -> Game {
-    let mut g:Game = Game{repeat_map:HashMap::new(),board:Board{last_pass:(0,0,0),positions: [[Position{content:PositionContent::NONE}; 8]; 8],wk_pos:(4,0),bk_pos:(4,7)},
-    old_state:Board{last_pass:(0,0,0),positions: [[Position{content:PositionContent::NONE}; 8]; 8],wk_pos:(4,0),bk_pos:(4,7)},
-    turn_counter:1,last_capture:0,max_repeated:0};
+    let mut g:Game = Game{repeat_map:HashMap::new(),board:Board::empty(),
+    old_state:Board::empty(),
+    turn_counter:1,last_capture:0,max_repeated:0,start_fen:START_FEN.to_string(),history:vec![],undo_stack:vec![],redo_stack:vec![],declared_result:None,chess960:false};
 
     g.restart();
 
     return g;
 }
 
+//creates a chess960 game from start position number index (0-959)
+pub fn start_chess960(index: usize) -> Game {
+    let mut g = start();
+    g.restart_chess960(index);
+    return g;
+}
+
+//back rank of chess960 start position number index in the usual numbering, 518 is the standard position. panics past 959
+pub fn chess960_back_rank(index: usize) -> [PieceType; 8] {
+    assert!(index < 960, "chess960 positions are numbered 0-959");
+    let mut rank = [PieceType::NONE; 8];
+    let mut n = index;
+    //light squared bishop on b, d, f or h, dark squared one on a, c, e or g
+    rank[2*(n % 4) + 1] = PieceType::BISHIOP;
+    n /= 4;
+    rank[2*(n % 4)] = PieceType::BISHIOP;
+    n /= 4;
+    //queen on one of the six free squares
+    let free: Vec<usize> = (0..8).filter(|x| rank[*x] == PieceType::NONE).collect();
+    rank[free[n % 6]] = PieceType::QUEEN;
+    n /= 6;
+    //the ten ways to put two knights on the five free squares
+    let knights = [(0,1),(0,2),(0,3),(0,4),(1,2),(1,3),(1,4),(2,3),(2,4),(3,4)];
+    let free: Vec<usize> = (0..8).filter(|x| rank[*x] == PieceType::NONE).collect();
+    rank[free[knights[n].0]] = PieceType::KNIGHT;
+    rank[free[knights[n].1]] = PieceType::KNIGHT;
+    //rook, king, rook on what is left
+    let free: Vec<usize> = (0..8).filter(|x| rank[*x] == PieceType::NONE).collect();
+    rank[free[0]] = PieceType::ROOK;
+    rank[free[1]] = PieceType::KING;
+    rank[free[2]] = PieceType::ROOK;
+    return rank;
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn mv(uci: &str) -> Move {
+        return Move::parse(uci).unwrap();
+    }
+
     // #[test]
     // fn it_works() {
     //     let result = add(2, 2);
@@ -932,34 +2156,34 @@ mod tests {
     fn stalemate(){
         let mut gam = start();
         
-        gam.move_piece("e2", "e3", "x");
-        gam.move_piece("a7", "a5", "x");
-        gam.move_piece("d1", "h5", "x");
-        gam.move_piece("a8", "a6", "x");
-        gam.move_piece("h5", "a5", "x");
-        gam.move_piece("h7", "h5", "x");
-        gam.move_piece("h2", "h4", "x");
-        gam.move_piece("a6", "h6", "x");
+        gam.move_piece(mv("e2e3")).unwrap();
+        gam.move_piece(mv("a7a5")).unwrap();
+        gam.move_piece(mv("d1h5")).unwrap();
+        gam.move_piece(mv("a8a6")).unwrap();
+        gam.move_piece(mv("h5a5")).unwrap();
+        gam.move_piece(mv("h7h5")).unwrap();
+        gam.move_piece(mv("h2h4")).unwrap();
+        gam.move_piece(mv("a6h6")).unwrap();
     
-        gam.move_piece("a5", "c7", "x");
-        gam.move_piece("f7", "f6", "x");
-        gam.move_piece("c7", "d7", "x");
-        gam.move_piece("e8", "f7", "x");
+        gam.move_piece(mv("a5c7")).unwrap();
+        gam.move_piece(mv("f7f6")).unwrap();
+        gam.move_piece(mv("c7d7")).unwrap();
+        gam.move_piece(mv("e8f7")).unwrap();
     
-        gam.move_piece("d7", "b7", "x");
-        gam.move_piece("d8", "d3", "x");
-        gam.move_piece("b7", "b8", "x");
-        gam.move_piece("d3", "h7", "x");
+        gam.move_piece(mv("d7b7")).unwrap();
+        gam.move_piece(mv("d8d3")).unwrap();
+        gam.move_piece(mv("b7b8")).unwrap();
+        gam.move_piece(mv("d3h7")).unwrap();
     
-        gam.move_piece("b8", "c8", "x");
-        gam.move_piece("f7", "g6", "x");
-        gam.move_piece("c8", "e6", "x");
+        gam.move_piece(mv("b8c8")).unwrap();
+        gam.move_piece(mv("f7g6")).unwrap();
+        gam.move_piece(mv("c8e6")).unwrap();
 
         let moves = gam.get_all_legal_moves();
         let state = gam.check_state();
 
         assert_eq!(moves, vec![]);
-        assert_eq!(state,GameState::DRAW);
+        assert_eq!(state,GameState::DRAW(DrawReason::STALEMATE));
 
     }
 
@@ -967,27 +2191,29 @@ mod tests {
     fn repetition(){
         let mut gam = start();
         
-        gam.move_piece("a2", "a4", "x");
-        gam.move_piece("a7", "a5", "x");
-        gam.move_piece("a1", "a3", "x");
-        gam.move_piece("a8", "a6", "x");
+        gam.move_piece(mv("a2a4"));
+        gam.move_piece(mv("a7a5"));
+        gam.move_piece(mv("a1a3"));
+        gam.move_piece(mv("a8a6"));
 
-        gam.move_piece("a3", "h3", "x");
-        gam.move_piece("a6", "h6", "x");
-        gam.move_piece("h3", "a3", "x");
-        gam.move_piece("h6", "a6", "x");
+        gam.move_piece(mv("a3h3"));
+        gam.move_piece(mv("a6h6"));
+        gam.move_piece(mv("h3a3"));
+        gam.move_piece(mv("h6a6"));
 
-        gam.move_piece("a3", "h3", "x");
-        gam.move_piece("a6", "h6", "x");
-        gam.move_piece("h3", "a3", "x");
-        gam.move_piece("h6", "a6", "x");
+        gam.move_piece(mv("a3h3"));
+        gam.move_piece(mv("a6h6"));
+        gam.move_piece(mv("h3a3"));
+        gam.move_piece(mv("h6a6"));
 
         print!("REP{:?}",gam.max_repeated);
 
-        // let moves = gam.get_all_legal_moves();
-        // let state = gam.check_state();
-        // assert_eq!(moves, vec![]);
-        // assert_eq!(state,GameState::DRAW);
+        //threefold repetition only ends the game when claimed
+        assert_eq!(gam.check_state(),GameState::ONGOING);
+        assert_eq!(gam.claimable_draw(),Some(DrawReason::THREEFOLD_REPETITION));
+        assert_eq!(gam.claim_draw(),Some(DrawReason::THREEFOLD_REPETITION));
+        assert_eq!(gam.check_state(),GameState::DRAW(DrawReason::THREEFOLD_REPETITION));
+        assert_eq!(gam.move_piece(mv("a3h3")),Err(MoveError::GameOver));
         
 
 
@@ -997,20 +2223,20 @@ mod tests {
     fn castle(){
         let mut gam = start();
         
-        gam.move_piece("a2", "a4", "x");
-        gam.move_piece("a7", "a5", "x");
-        gam.move_piece("b1", "a3", "x");
-        gam.move_piece("a8", "a6", "x");
+        gam.move_piece(mv("a2a4"));
+        gam.move_piece(mv("a7a5"));
+        gam.move_piece(mv("b1a3"));
+        gam.move_piece(mv("a8a6"));
          
-        gam.move_piece("d2", "d4", "x");
-        gam.move_piece("a6", "a8", "x");
-        gam.move_piece("c1", "h6", "x");
-        gam.move_piece("a8", "a6", "x");
+        gam.move_piece(mv("d2d4"));
+        gam.move_piece(mv("a6a8"));
+        gam.move_piece(mv("c1h6"));
+        gam.move_piece(mv("a8a6"));
 
-        gam.move_piece("d1", "d3", "x");
-        gam.move_piece("a6", "a8", "x");
-        gam.move_piece("b6", "b5", "x");
-        gam.move_piece("e1", "c1", "x");
+        gam.move_piece(mv("d1d3"));
+        gam.move_piece(mv("a6a8"));
+        gam.move_piece(mv("b6b5"));
+        gam.move_piece(mv("e1c1"));
 
         print!("REP{:?}",gam.max_repeated);
 
@@ -1048,13 +2274,13 @@ mod tests {
     fn enpassant(){
         let mut gam = start();
         
-        gam.move_piece("a2", "a4", "x");
-        gam.move_piece("a7", "a6", "x");
-        gam.move_piece("a4", "a5", "x");
-        gam.move_piece("b7", "b5", "x");
+        gam.move_piece(mv("a2a4"));
+        gam.move_piece(mv("a7a6"));
+        gam.move_piece(mv("a4a5"));
+        gam.move_piece(mv("b7b5"));
 
         //en passant 
-        gam.move_piece("a5", "b6", "x");
+        gam.move_piece(mv("a5b6"));
 
         print!("REP{:?}",gam.max_repeated);
 
@@ -1072,18 +2298,18 @@ mod tests {
     fn promotion(){
         let mut gam = start();
         
-        gam.move_piece("a2", "a4", "x");
-        gam.move_piece("a7", "a6", "x");
-        gam.move_piece("a4", "a5", "x");
-        gam.move_piece("b7", "b5", "x");
+        gam.move_piece(mv("a2a4"));
+        gam.move_piece(mv("a7a6"));
+        gam.move_piece(mv("a4a5"));
+        gam.move_piece(mv("b7b5"));
 
         //en passant 
-        gam.move_piece("a5", "b6", "x");
-        gam.move_piece("a8", "a7", "x");
-        gam.move_piece("b6", "a7", "x");
-        gam.move_piece("c8", "b7", "x");
+        gam.move_piece(mv("a5b6"));
+        gam.move_piece(mv("a8a7"));
+        gam.move_piece(mv("b6a7"));
+        gam.move_piece(mv("c8b7"));
         //promotion
-        gam.move_piece("a7", "b8", "Q");
+        gam.move_piece(mv("a7b8q"));
 
         print!("REP{:?}",gam.max_repeated);
 
@@ -1103,12 +2329,12 @@ mod tests {
     fn checkmate(){
         let mut gam = start();
         
-        gam.move_piece("f2", "f3", "x");
-        gam.move_piece("e7", "e6", "x");
-        gam.move_piece("g2", "g4", "x");
+        gam.move_piece(mv("f2f3"));
+        gam.move_piece(mv("e7e6"));
+        gam.move_piece(mv("g2g4"));
         
         //fool's mate
-        gam.move_piece("d8", "h4", "x");
+        gam.move_piece(mv("d8h4"));
 
 
 
@@ -1120,10 +2346,657 @@ mod tests {
         let representation = gam.get_board_representation();
         let correct_state = "RNBQKBNR/PPPPP..P/.....P../......Pq/......../....p.../pppp.ppp/rnb.kbnr";
 
-        assert_eq!(state,GameState::WIN_B);
+        assert_eq!(state,GameState::WIN_B(WinReason::CHECKMATE));
         assert_eq!(correct_state,representation);
 
 
     }
 
+    #[test]
+    fn notation_parsing(){
+        assert_eq!(Square::parse("e4"), Ok(Square::new(4,3)));
+        assert_eq!("H8".parse::<Square>(), Ok(Square::new(7,7)));
+        assert_eq!(Square::new(0,0).to_string(), "a1");
+        assert_eq!(Square::from_index(12), Square::new(4,1));
+        assert_eq!(Square::new(7,7).index(), 63);
+        for bad in ["", "e", "e9", "i1", "e44", "é4"] {
+            assert_eq!(Square::parse(bad), Err(NotationError::InvalidSquare(bad.to_string())));
+        }
+
+        assert_eq!(Move::parse("e7e8q"), Ok(Move::with_promotion(Square::new(4,6), Square::new(4,7), PieceType::QUEEN)));
+        assert_eq!(mv("g1f3").to_string(), "g1f3");
+        assert_eq!(mv("a2a1n").to_string(), "a2a1n");
+        assert_eq!(Move::parse("e7e8k"), Err(NotationError::InvalidPromotion('k')));
+        assert_eq!(Move::parse("e7"), Err(NotationError::InvalidMove("e7".to_string())));
+        assert_eq!(Move::parse("e7z8"), Err(NotationError::InvalidSquare("z8".to_string())));
+
+        //moves are matched against the generated ones, which carry the flags
+        let mut gam = start();
+        let moves = gam.get_legal_moves(Square::new(4,1));
+        assert_eq!(moves, vec![Move::new(Square::new(4,1), Square::new(4,2)), Move{from:Square::new(4,1), to:Square::new(4,3), promotion:None, flags:Move::DOUBLE_PUSH}]);
+        assert!(gam.get_legal_moves(Square::new(4,6)).is_empty());
+        assert_eq!(gam.move_piece(mv("e2e5")), Err(MoveError::IllegalDestination(Square::new(4,4))));
+        assert!(gam.move_piece(mv("e2e4")).is_ok());
+        assert_eq!(gam.history, vec![Move{from:Square::new(4,1), to:Square::new(4,3), promotion:None, flags:Move::DOUBLE_PUSH}]);
+    }
+
+    #[test]
+    fn move_errors(){
+        let mut gam = Game::from_fen("4k3/1P6/8/8/8/8/3r4/R3K2R w KQ - 0 1").unwrap();
+        assert_eq!(gam.move_piece(mv("c3c4")), Err(MoveError::NoPiece(Square::new(2,2))));
+        assert_eq!(gam.move_piece(mv("d2d1")), Err(MoveError::WrongSide(Color::W)));
+        assert_eq!(gam.move_piece(mv("a1b2")), Err(MoveError::IllegalDestination(Square::new(1,1))));
+        //the rook on d2 covers d1, so the king cannot castle through it
+        assert_eq!(gam.move_piece(mv("e1c1")), Err(MoveError::IllegalDestination(Square::new(2,0))));
+        assert_eq!(gam.move_piece(mv("e1d1")), Err(MoveError::KingInCheck));
+        assert_eq!(gam.move_piece(mv("b7b8")), Err(MoveError::MissingPromotion));
+        assert_eq!(gam.move_piece(Move::with_promotion(Square::new(1,6), Square::new(1,7), PieceType::KING)), Err(MoveError::InvalidPromotion(PieceType::KING)));
+        assert_eq!(gam.move_piece(mv("a1a2q")), Err(MoveError::InvalidPromotion(PieceType::QUEEN)));
+        assert_eq!(gam.move_piece(mv("b7b8q")).map(|m| m.flags), Ok(Move::PROMOTION));
+
+        //fool's mate
+        let mut gam = start();
+        for m in ["f2f3", "e7e5", "g2g4", "d8h4"] {
+            assert!(gam.move_piece(mv(m)).is_ok());
+        }
+        assert_eq!(gam.move_piece(mv("a2a3")), Err(MoveError::GameOver));
+        assert_eq!(gam.move_piece_san("a3"), Err(SanError::IllegalMove("a3".to_string())));
+        //drawn by the 75 move rule, the rook move itself is fine
+        let mut gam = Game::from_fen("4k3/8/8/8/8/8/8/R3K3 w - - 150 80").unwrap();
+        assert_eq!(gam.move_piece_san("Ra2"), Err(SanError::Rejected(MoveError::GameOver)));
+        assert_eq!(MoveError::KingInCheck.to_string(), "your king would be in check");
+    }
+
+    #[test]
+    fn undo_redo(){
+        let mut gam = Game::from_fen("r3k2r/8/8/8/1p6/8/P7/R3K2R w KQkq - 3 20").unwrap();
+        let fens = vec![gam.to_fen()];
+        let mut fens = fens;
+        //double push taken en passant, a capture, castling on both sides and a king move
+        for m in ["a2a4", "b4a3", "a1a3", "e8c8", "e1g1", "c8b8"] {
+            assert!(gam.move_piece(mv(m)).is_ok(), "{}", m);
+            fens.push(gam.to_fen());
+        }
+
+        for n in (0..6).rev() {
+            assert!(gam.undo().is_some());
+            assert_eq!(gam.to_fen(), fens[n]);
+        }
+        assert_eq!(gam.undo(), None);
+        assert_eq!(gam.repeat_map.len(), 1);
+        assert_eq!(gam.board.wk_pos, Square::new(4,0));
+
+        for n in 1..7 {
+            assert!(gam.redo().is_some());
+            assert_eq!(gam.to_fen(), fens[n]);
+        }
+        assert_eq!(gam.board.wk_pos, Square::new(6,0));
+        assert_eq!(gam.board.bk_pos, Square::new(1,7));
+
+        //a new move drops the moves that could still be redone
+        gam.undo();
+        assert_eq!(gam.move_piece(mv("c8d7")), Ok(mv("c8d7")));
+        assert_eq!(gam.redo(), None);
+        assert_eq!(gam.history.len(), 6);
+    }
+
+    #[test]
+    fn zobrist_hashing(){
+        //the incremental key always matches the one computed from scratch
+        let mut gam = Game::from_fen("r3k2r/1P6/8/8/1p6/8/P7/R3K2R w KQkq - 0 1").unwrap();
+        for m in ["a2a4", "b4a3", "e1c1", "e8g8", "b7b8q", "a8b8"] {
+            assert!(gam.move_piece(mv(m)).is_ok(), "{}", m);
+            let (t, hash) = (gam.turn_counter, gam.board.hash);
+            assert_eq!(hash, gam.board.compute_hash(t), "{}", m);
+        }
+
+        //transpositions reach the same key
+        let mut a = start();
+        let mut b = start();
+        for m in ["g1f3", "g8f6", "b1c3"] {
+            a.move_piece(mv(m)).unwrap();
+        }
+        for m in ["b1c3", "g8f6", "g1f3"] {
+            b.move_piece(mv(m)).unwrap();
+        }
+        assert_eq!(a.board.hash, b.board.hash);
+
+        //side to move, castling rights and a usable en passant file change the key
+        let white = Game::from_fen("4k3/8/8/8/8/8/8/R3K3 w - - 0 1").unwrap();
+        let black = Game::from_fen("4k3/8/8/8/8/8/8/R3K3 b - - 0 1").unwrap();
+        let castle = Game::from_fen("4k3/8/8/8/8/8/8/R3K3 w Q - 0 1").unwrap();
+        assert_ne!(white.board.hash, black.board.hash);
+        assert_ne!(white.board.hash, castle.board.hash);
+
+        let ep = Game::from_fen("4k3/8/8/3Pp3/8/8/8/4K3 w - e6 0 2").unwrap();
+        let no_ep = Game::from_fen("4k3/8/8/3Pp3/8/8/8/4K3 w - - 0 2").unwrap();
+        assert_ne!(ep.board.hash, no_ep.board.hash);
+        //nothing can take en passant, so the target square does not matter
+        let lone = Game::from_fen("4k3/8/8/4p3/8/8/8/4K3 w - e6 0 2").unwrap();
+        let lone_no_ep = Game::from_fen("4k3/8/8/4p3/8/8/8/4K3 w - - 0 2").unwrap();
+        assert_eq!(lone.board.hash, lone_no_ep.board.hash);
+    }
+
+    #[test]
+    fn move_rules(){
+        //the clock counts plies and is reset by pawn moves and captures
+        let mut gam = Game::from_fen("4k3/4p3/8/8/8/8/8/R3K3 w - - 97 80").unwrap();
+        gam.move_piece(mv("a1a2")).unwrap();
+        gam.move_piece(mv("e8d8")).unwrap();
+        assert_eq!(gam.claimable_draw(), None);
+        gam.move_piece(mv("a2a3")).unwrap();
+        assert_eq!(gam.board.halfmove_clock, 100);
+        assert_eq!(gam.claimable_draw(), Some(DrawReason::FIFTY_MOVES));
+        assert_eq!(gam.check_state(), GameState::ONGOING);
+        gam.move_piece(mv("e7e5")).unwrap();
+        assert_eq!(gam.board.halfmove_clock, 0);
+        assert_eq!(gam.claimable_draw(), None);
+
+        //75 moves end the game without a claim
+        let mut gam = Game::from_fen("4k3/8/8/8/8/8/8/R3K3 w - - 149 80").unwrap();
+        assert_eq!(gam.check_state(), GameState::ONGOING);
+        gam.move_piece(mv("a1a2")).unwrap();
+        assert_eq!(gam.check_state(), GameState::DRAW(DrawReason::SEVENTY_FIVE_MOVES));
+        assert_eq!(gam.claimable_draw(), None);
+
+        //unless the last move mates
+        let mut gam = Game::from_fen("k7/8/1K6/8/8/8/8/7R w - - 149 80").unwrap();
+        gam.move_piece(mv("h1h8")).unwrap();
+        assert_eq!(gam.check_state(), GameState::WIN_W(WinReason::CHECKMATE));
+
+        //captures reset the clock, including en passant
+        let mut gam = Game::from_fen("4k3/8/8/3Pp3/8/8/8/R3K3 w - e6 40 80").unwrap();
+        let played = gam.move_piece(mv("d5e6")).unwrap();
+        assert!(played.is_en_passant());
+        assert_eq!(gam.board.halfmove_clock, 0);
+        assert_eq!(gam.last_capture, 159);
+
+        //the fifth occurrence draws on its own, undo takes it back
+        let mut gam = start();
+        let shuffle = ["g1f3", "g8f6", "f3g1", "f6g8"];
+        for _ in 0..4 {
+            for m in shuffle {
+                gam.move_piece(mv(m)).unwrap();
+            }
+        }
+        assert_eq!(gam.repetitions(), 5);
+        assert_eq!(gam.check_state(), GameState::DRAW(DrawReason::FIVEFOLD_REPETITION));
+        gam.undo();
+        assert_eq!(gam.check_state(), GameState::ONGOING);
+        assert_eq!(gam.claimable_draw(), Some(DrawReason::THREEFOLD_REPETITION));
+    }
+
+    #[test]
+    fn insufficient_material(){
+        let drawn = [
+            "4k3/8/8/8/8/8/8/4K3 w - - 0 1",
+            "4k3/8/8/8/8/8/8/4KN2 w - - 0 1",
+            "4k3/8/8/8/8/8/8/2B1K3 b - - 0 1",
+            "4k3/8/8/8/8/8/8/2b1K3 w - - 0 1",
+            //bishops on dark squares only
+            "5b2/4k3/8/8/8/8/8/2B1K3 w - - 0 1",
+            "4k3/8/8/8/8/8/8/B1B1K3 w - - 0 1",
+        ];
+        for fen in drawn {
+            let mut gam = Game::from_fen(fen).unwrap();
+            assert_eq!(gam.check_state(), GameState::DRAW(DrawReason::INSUFFICIENT_MATERIAL), "{}", fen);
+        }
+
+        let playable = [
+            "4k3/8/8/8/8/8/P7/4K3 w - - 0 1",
+            "4k3/8/8/8/8/8/8/4KNN1 w - - 0 1",
+            "4k3/8/8/8/8/8/8/3QK3 w - - 0 1",
+            "4kn2/8/8/8/8/8/8/4KN2 w - - 0 1",
+            //bishops on different colours can still mate with help
+            "2b1k3/8/8/8/8/8/8/2B1K3 w - - 0 1",
+            "4k3/8/8/8/8/8/8/2B1KB2 w - - 0 1",
+        ];
+        for fen in playable {
+            let mut gam = Game::from_fen(fen).unwrap();
+            assert_eq!(gam.check_state(), GameState::ONGOING, "{}", fen);
+        }
+
+        //taking the last pawn ends the game
+        let mut gam = Game::from_fen("4k3/8/8/8/8/8/4p3/4K3 w - - 0 1").unwrap();
+        gam.move_piece(mv("e1e2"));
+        assert_eq!(gam.check_state(), GameState::DRAW(DrawReason::INSUFFICIENT_MATERIAL));
+        assert_eq!(gam.move_piece(mv("e8d7")), Err(MoveError::GameOver));
+    }
+
+    #[test]
+    fn declared_results(){
+        let mut gam = start();
+        gam.move_piece(mv("e2e4"));
+        gam.resign(Color::B);
+        assert_eq!(gam.check_state(), GameState::WIN_W(WinReason::RESIGNATION));
+        assert_eq!(gam.check_state().winner(), Some(Color::W));
+        assert_eq!(gam.check_state().to_string(), "White wins by resignation");
+        assert_eq!(gam.move_piece(mv("e7e5")), Err(MoveError::GameOver));
+        //a finished game stays finished
+        gam.agree_draw();
+        assert_eq!(gam.check_state(), GameState::WIN_W(WinReason::RESIGNATION));
+        //taking back the last move takes back the resignation too
+        gam.undo();
+        assert_eq!(gam.check_state(), GameState::ONGOING);
+
+        gam.agree_draw();
+        assert_eq!(gam.check_state(), GameState::DRAW(DrawReason::AGREEMENT));
+        assert_eq!(gam.check_state().winner(), None);
+
+        let mut gam = start();
+        gam.timeout(Color::W);
+        assert_eq!(gam.check_state().to_string(), "Black wins by timeout");
+        //a bare king can not win on time
+        let mut gam = Game::from_fen("4k3/8/8/8/8/8/8/R3K3 w - - 0 1").unwrap();
+        gam.timeout(Color::W);
+        assert_eq!(gam.check_state(), GameState::DRAW(DrawReason::INSUFFICIENT_MATERIAL));
+
+        let mut gam = Game::from_fen("k7/8/1K6/8/8/8/8/7R w - - 0 1").unwrap();
+        gam.move_piece(mv("h1h8"));
+        assert_eq!(gam.check_state().to_string(), "White wins by checkmate");
+        assert_eq!(gam.check_state().headline(), "White wins");
+    }
+
+    //fen and node counts from depth 1 on, deeper counts are checked by the ignored test below
+    const PERFT_POSITIONS: [(&str, &[u64]); 6] = [
+        (START_FEN, &[20, 400, 8902, 197281, 4865609]),
+        //kiwipete
+        ("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1", &[48, 2039, 97862, 4085603]),
+        ("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1", &[14, 191, 2812, 43238, 674624]),
+        ("r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1", &[6, 264, 9467, 422333]),
+        ("rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8", &[44, 1486, 62379, 2103487]),
+        ("r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10", &[46, 2079, 89890, 3894594]),
+    ];
+
+    //chess960 positions from the Ethereal suite, and one where castling would uncover a check from the a1 queen
+    const CHESS960_PERFT_POSITIONS: [(&str, &[u64]); 5] = [
+        ("bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w KQkq - 2 9", &[21, 528, 12189, 326672]),
+        ("2nnrbkr/p1qppppp/8/1ppb4/6PP/3PP3/PPP2P2/BQNNRBKR w KQkq - 1 9", &[21, 807, 18002, 667366]),
+        ("b1q1rrkb/pppppppp/3nn3/8/P7/1PPP4/4PPPP/BQNNRKRB w KQ - 1 9", &[20, 479, 10471, 273318]),
+        ("4r3/3k4/8/8/8/8/6PP/qR1K1R2 w KQ - 0 1", &[19, 628, 12858, 405636]),
+        //the black king walks along white's back rank
+        ("2r5/8/8/8/8/8/6PP/k2KR3 w K - 0 1", &[17, 242, 3931, 57700]),
+    ];
+
+    fn check_perft(positions: &[(&str, &[u64])], max_depth: usize) {
+        for (fen, counts) in positions {
+            let mut gam = Game::from_fen(fen).unwrap();
+            for (n, expected) in counts.iter().enumerate().take(max_depth) {
+                assert_eq!(gam.perft(n + 1), *expected, "{} at depth {}", fen, n + 1);
+            }
+            //perft must leave the game as it found it
+            assert_eq!(gam.to_fen(), *fen);
+        }
+    }
+
+    #[test]
+    fn perft(){
+        check_perft(&PERFT_POSITIONS, 3);
+        check_perft(&CHESS960_PERFT_POSITIONS, 3);
+
+        let mut gam = Game::from_fen(PERFT_POSITIONS[1].0).unwrap();
+        let divide = gam.perft_divide(2);
+        assert_eq!(divide.len(), 48);
+        assert_eq!(divide.iter().map(|d| d.1).sum::<u64>(), 2039);
+        //castling both ways and the capture of the pawn on a6
+        for (m, nodes) in [("e1g1", 43), ("e1c1", 43), ("e2a6", 36)] {
+            assert!(divide.iter().any(|d| d.0.matches(mv(m)) && d.1 == nodes), "{}", m);
+        }
+    }
+
+    //cargo test --release -- --ignored
+    #[test]
+    #[ignore]
+    fn perft_deep(){
+        check_perft(&PERFT_POSITIONS, 5);
+        check_perft(&CHESS960_PERFT_POSITIONS, 4);
+    }
+
+    //every move taken back leaves the board exactly as it was, hash and clock included
+    fn check_unmake(gam: &mut Game, depth: usize) {
+        if depth == 0 {
+            return;
+        }
+        for mv in gam.get_all_legal_moves() {
+            let saved = gam.board.clone();
+            let undo = gam.board.make_move(mv, gam.turn_counter);
+            assert_eq!(gam.board.hash, gam.board.compute_hash(gam.turn_counter + 1), "{} after {}", gam.board.placement_fen(), mv);
+            gam.turn_counter += 1;
+            check_unmake(gam, depth - 1);
+            gam.turn_counter -= 1;
+            gam.board.unmake_move(undo);
+            assert_eq!(gam.board, saved, "{} after {}", saved.placement_fen(), mv);
+        }
+    }
+
+    //the same moves as checking every pseudo legal move with is_legal
+    fn check_generator(gam: &mut Game, depth: usize) {
+        let mut legal = gam.get_all_legal_moves();
+        let mut expected = vec![];
+        gam.board.pseudo_legal_moves(gam.turn_counter, !0, gam.chess960, &mut expected);
+        expected.retain(|m| gam.board.is_legal(*m, gam.turn_counter));
+        let mut captures = vec![];
+        gam.board.generate_captures(gam.turn_counter, &mut captures);
+        let key = |m: &Move| (m.from.index(), m.to.index(), m.promotion.map(|p| p.index()), m.flags);
+        legal.sort_by_key(key);
+        expected.sort_by_key(key);
+        captures.sort_by_key(key);
+        assert_eq!(legal, expected, "{}", gam.board.placement_fen());
+        expected.retain(|m| (m.is_capture() || m.promotion.is_some()) && !(m.is_castle() && gam.chess960));
+        assert_eq!(captures, expected, "{}", gam.board.placement_fen());
+        if depth == 0 {
+            return;
+        }
+        for mv in legal {
+            let undo = gam.board.make_move(mv, gam.turn_counter);
+            gam.turn_counter += 1;
+            check_generator(gam, depth - 1);
+            gam.turn_counter -= 1;
+            gam.board.unmake_move(undo);
+        }
+    }
+
+    #[test]
+    fn legal_generator(){
+        for (fen, _) in PERFT_POSITIONS.iter().chain(CHESS960_PERFT_POSITIONS.iter()) {
+            check_generator(&mut Game::from_fen(fen).unwrap(), 2);
+        }
+
+        //double check from the knight and the rook, taking the knight with the bishop is not enough
+        let mut gam = Game::from_fen("5rk1/8/8/8/8/8/3n2PP/R1B2K2 w - - 0 1").unwrap();
+        let moves = gam.get_all_legal_moves();
+        assert!(!moves.is_empty());
+        assert!(moves.iter().all(|m| m.from == Square::new(5,0)));
+        //the bishop is pinned along the diagonal and can only move on it
+        let mut gam = Game::from_fen("4k3/8/8/b7/8/8/3B4/4K3 w - - 0 1").unwrap();
+        let moves = gam.get_legal_moves(Square::new(3,1));
+        assert_eq!(moves.len(), 3);
+        assert!(moves.iter().any(|m| m.to == Square::new(0,4)));
+        //en passant that would open the rank to the king
+        let mut gam = Game::from_fen("8/8/8/K2pP2r/8/8/8/7k w - d6 0 1").unwrap();
+        assert!(gam.get_legal_moves(Square::new(4,4)).iter().all(|m| !m.is_en_passant()));
+    }
+
+    #[test]
+    fn attack_maps(){
+        let gam = Game::from_fen("4k3/8/8/3p4/8/1BN5/8/R3K3 w - - 0 1").unwrap();
+        let d5 = Square::new(3,4);
+        //the bishop and the knight both attack the pawn, nothing covers it
+        assert_eq!(squares_of(gam.board.attackers_of(d5, Color::W)), vec![Square::new(1,2), Square::new(2,2)]);
+        assert!(!gam.board.is_defended(d5, Color::B));
+        //the minor pieces don't cover each other, but all four white pieces cover d1
+        assert!(!gam.board.is_defended(Square::new(1,2), Color::W));
+        assert!(!gam.board.is_defended(Square::new(2,2), Color::W));
+        assert_eq!(gam.board.attackers_of(Square::new(3,0), Color::W).count_ones(), 4);
+
+        let attacked = gam.board.attacked_by(Color::B);
+        //the pawn covers c4 and e4, the king its five neighbours
+        assert_eq!(squares_of(attacked & 0xFFFFFFFF), vec![Square::new(2,3), Square::new(4,3)]);
+        assert_eq!(attacked.count_ones(), 7);
+        //the rook stops at its king and the bishop at the pawn
+        let attacked = gam.board.attacked_by(Color::W);
+        assert!(attacked & (1 << Square::new(4,0).index()) != 0);
+        assert!(attacked & (1 << Square::new(7,0).index()) == 0);
+        assert!(attacked & (1 << Square::new(4,5).index()) == 0);
+    }
+
+    #[test]
+    fn static_exchange(){
+        let see = |fen: &str, uci: &str| {
+            let mut gam = Game::from_fen(fen).unwrap();
+            let mv = gam.get_all_legal_moves().into_iter().find(|m| m.matches(Move::parse(uci).unwrap())).unwrap();
+            return gam.board.see(mv);
+        };
+        assert_eq!(see("4k3/8/8/4p3/8/8/8/4RK2 w - - 0 1", "e1e5"), 100);
+        assert_eq!(see("4k3/8/3p4/4p3/8/8/8/4RK2 w - - 0 1", "e1e5"), -400);
+        //the second rook joins in once the first has gone
+        assert_eq!(see("4r1k1/8/8/4p3/8/8/4R3/4RK2 w - - 0 1", "e2e5"), 100);
+        //the king can't take back while the rook covers the square through the queen
+        assert_eq!(see("8/8/8/4k3/3p4/8/8/3Q3K w - - 0 1", "d1d4"), -800);
+        assert_eq!(see("8/8/8/4k3/3p4/8/3Q4/3R3K w - - 0 1", "d2d4"), 100);
+        assert_eq!(see("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1", "e5d6"), 100);
+        assert_eq!(see("4k3/1P6/8/8/8/8/8/4K3 w - - 0 1", "b7b8q"), 800);
+
+        let gam = Game::from_fen("4k3/8/5p2/4n3/8/8/8/4RK2 w - - 0 1").unwrap();
+        //taking the guarded knight with the rook costs more than it wins
+        assert!(!gam.board.is_hanging(Square::new(4,4)));
+        let gam = Game::from_fen("4k3/8/8/4n3/3P4/8/8/4RK2 b - - 0 1").unwrap();
+        assert!(gam.board.is_hanging(Square::new(4,4)));
+        assert!(!gam.board.is_hanging(Square::new(3,3)));
+    }
+
+    #[test]
+    fn make_unmake(){
+        for (fen, _) in PERFT_POSITIONS.iter().chain(CHESS960_PERFT_POSITIONS.iter()) {
+            check_unmake(&mut Game::from_fen(fen).unwrap(), 2);
+        }
+
+        let mut gam = Game::from_fen("4k3/8/8/3pP3/8/8/8/4K3 w - d6 7 30").unwrap();
+        let mv = gam.parse_san("exd6").unwrap();
+        let undo = gam.board.make_move(mv, gam.turn_counter);
+        assert_eq!(undo.captured, Some(Piece{variant:PieceType::PAWN,color:Color::B}));
+        assert_eq!(undo.ep, Some(Square::new(3,5)));
+        assert_eq!(undo.halfmove_clock, 7);
+        assert_eq!(gam.board.halfmove_clock, 0);
+        assert!(gam.board.piece_at(Square::new(3,4)).is_none());
+        gam.board.unmake_move(undo);
+        assert_eq!(gam.to_fen(), "4k3/8/8/3pP3/8/8/8/4K3 w - d6 7 30");
+    }
+
+    #[test]
+    fn chess960_positions(){
+        let names = |index| chess960_back_rank(index).iter().map(|p| san_letter(*p)).collect::<String>();
+        //first and last entry of the usual numbering, 518 is the standard position
+        assert_eq!(names(0), "BBQNNRKR");
+        assert_eq!(names(959), "RKRNNQBB");
+        assert_eq!(start_chess960(518).to_fen(), START_FEN);
+
+        let mut seen = std::collections::HashSet::new();
+        for index in 0..960 {
+            let rank = names(index);
+            let at = |c| rank.find(c).unwrap();
+            let last = |c| rank.rfind(c).unwrap();
+            //bishops on both square colours and the king between the rooks
+            assert_ne!(at('B') % 2, last('B') % 2, "{}", rank);
+            assert!(at('R') < at('K') && at('K') < last('R'), "{}", rank);
+            seen.insert(rank);
+        }
+        assert_eq!(seen.len(), 960);
+
+        let mut gam = start_chess960(0);
+        assert!(gam.chess960);
+        assert_eq!(gam.to_fen(), "bbqnnrkr/pppppppp/8/8/8/8/PPPPPPPP/BBQNNRKR w KQkq - 0 1");
+        gam.restart();
+        assert!(!gam.chess960);
+        assert_eq!(gam.to_fen(), START_FEN);
+    }
+
+    #[test]
+    fn chess960_castling(){
+        //king on g1 between rooks on b1 and h1, Shredder-FEN is read and written back as X-FEN
+        let mut gam = Game::from_fen("1r4kr/pppppppp/8/8/8/8/PPPPPPPP/1R4KR w HBhb - 0 1").unwrap();
+        assert!(gam.chess960);
+        let start_fen = "1r4kr/pppppppp/8/8/8/8/PPPPPPPP/1R4KR w KQkq - 0 1";
+        assert_eq!(gam.to_fen(), start_fen);
+
+        //castling is written as the king taking its own rook
+        let king = Square::parse("g1").unwrap();
+        let castles: Vec<Square> = gam.get_legal_moves(king).iter().filter(|m| m.is_castle()).map(|m| m.to).collect();
+        assert_eq!(castles, vec![Square::parse("h1").unwrap(), Square::parse("b1").unwrap()]);
+        assert_eq!(gam.to_san(mv("g1h1")), "O-O");
+        assert_eq!(gam.to_san(mv("g1b1")), "O-O-O");
+
+        gam.move_piece(mv("g1b1")).unwrap();
+        assert_eq!(gam.to_fen(), "1r4kr/pppppppp/8/8/8/8/PPPPPPPP/2KR3R b kq - 1 1");
+        //O-O leaves the king where it stands
+        gam.move_piece_san("O-O").unwrap();
+        assert_eq!(gam.to_fen(), "1r3rk1/pppppppp/8/8/8/8/PPPPPPPP/2KR3R w - - 2 2");
+        assert_eq!(gam.board.bk_pos, Square::parse("g8").unwrap());
+        gam.undo();
+        gam.undo();
+        assert_eq!(gam.to_fen(), start_fen);
+
+        //X-FEN names the rook by its file when it is not the outermost one
+        let mut gam = Game::from_fen("4k3/8/8/8/8/8/8/R1R1K3 w C - 0 1").unwrap();
+        assert_eq!(gam.to_fen(), "4k3/8/8/8/8/8/8/R1R1K3 w C - 0 1");
+        gam.move_piece(mv("e1c1")).unwrap();
+        assert_eq!(gam.to_fen(), "4k3/8/8/8/8/8/8/R1KR4 b - - 1 1");
+
+        //the a1 rook is the one Q stands for, but the c1 rook is in the way
+        let mut gam = Game::from_fen("4k3/8/8/8/8/8/8/R1R1K3 w Q - 0 1").unwrap();
+        assert!(!gam.get_all_legal_moves().iter().any(|m| m.is_castle()));
+    }
+
+    #[test]
+    fn undo_repetition(){
+        let mut gam = start();
+        let shuffle = ["g1f3", "g8f6", "f3g1", "f6g8"];
+        for m in shuffle.iter().chain(shuffle.iter()) {
+            gam.move_piece(mv(m)).unwrap();
+        }
+        //the start position counts as well, it has now occurred three times
+        assert_eq!(gam.max_repeated, 3);
+        gam.undo();
+        gam.undo();
+        gam.undo();
+        gam.undo();
+        assert_eq!(gam.max_repeated, 2);
+        assert_eq!(gam.repeat_map.values().sum::<usize>(), 5);
+        assert_eq!(gam.repeat_map.len(), 4);
+        gam.redo();
+        gam.redo();
+        gam.redo();
+        gam.redo();
+        assert_eq!(gam.max_repeated, 3);
+    }
+
+    #[test]
+    fn san_output(){
+        let mut gam = Game::from_fen("r3k2r/1P6/8/2pP4/8/1N3N2/8/R3K2R w KQkq c6 0 1").unwrap();
+        assert_eq!(gam.to_san(mv("b3d4")), "Nbd4");
+        assert_eq!(gam.to_san(mv("a1d1")), "Rd1");
+        assert_eq!(gam.to_san(mv("d5c6")), "dxc6");
+        assert_eq!(gam.to_san(mv("d5d6")), "d6");
+        assert_eq!(gam.to_san(mv("e1g1")), "O-O");
+        assert_eq!(gam.to_san(mv("e1c1")), "O-O-O");
+        assert_eq!(gam.to_san(mv("b7a8q")), "bxa8=Q+");
+        assert_eq!(gam.to_san(mv("b7b8n")), "b8=N");
+        assert_eq!(gam.to_san(mv("h1h8")), "Rxh8+");
+
+        //rank and full square disambiguation
+        let mut gam = Game::from_fen("1k6/8/8/8/4Q2Q/8/8/K6Q w - - 0 1").unwrap();
+        assert_eq!(gam.to_san(mv("e4g4")), "Qeg4");
+        assert_eq!(gam.to_san(mv("h1h2")), "Q1h2+");
+        assert_eq!(gam.to_san(mv("h4e1")), "Qh4e1");
+        assert_eq!(gam.parse_san("Qh4e1").map(|m| m.to_string()), Ok("h4e1".to_string()));
+
+        let mut gam = start();
+        gam.move_piece(mv("f2f3"));
+        gam.move_piece(mv("e7e5"));
+        gam.move_piece(mv("g2g4"));
+        assert_eq!(gam.to_san(mv("d8h4")), "Qh4#");
+        assert_eq!(gam.get_all_legal_moves_san().len(), 30);
+    }
+
+    #[test]
+    fn san_input(){
+        let mut gam = Game::from_fen("r3k2r/1P6/8/2pP4/8/1N3N2/8/R3K2R w KQkq c6 0 1").unwrap();
+        assert_eq!(gam.parse_san("Nbd4").map(|m| m.to_string()), Ok("b3d4".to_string()));
+        assert_eq!(gam.parse_san("N3d4"), Err(SanError::Ambiguous("N3d4".to_string())));
+        assert_eq!(gam.parse_san("Nd4"), Err(SanError::Ambiguous("Nd4".to_string())));
+        assert_eq!(gam.parse_san("dxc6").map(|m| m.to_string()), Ok("d5c6".to_string()));
+        assert_eq!(gam.parse_san("O-O-O").map(|m| m.to_string()), Ok("e1c1".to_string()));
+        assert_eq!(gam.parse_san("0-0").map(|m| m.to_string()), Ok("e1g1".to_string()));
+        assert_eq!(gam.parse_san("bxa8=Q+").map(|m| m.to_string()), Ok("b7a8q".to_string()));
+        assert_eq!(gam.parse_san("b8N").map(|m| m.to_string()), Ok("b7b8n".to_string()));
+        assert_eq!(gam.parse_san("Rxh8!?").map(|m| m.to_string()), Ok("h1h8".to_string()));
+        assert_eq!(gam.parse_san("b8"), Err(SanError::MissingPromotion("b8".to_string())));
+        assert_eq!(gam.parse_san("d6=Q"), Err(SanError::UnexpectedPromotion("d6=Q".to_string())));
+        assert_eq!(gam.parse_san("Ke3"), Err(SanError::IllegalMove("Ke3".to_string())));
+        assert_eq!(gam.parse_san("c6"), Err(SanError::IllegalMove("c6".to_string())));
+        for bad in ["", "Z4", "Nbxd", "xd4", "d5d6", "Nb3xxd4", "e9"] {
+            assert_eq!(gam.parse_san(bad), Err(SanError::Malformed(bad.to_string())));
+        }
+
+        //every generated move parses back to itself
+        let mut gam = Game::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1").unwrap();
+        for san in gam.get_all_legal_moves_san() {
+            let parsed = gam.parse_san(&san).unwrap();
+            assert_eq!(gam.to_san(parsed), san);
+        }
+        assert!(gam.move_piece_san("O-O").is_ok());
+        assert_eq!(gam.to_fen(), "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R4RK1 b kq - 1 1");
+    }
+
+    #[test]
+    fn fen_start_position(){
+        let mut gam = start();
+        assert_eq!(gam.to_fen(), START_FEN);
+
+        let mut from_fen = Game::from_fen(START_FEN).unwrap();
+        assert_eq!(from_fen.get_board_representation(), gam.get_board_representation());
+        assert_eq!(from_fen.get_all_legal_moves().len(), 20);
+    }
+
+    #[test]
+    fn fen_round_trip(){
+        let fens = vec![
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+            "4k3/8/8/2pP4/8/8/8/4K3 w - c6 0 41",
+            "r3k3/8/8/8/8/8/8/4K2R b Kq - 17 60",
+        ];
+        for fen in fens {
+            let mut gam = Game::from_fen(fen).unwrap();
+            assert_eq!(gam.to_fen(), fen);
+        }
+    }
+
+    #[test]
+    fn fen_after_moves(){
+        let mut gam = start();
+        gam.move_piece(mv("e2e4")).unwrap();
+        assert_eq!(gam.to_fen(), "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1");
+        gam.move_piece(mv("g8f6")).unwrap();
+        gam.move_piece(mv("e1e2")).unwrap();
+        assert_eq!(gam.to_fen(), "rnbqkb1r/pppppppp/5n2/8/4P3/8/PPPPKPPP/RNBQ1BNR b kq - 2 2");
+
+        //en passant square taken from the FEN can be used
+        let mut gam = Game::from_fen("4k3/8/8/2pP4/8/8/8/4K3 w - c6 0 41").unwrap();
+        assert!(gam.get_legal_moves(Square::new(3,4)).contains(&Move{from:Square::new(3,4), to:Square::new(2,5), promotion:None, flags:Move::CAPTURE | Move::EN_PASSANT}));
+        assert!(gam.move_piece(mv("d5c6")).is_ok());
+        assert_eq!(gam.to_fen(), "4k3/8/2P5/8/8/8/8/4K3 b - - 0 41");
+    }
+
+    #[test]
+    fn fen_errors(){
+        let cases = vec![
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0", FenError::FieldCount(5)),
+            ("rnbqkbnr/pppppppp/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", FenError::RankCount(7)),
+            ("rnbqkbnr/pppppppp/9/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", FenError::RankLength(6)),
+            ("rnbqkbnr/ppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", FenError::RankLength(7)),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNRR w KQkq - 0 1", FenError::RankLength(1)),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNX w KQkq - 0 1", FenError::InvalidPiece('X')),
+            ("rnbq1bnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQ - 0 1", FenError::KingCount(Color::B)),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNP w kq - 0 1", FenError::PawnOnBackRank),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR x KQkq - 0 1", FenError::InvalidSideToMove("x".to_string())),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBN1 w KQkq - 0 1", FenError::InvalidCastling("KQkq".to_string())),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KKkq - 0 1", FenError::InvalidCastling("KKkq".to_string())),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KHkq - 0 1", FenError::InvalidCastling("KHkq".to_string())),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w GAga - 0 1", FenError::InvalidCastling("GAga".to_string())),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq e3 0 1", FenError::InvalidEnPassant("e3".to_string())),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq z9 0 1", FenError::InvalidEnPassant("z9".to_string())),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - -1 1", FenError::InvalidHalfmoveClock("-1".to_string())),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 0", FenError::InvalidFullmoveNumber("0".to_string())),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 18446744073709551615", FenError::InvalidFullmoveNumber("18446744073709551615".to_string())),
+            ("4k2R/8/8/8/8/8/8/4K3 w - - 0 1", FenError::OpponentInCheck),
+        ];
+        for (fen, err) in cases {
+            assert_eq!(Game::from_fen(fen).err(), Some(err), "{}", fen);
+        }
+    }
+
 }
@@ -0,0 +1,513 @@
+//reading and writing games in Portable Game Notation
+//moves are stored as SAN strings and are always checked by replaying them through my_chess
+
+use crate::my_chess::{self, FenError, Game, GameState, START_FEN};
+
+//tags every exported game starts with, in this order
+pub const SEVEN_TAG_ROSTER: [&str; 7] = ["Event", "Site", "Date", "Round", "White", "Black", "Result"];
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct PgnMove {
+    pub san: String,
+    //numeric annotation glyphs, "!" and "?" style suffixes are stored as their NAG ($1-$6)
+    pub nags: Vec<u8>,
+    //comment that follows the move
+    pub comment: Option<String>,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct PgnGame {
+    pub tags: Vec<(String, String)>,
+    //comment before the first move
+    pub comment: Option<String>,
+    pub moves: Vec<PgnMove>,
+    //termination marker: "1-0", "0-1", "1/2-1/2" or "*"
+    pub result: String,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum PgnError {
+    UnterminatedTag,
+    UnterminatedComment,
+    UnterminatedVariation,
+    InvalidTag(String),
+    UnexpectedToken(String),
+    //the movetext of the game (counted from 1) has no termination marker
+    MissingResult(usize),
+    InvalidFen(FenError),
+    //game and ply are counted from 1
+    IllegalMove { game: usize, ply: usize, san: String },
+}
+
+impl std::fmt::Display for PgnError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            PgnError::UnterminatedTag => write!(f, "tag pair is missing ']'"),
+            PgnError::UnterminatedComment => write!(f, "comment is missing '}}'"),
+            PgnError::UnterminatedVariation => write!(f, "variation is missing ')'"),
+            PgnError::InvalidTag(t) => write!(f, "invalid tag pair '{}'", t),
+            PgnError::UnexpectedToken(t) => write!(f, "unexpected token '{}'", t),
+            PgnError::MissingResult(g) => write!(f, "game {} has no result", g),
+            PgnError::InvalidFen(e) => write!(f, "invalid FEN tag: {}", e),
+            PgnError::IllegalMove { game, ply, san } => write!(f, "game {}, ply {}: illegal move '{}'", game, ply, san),
+        }
+    }
+}
+
+impl PgnGame {
+    //builds the record of a game played with my_chess, missing roster tags are filled with "?"
+    pub fn from_game(game: &mut Game, tags: &[(&str, &str)]) -> PgnGame {
+        let result = match game.check_state() {
+            GameState::WIN_W(_) => "1-0",
+            GameState::WIN_B(_) => "0-1",
+            GameState::DRAW(_) => "1/2-1/2",
+            GameState::ONGOING => "*",
+        };
+
+        let mut all_tags = vec![];
+        for name in SEVEN_TAG_ROSTER {
+            let default = match name {
+                "Date" => "????.??.??",
+                "Result" => result,
+                _ => "?",
+            };
+            let value = tags.iter().find(|t| t.0 == name).map_or(default, |t| t.1);
+            all_tags.push((name.to_string(), value.to_string()));
+        }
+        if game.start_fen != START_FEN {
+            all_tags.push(("SetUp".to_string(), "1".to_string()));
+            all_tags.push(("FEN".to_string(), game.start_fen.clone()));
+        }
+        if game.chess960 {
+            all_tags.push(("Variant".to_string(), "Chess960".to_string()));
+        }
+        for (name, value) in tags {
+            if !all_tags.iter().any(|t| t.0 == *name) {
+                all_tags.push((name.to_string(), value.to_string()));
+            }
+        }
+
+        //replay from the start to write each move in the position it was played in
+        let mut replay = Game::from_fen(&game.start_fen).unwrap();
+        replay.chess960 = game.chess960;
+        let mut moves = vec![];
+        for mv in game.history.clone() {
+            let san = replay.to_san(mv);
+            //the game played every move in its history, so they all replay
+            replay.move_piece(mv).expect("move from the game's history");
+            moves.push(PgnMove { san: san, nags: vec![], comment: None });
+        }
+
+        return PgnGame { tags: all_tags, comment: None, moves: moves, result: result.to_string() };
+    }
+
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        return self.tags.iter().find(|t| t.0 == name).map(|t| t.1.as_str());
+    }
+
+    //replays the moves, starting from the FEN tag when there is one
+    pub fn to_game(&self) -> Result<Game, PgnError> {
+        let mut game = match self.tag("FEN") {
+            Some(fen) => Game::from_fen(fen).map_err(PgnError::InvalidFen)?,
+            None => my_chess::start(),
+        };
+        //the FEN alone can look like standard chess, e.g. chess960 position 518
+        if self.tag("Variant").map_or(false, |v| v.eq_ignore_ascii_case("chess960")) {
+            game.chess960 = true;
+        }
+        for (n, mv) in self.moves.iter().enumerate() {
+            let illegal = PgnError::IllegalMove { game: 1, ply: n + 1, san: mv.san.clone() };
+            let legal = match game.parse_san(&mv.san) {
+                Ok(m) => m,
+                Err(_) => return Err(illegal),
+            };
+            if game.move_piece(legal).is_err() {
+                return Err(illegal);
+            }
+        }
+        return Ok(game);
+    }
+
+    //export format: tag pairs, an empty line, movetext wrapped at 80 columns
+    pub fn write(&self) -> String {
+        let mut out = String::new();
+        for (name, value) in &self.tags {
+            let escaped = value.replace('\\', "\\\\").replace('"', "\\\"");
+            out.push_str(&format!("[{} \"{}\"]\n", name, escaped));
+        }
+        out.push('\n');
+
+        //the first move number depends on the position the game started from
+        let (mut number, mut white) = match self.tag("FEN").and_then(|fen| Game::from_fen(fen).ok()) {
+            Some(game) => ((game.turn_counter + 1) / 2, game.turn_counter % 2 == 1),
+            None => (1, true),
+        };
+
+        let mut tokens: Vec<String> = vec![];
+        if let Some(c) = &self.comment {
+            tokens.push(format!("{{{}}}", c));
+        }
+        let mut need_number = true;
+        for mv in &self.moves {
+            if white {
+                tokens.push(format!("{}.", number));
+            } else if need_number {
+                tokens.push(format!("{}...", number));
+            }
+            tokens.push(mv.san.clone());
+            need_number = false;
+            for nag in &mv.nags {
+                tokens.push(format!("${}", nag));
+            }
+            if let Some(c) = &mv.comment {
+                tokens.push(format!("{{{}}}", c));
+                need_number = true;
+            }
+            if !white {
+                number += 1;
+            }
+            white = !white;
+        }
+        tokens.push(self.result.clone());
+
+        let mut line = String::new();
+        for token in tokens {
+            if !line.is_empty() && line.len() + 1 + token.len() > 80 {
+                out.push_str(&line);
+                out.push('\n');
+                line.clear();
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(&token);
+        }
+        out.push_str(&line);
+        out.push('\n');
+        return out;
+    }
+}
+
+//exports a game played with my_chess
+pub fn write_pgn(game: &mut Game, tags: &[(&str, &str)]) -> String {
+    return PgnGame::from_game(game, tags).write();
+}
+
+//reads every game in a PGN file, each game is replayed to check that its moves are legal
+pub fn read_pgn(text: &str) -> Result<Vec<PgnGame>, PgnError> {
+    let games = parse_pgn(text)?;
+    for (n, game) in games.iter().enumerate() {
+        match game.to_game() {
+            Err(PgnError::IllegalMove { ply, san, .. }) => return Err(PgnError::IllegalMove { game: n + 1, ply: ply, san: san }),
+            Err(e) => return Err(e),
+            Ok(_) => {}
+        }
+    }
+    return Ok(games);
+}
+
+fn new_game() -> PgnGame {
+    return PgnGame { tags: vec![], comment: None, moves: vec![], result: String::new() };
+}
+
+//suffix annotations and the NAGs they stand for
+fn suffix_nag(suffix: &str) -> Option<u8> {
+    match suffix {
+        "!" => Some(1),
+        "?" => Some(2),
+        "!!" => Some(3),
+        "??" => Some(4),
+        "!?" => Some(5),
+        "?!" => Some(6),
+        _ => None,
+    }
+}
+
+//splits the text into games without checking the moves
+fn parse_pgn(text: &str) -> Result<Vec<PgnGame>, PgnError> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut games = vec![];
+    let mut current = new_game();
+    let mut in_movetext = false;
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+
+        //escape mechanism, lines starting with % are skipped
+        if c == '%' && (i == 0 || chars[i - 1] == '\n') {
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+            continue;
+        }
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+
+        match c {
+            '[' => {
+                if in_movetext {
+                    return Err(PgnError::MissingResult(games.len() + 1));
+                }
+                let start = i + 1;
+                let mut in_string = false;
+                i += 1;
+                while i < chars.len() && (in_string || chars[i] != ']') {
+                    if chars[i] == '\\' && in_string {
+                        i += 1;
+                    } else if chars[i] == '"' {
+                        in_string = !in_string;
+                    }
+                    i += 1;
+                }
+                if i >= chars.len() {
+                    return Err(PgnError::UnterminatedTag);
+                }
+                let raw: String = chars[start..i].iter().collect();
+                current.tags.push(parse_tag(&raw)?);
+                i += 1;
+            }
+            '{' => {
+                let start = i + 1;
+                while i < chars.len() && chars[i] != '}' {
+                    i += 1;
+                }
+                if i >= chars.len() {
+                    return Err(PgnError::UnterminatedComment);
+                }
+                let comment: String = chars[start..i].iter().collect::<String>().trim().to_string();
+                add_comment(&mut current, comment);
+                in_movetext = true;
+                i += 1;
+            }
+            ';' => {
+                let start = i + 1;
+                while i < chars.len() && chars[i] != '\n' {
+                    i += 1;
+                }
+                let comment: String = chars[start..i].iter().collect::<String>().trim().to_string();
+                add_comment(&mut current, comment);
+                in_movetext = true;
+            }
+            '(' => {
+                //variations are skipped, they may nest and contain comments
+                let mut depth = 0;
+                while i < chars.len() {
+                    match chars[i] {
+                        '(' => depth += 1,
+                        ')' => depth -= 1,
+                        '{' => {
+                            while i < chars.len() && chars[i] != '}' {
+                                i += 1;
+                            }
+                        }
+                        _ => {}
+                    }
+                    if depth == 0 {
+                        break;
+                    }
+                    i += 1;
+                }
+                if i >= chars.len() {
+                    return Err(PgnError::UnterminatedVariation);
+                }
+                i += 1;
+            }
+            ')' => return Err(PgnError::UnexpectedToken(")".to_string())),
+            _ => {
+                let start = i;
+                while i < chars.len() && !chars[i].is_whitespace() && !"[]{}();$".contains(chars[i]) {
+                    i += 1;
+                }
+                //NAGs
+                if c == '$' {
+                    i += 1;
+                    while i < chars.len() && chars[i].is_ascii_digit() {
+                        i += 1;
+                    }
+                }
+                let token: String = chars[start..i].iter().collect();
+                in_movetext = true;
+
+                if token == "1-0" || token == "0-1" || token == "1/2-1/2" || token == "*" {
+                    current.result = token;
+                    games.push(current);
+                    current = new_game();
+                    in_movetext = false;
+                } else if c == '$' {
+                    let nag = token[1..].parse().map_err(|_| PgnError::UnexpectedToken(token.clone()))?;
+                    match current.moves.last_mut() {
+                        Some(m) => m.nags.push(nag),
+                        None => return Err(PgnError::UnexpectedToken(token)),
+                    }
+                } else if c.is_ascii_digit() && token.trim_end_matches('.').chars().all(|d| d.is_ascii_digit()) {
+                    //move number indication
+                } else if let Some(nag) = suffix_nag(&token) {
+                    match current.moves.last_mut() {
+                        Some(m) => m.nags.push(nag),
+                        None => return Err(PgnError::UnexpectedToken(token)),
+                    }
+                } else {
+                    //a move, possibly with its number attached ("1.e4") and a suffix annotation ("Nf3!?")
+                    let san = match token.rfind('.') {
+                        Some(p) if token[..p].chars().all(|d| d.is_ascii_digit() || d == '.') => &token[p + 1..],
+                        _ => &token[..],
+                    };
+                    let stripped = san.trim_end_matches(|d| d == '!' || d == '?');
+                    if stripped.is_empty() {
+                        return Err(PgnError::UnexpectedToken(token));
+                    }
+                    let mut nags = vec![];
+                    if let Some(nag) = suffix_nag(&san[stripped.len()..]) {
+                        nags.push(nag);
+                    }
+                    current.moves.push(PgnMove { san: stripped.to_string(), nags: nags, comment: None });
+                }
+            }
+        }
+    }
+
+    if in_movetext || !current.tags.is_empty() {
+        return Err(PgnError::MissingResult(games.len() + 1));
+    }
+    return Ok(games);
+}
+
+fn add_comment(game: &mut PgnGame, comment: String) {
+    let target = match game.moves.last_mut() {
+        Some(m) => &mut m.comment,
+        None => &mut game.comment,
+    };
+    match target {
+        Some(existing) => {
+            existing.push(' ');
+            existing.push_str(&comment);
+        }
+        None => *target = Some(comment),
+    }
+}
+
+//the text between the brackets of a tag pair: Name "value"
+fn parse_tag(raw: &str) -> Result<(String, String), PgnError> {
+    let invalid = PgnError::InvalidTag(raw.to_string());
+    let raw = raw.trim();
+    let name_end = raw.find(|c: char| c.is_whitespace()).ok_or(invalid.clone())?;
+    let name = &raw[..name_end];
+    let rest = raw[name_end..].trim();
+    if name.is_empty() || !rest.starts_with('"') || !rest.ends_with('"') || rest.len() < 2 {
+        return Err(invalid);
+    }
+
+    let mut value = String::new();
+    let mut escaped = false;
+    for c in rest[1..rest.len() - 1].chars() {
+        if escaped {
+            value.push(c);
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else {
+            value.push(c);
+        }
+    }
+    return Ok((name.to_string(), value));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::my_chess::Move;
+
+    #[test]
+    fn export_fools_mate() {
+        let mut gam = my_chess::start();
+        gam.move_piece(Move::parse("f2f3").unwrap()).unwrap();
+        gam.move_piece(Move::parse("e7e5").unwrap()).unwrap();
+        gam.move_piece(Move::parse("g2g4").unwrap()).unwrap();
+        gam.move_piece(Move::parse("d8h4").unwrap()).unwrap();
+
+        let pgn = write_pgn(&mut gam, &[("White", "Pechmann"), ("Annotator", "test")]);
+        let expected = "[Event \"?\"]\n[Site \"?\"]\n[Date \"????.??.??\"]\n[Round \"?\"]\n[White \"Pechmann\"]\n[Black \"?\"]\n[Result \"0-1\"]\n[Annotator \"test\"]\n\n1. f3 e5 2. g4 Qh4# 0-1\n";
+        assert_eq!(pgn, expected);
+    }
+
+    #[test]
+    fn export_from_fen() {
+        let mut gam = Game::from_fen("4k3/8/8/8/8/8/4P3/R3K3 b Q - 0 30").unwrap();
+        gam.move_piece(Move::parse("e8d7").unwrap()).unwrap();
+        gam.move_piece(Move::parse("e1c1").unwrap()).unwrap();
+        let pgn = write_pgn(&mut gam, &[]);
+        assert!(pgn.contains("[SetUp \"1\"]\n[FEN \"4k3/8/8/8/8/8/4P3/R3K3 b Q - 0 30\"]\n"));
+        assert!(pgn.ends_with("\n30... Kd7 31. O-O-O+ *\n"));
+
+        let games = read_pgn(&pgn).unwrap();
+        let mut replayed = games[0].to_game().unwrap();
+        assert_eq!(replayed.to_fen(), gam.to_fen());
+    }
+
+    #[test]
+    fn export_chess960() {
+        //518 looks like standard chess, only the Variant tag tells that castling takes the rook
+        let mut gam = my_chess::start_chess960(518);
+        for m in ["g1f3", "g8f6", "e2e3", "e7e6", "f1e2", "f8e7", "e1h1"] {
+            gam.move_piece(Move::parse(m).unwrap()).unwrap();
+        }
+        let pgn = write_pgn(&mut gam, &[]);
+        assert!(pgn.contains("[Variant \"Chess960\"]\n"));
+        assert!(pgn.contains("4. O-O *"));
+
+        let games = read_pgn(&pgn).unwrap();
+        let mut replayed = games[0].to_game().unwrap();
+        assert!(replayed.chess960);
+        assert_eq!(replayed.to_fen(), gam.to_fen());
+    }
+
+    #[test]
+    fn read_multiple_games() {
+        let text = "[Event \"First\"]\n[Site \"KTH \\\"E\\\" building\"]\n[Result \"1-0\"]\n\n\
+            {Opening comment} 1. e4 e5 2. Nf3!? $14 Nc6 (2... d6 {Philidor} (2... f6?)) 3. Bb5 ; Ruy Lopez\n\
+            a6 4. Ba4 Nf6 5. O-O Be7 1-0\n\
+            \n\
+            % skipped line\n\
+            [Event \"Second\"]\n\
+            [Result \"*\"]\n\
+            \n\
+            1.d4 d5 2.c4 dxc4 *\n";
+        let games = read_pgn(text).unwrap();
+        assert_eq!(games.len(), 2);
+
+        assert_eq!(games[0].tag("Site"), Some("KTH \"E\" building"));
+        assert_eq!(games[0].comment, Some("Opening comment".to_string()));
+        assert_eq!(games[0].moves.len(), 10);
+        assert_eq!(games[0].moves[2], PgnMove { san: "Nf3".to_string(), nags: vec![5, 14], comment: None });
+        assert_eq!(games[0].moves[4].comment, Some("Ruy Lopez".to_string()));
+        assert_eq!(games[0].moves[8].san, "O-O");
+        assert_eq!(games[0].result, "1-0");
+
+        assert_eq!(games[1].tag("Event"), Some("Second"));
+        let mut second = games[1].to_game().unwrap();
+        assert_eq!(second.to_fen(), "rnbqkbnr/ppp1pppp/8/8/2pP4/8/PP2PPPP/RNBQKBNR w KQkq - 0 3");
+    }
+
+    #[test]
+    fn round_trip_with_annotations() {
+        let text = "[Event \"?\"]\n[Site \"?\"]\n[Date \"????.??.??\"]\n[Round \"?\"]\n[White \"?\"]\n[Black \"?\"]\n[Result \"*\"]\n\n\
+            1. e4 $1 {best by test} 1... c5 2. Nf3 d6 *\n";
+        let games = read_pgn(text).unwrap();
+        assert_eq!(games[0].write(), text);
+    }
+
+    #[test]
+    fn read_errors() {
+        assert_eq!(read_pgn("1. e4 e5"), Err(PgnError::MissingResult(1)));
+        assert_eq!(read_pgn("[Event \"x\"\n1. e4 *"), Err(PgnError::UnterminatedTag));
+        assert_eq!(read_pgn("1. e4 {never closed *"), Err(PgnError::UnterminatedComment));
+        assert_eq!(read_pgn("[Event x]\n1. e4 *"), Err(PgnError::InvalidTag("Event x".to_string())));
+        assert_eq!(
+            read_pgn("1. e4 e5 *\n\n1. e4 e5 2. Ke3 *"),
+            Err(PgnError::IllegalMove { game: 2, ply: 3, san: "Ke3".to_string() })
+        );
+    }
+}
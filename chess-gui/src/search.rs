@@ -0,0 +1,840 @@
+//the computer player: negamax with alpha-beta pruning and iterative deepening over my_chess::Board,
+//positions are scored by material and piece-square tables once a quiescence search has played out the captures.
+//a transposition table and move ordering (hash move, MVV-LVA captures, killer moves, history) let alpha-beta cut off early
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::my_chess::{self, Board, Color, Game, Move, PieceType};
+
+//a mate found at ply n scores MATE - n, so shorter mates score higher
+pub const MATE: i32 = 30000;
+const INFINITY: i32 = 32000;
+pub const MAX_DEPTH: usize = 64;
+
+//how often the clock and the stop flag are looked at, in nodes
+const CHECK_INTERVAL: u64 = 2048;
+
+//transposition table size unless asked for another one
+pub const DEFAULT_TT_MB: usize = 16;
+
+//move ordering, anything else is ordered by its history score which stays below KILLER_SCORE
+const HASH_MOVE_SCORE: i32 = 1_000_000;
+const CAPTURE_SCORE: i32 = 100_000;
+const KILLER_SCORE: i32 = 90_000;
+
+//how the stored score relates to the real one, alpha-beta only proves bounds when it cuts off
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Bound {
+    Exact,
+    //the score is at least this, a move failed high
+    Lower,
+    //the score is at most this, no move reached alpha
+    Upper,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct TtEntry {
+    pub key: u64,
+    pub depth: usize,
+    pub score: i32,
+    pub bound: Bound,
+    pub best: Option<Move>,
+}
+
+//results of earlier searches by zobrist key. one entry per slot, a deeper result is kept over a shallower one
+pub struct TranspositionTable {
+    entries: Vec<Option<TtEntry>>,
+    pub probes: u64,
+    pub hits: u64,
+}
+
+impl TranspositionTable {
+    //a table of about mb megabytes, the number of slots is rounded down to a power of two
+    pub fn new(mb: usize) -> TranspositionTable {
+        let wanted = (mb.max(1) << 20) / std::mem::size_of::<Option<TtEntry>>();
+        let slots = 1usize << (usize::BITS - 1 - wanted.leading_zeros());
+        return TranspositionTable{entries: vec![None; slots], probes: 0, hits: 0};
+    }
+
+    fn slot(&self, key: u64) -> usize {
+        return (key as usize) & (self.entries.len() - 1);
+    }
+
+    pub fn probe(&mut self, key: u64) -> Option<TtEntry> {
+        self.probes += 1;
+        let entry = self.entries[self.slot(key)].filter(|e| e.key == key);
+        if entry.is_some() {
+            self.hits += 1;
+        }
+        return entry;
+    }
+
+    //looks at the table without counting it as a probe
+    pub fn get(&self, key: u64) -> Option<TtEntry> {
+        return self.entries[self.slot(key)].filter(|e| e.key == key);
+    }
+
+    //a different position only replaces one searched at most as deep, the same position is always updated
+    pub fn store(&mut self, entry: TtEntry) {
+        let slot = self.slot(entry.key);
+        let replace = match self.entries[slot] {
+            Some(old) => old.key == entry.key || entry.depth >= old.depth,
+            None => true,
+        };
+        if replace {
+            self.entries[slot] = Some(entry);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.iter_mut().for_each(|e| *e = None);
+        self.probes = 0;
+        self.hits = 0;
+    }
+
+    //share of hits among probes so far
+    pub fn hit_rate(&self) -> f64 {
+        return if self.probes == 0 {0.0} else {self.hits as f64 / self.probes as f64};
+    }
+
+    //filled slots per thousand, as UCI reports it, estimated from the first thousand slots
+    pub fn hashfull(&self) -> usize {
+        return self.entries.iter().take(1000).filter(|e| e.is_some()).count() * 1000 / self.entries.len().min(1000);
+    }
+}
+
+//mate scores are stored relative to the node, so they stay right when the position is reached at another ply
+fn score_to_tt(score: i32, ply: usize) -> i32 {
+    if score > MATE - MAX_DEPTH as i32 {
+        return score + ply as i32;
+    }
+    if score < -MATE + MAX_DEPTH as i32 {
+        return score - ply as i32;
+    }
+    return score;
+}
+
+fn score_from_tt(score: i32, ply: usize) -> i32 {
+    if score > MATE - MAX_DEPTH as i32 {
+        return score - ply as i32;
+    }
+    if score < -MATE + MAX_DEPTH as i32 {
+        return score + ply as i32;
+    }
+    return score;
+}
+
+//when to stop, the search ends at whichever limit is reached first
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Limits {
+    pub depth: usize,
+    pub movetime: Option<Duration>,
+}
+
+impl Limits {
+    pub fn depth(depth: usize) -> Limits {
+        return Limits{depth: depth.min(MAX_DEPTH), movetime: None};
+    }
+
+    pub fn movetime(movetime: Duration) -> Limits {
+        return Limits{depth: MAX_DEPTH, movetime: Some(movetime)};
+    }
+}
+
+//outcome of the deepest finished iteration
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct SearchResult {
+    //None if the side to move has no legal moves
+    pub best: Option<Move>,
+    //centipawns from the side to move's point of view
+    pub score: i32,
+    pub depth: usize,
+    pub nodes: u64,
+    pub pv: Vec<Move>,
+    pub elapsed: Duration,
+    //transposition table lookups during this search and how many found their position
+    pub tt_probes: u64,
+    pub tt_hits: u64,
+    //the best lines when the searcher looks for more than one, best first. the first one is score and pv
+    pub lines: Vec<PvLine>,
+}
+
+//one of the best moves with its score and the line that follows it
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct PvLine {
+    pub score: i32,
+    pub pv: Vec<Move>,
+}
+
+impl PvLine {
+    pub fn mate_in(&self) -> Option<i32> {
+        return mate_in(self.score);
+    }
+}
+
+//moves until mate for a score, negative if the side to move gets mated
+pub fn mate_in(score: i32) -> Option<i32> {
+    if score.abs() < MATE - MAX_DEPTH as i32 {
+        return None;
+    }
+    let plies = MATE - score.abs();
+    let moves = (plies + 1) / 2;
+    return Some(if score > 0 {moves} else {-moves});
+}
+
+impl SearchResult {
+    pub fn tt_hit_rate(&self) -> f64 {
+        return if self.tt_probes == 0 {0.0} else {self.tt_hits as f64 / self.tt_probes as f64};
+    }
+
+    pub fn nps(&self) -> u64 {
+        let secs = self.elapsed.as_secs_f64();
+        return if secs > 0.0 {(self.nodes as f64 / secs) as u64} else {0};
+    }
+
+    //moves until mate, negative if the side to move gets mated
+    pub fn mate_in(&self) -> Option<i32> {
+        return mate_in(self.score);
+    }
+}
+
+pub struct Searcher {
+    //set from another thread to end the search, the best move found so far is returned.
+    //search leaves it alone, whoever starts a search clears it first, so a stop sent before the thread runs isn't lost
+    pub stop: Arc<AtomicBool>,
+    //kept between searches, so the next move starts with what the last one found
+    pub tt: TranspositionTable,
+    //how many of the best moves get an exact score and a line, analysis shows more than one
+    pub multi_pv: usize,
+    //quiet moves that caused a cutoff, two per ply
+    killers: Vec<[Option<Move>; 2]>,
+    //how well quiet moves from one square to another did, by the side that played them
+    history: [[[i32; 64]; 64]; 2],
+    board: Board,
+    turn_counter: usize,
+    chess960: bool,
+    //how often each position occurred in the game and on the path to the current node
+    seen: HashMap<u64, usize>,
+    nodes: u64,
+    deadline: Option<Instant>,
+    stopped: bool,
+}
+
+impl Searcher {
+    pub fn new() -> Searcher {
+        return Searcher::with_tt_size(DEFAULT_TT_MB);
+    }
+
+    pub fn with_tt_size(mb: usize) -> Searcher {
+        return Searcher{stop: Arc::new(AtomicBool::new(false)), tt: TranspositionTable::new(mb), multi_pv: 1, killers: vec![[None; 2]; MAX_DEPTH + 1], history: [[[0; 64]; 64]; 2],
+            board: Board::empty(), turn_counter: 1, chess960: false, seen: HashMap::new(), nodes: 0, deadline: None, stopped: false};
+    }
+
+    //forgets everything learned in earlier searches, for a new game
+    pub fn clear(&mut self) {
+        self.tt.clear();
+        self.killers = vec![[None; 2]; MAX_DEPTH + 1];
+        self.history = [[[0; 64]; 64]; 2];
+    }
+
+    //searches deeper and deeper until a limit is hit, report is called after every finished depth
+    pub fn search(&mut self, game: &Game, limits: Limits, mut report: impl FnMut(&SearchResult)) -> SearchResult {
+        let start = Instant::now();
+        self.board = game.board.clone();
+        self.turn_counter = game.turn_counter;
+        self.chess960 = game.chess960;
+        self.seen = game.repeat_map.clone();
+        self.nodes = 0;
+        self.deadline = limits.movetime.map(|t| start + t);
+        self.stopped = false;
+        //killers are about the plies of this search, history is only aged
+        self.killers = vec![[None; 2]; MAX_DEPTH + 1];
+        self.history.iter_mut().flatten().flatten().for_each(|h| *h /= 8);
+        let (probes, hits) = (self.tt.probes, self.tt.hits);
+
+        let mut moves = vec![];
+        self.board.generate_moves(self.turn_counter, self.chess960, &mut moves);
+        //something to play even if the first iteration does not finish
+        let mut result = SearchResult{best: moves.first().copied(), score: 0, depth: 0, nodes: 0, pv: vec![], elapsed: Duration::ZERO, tt_probes: 0, tt_hits: 0, lines: vec![]};
+        if moves.is_empty() {
+            return result;
+        }
+
+        for depth in 1..=limits.depth.max(1) {
+            let lines = self.root(&mut moves, depth);
+            if self.stopped {
+                break;
+            }
+            result = SearchResult{best: lines[0].pv.first().copied(), score: lines[0].score, depth: depth, nodes: self.nodes, pv: lines[0].pv.clone(), elapsed: start.elapsed(),
+                tt_probes: self.tt.probes - probes, tt_hits: self.tt.hits - hits, lines: lines};
+            report(&result);
+            //the best moves are searched first next time, in their order
+            for line in result.lines.iter().rev() {
+                if let Some(n) = moves.iter().position(|m| Some(m) == line.pv.first()) {
+                    moves[..=n].rotate_right(1);
+                }
+            }
+            //a forced mate won't get any shorter
+            if result.mate_in().is_some() || self.out_of_time() {
+                break;
+            }
+        }
+        result.nodes = self.nodes;
+        result.elapsed = start.elapsed();
+        result.tt_probes = self.tt.probes - probes;
+        result.tt_hits = self.tt.hits - hits;
+        return result;
+    }
+
+    //the multi_pv best lines, best first. a move only has to beat the worst of them, so alpha is that line's score
+    fn root(&mut self, moves: &mut [Move], depth: usize) -> Vec<PvLine> {
+        let wanted = self.multi_pv.clamp(1, moves.len());
+        let mut lines: Vec<PvLine> = Vec::with_capacity(wanted + 1);
+        for mv in moves.iter() {
+            let alpha = if lines.len() < wanted {-INFINITY} else {lines[wanted - 1].score};
+            let mut line = vec![];
+            let score = -self.child(*mv, depth - 1, 1, -INFINITY, -alpha, &mut line);
+            if self.stopped {
+                return lines;
+            }
+            if score > alpha {
+                let mut pv = vec![*mv];
+                pv.extend(line);
+                let at = lines.iter().position(|l| l.score < score).unwrap_or(lines.len());
+                lines.insert(at, PvLine{score: score, pv: pv});
+                lines.truncate(wanted);
+            }
+        }
+        return lines;
+    }
+
+    //plays mv, searches the position after it and takes it back
+    fn child(&mut self, mv: Move, depth: usize, ply: usize, alpha: i32, beta: i32, pv: &mut Vec<Move>) -> i32 {
+        let undo = self.board.make_move(mv, self.turn_counter);
+        self.turn_counter += 1;
+        *self.seen.entry(self.board.hash).or_insert(0) += 1;
+
+        let score = self.negamax(depth, ply, alpha, beta, pv);
+
+        let count = self.seen.get_mut(&self.board.hash).unwrap();
+        *count -= 1;
+        if *count == 0 {
+            self.seen.remove(&self.board.hash);
+        }
+        self.turn_counter -= 1;
+        self.board.unmake_move(undo);
+        return score;
+    }
+
+    //follows the hash moves from the current position for at most plies moves, as long as they are legal and don't repeat
+    fn tt_line(&mut self, plies: usize) -> Vec<Move> {
+        let mut line = vec![];
+        let mut undos = vec![];
+        let mut visited = vec![];
+        while line.len() < plies {
+            let Some(mv) = self.tt.get(self.board.hash).and_then(|e| e.best) else {break};
+            let mut moves = vec![];
+            self.board.generate_moves(self.turn_counter, self.chess960, &mut moves);
+            if !moves.contains(&mv) || visited.contains(&self.board.hash) {
+                break;
+            }
+            visited.push(self.board.hash);
+            undos.push(self.board.make_move(mv, self.turn_counter));
+            self.turn_counter += 1;
+            line.push(mv);
+        }
+        while let Some(undo) = undos.pop() {
+            self.turn_counter -= 1;
+            self.board.unmake_move(undo);
+        }
+        return line;
+    }
+
+    //fail-soft, the score can lie outside alpha and beta which makes the stored bounds tighter
+    fn negamax(&mut self, depth: usize, ply: usize, mut alpha: i32, beta: i32, pv: &mut Vec<Move>) -> i32 {
+        self.nodes += 1;
+        if self.nodes.is_multiple_of(CHECK_INTERVAL) && (self.stop.load(Ordering::Relaxed) || self.out_of_time()) {
+            self.stopped = true;
+        }
+        if self.stopped {
+            return 0;
+        }
+
+        //a position seen before is scored as a draw, the side ahead will look for something else
+        if self.seen.get(&self.board.hash).is_some_and(|n| *n > 1) || self.board.halfmove_clock >= 100 || self.board.has_insufficient_material() {
+            return 0;
+        }
+        if depth == 0 {
+            return self.quiesce(ply, alpha, beta);
+        }
+
+        let mut hash_move = None;
+        if let Some(entry) = self.tt.probe(self.board.hash) {
+            hash_move = entry.best;
+            let score = score_from_tt(entry.score, ply);
+            let usable = match entry.bound {
+                Bound::Exact => true,
+                Bound::Lower => score >= beta,
+                Bound::Upper => score <= alpha,
+            };
+            if entry.depth >= depth && usable {
+                pv.clear();
+                //an exact score can end up in the pv, which goes on along the hash moves
+                if entry.bound == Bound::Exact {
+                    pv.extend(self.tt_line(depth));
+                } else {
+                    pv.extend(entry.best);
+                }
+                return score;
+            }
+        }
+
+        let mut moves = vec![];
+        self.board.generate_moves(self.turn_counter, self.chess960, &mut moves);
+        if moves.is_empty() {
+            let king = if self.turn_counter % 2 == 1 {self.board.wk_pos} else {self.board.bk_pos};
+            return if self.board.is_in_check(king) {-MATE + ply as i32} else {0};
+        }
+        if ply >= MAX_DEPTH {
+            return evaluate(&self.board, self.turn_counter);
+        }
+
+        self.order_moves(&mut moves, hash_move, ply);
+        let alpha_start = alpha;
+        let mut best_score = -INFINITY;
+        let mut best_move = None;
+        for mv in moves {
+            let mut line = vec![];
+            let score = -self.child(mv, depth - 1, ply + 1, -beta, -alpha, &mut line);
+            if self.stopped {
+                return 0;
+            }
+            if score > best_score {
+                best_score = score;
+                best_move = Some(mv);
+            }
+            if score > alpha {
+                alpha = score;
+                pv.clear();
+                pv.push(mv);
+                pv.extend(line);
+                if alpha >= beta {
+                    if !mv.is_capture() && mv.promotion.is_none() {
+                        self.remember_quiet(mv, depth, ply);
+                    }
+                    break;
+                }
+            }
+        }
+
+        let bound = if best_score >= beta {Bound::Lower} else if best_score > alpha_start {Bound::Exact} else {Bound::Upper};
+        self.tt.store(TtEntry{key: self.board.hash, depth: depth, score: score_to_tt(best_score, ply), bound: bound, best: best_move});
+        return best_score;
+    }
+
+    //captures and queen promotions are played out until the position is quiet, so the evaluation never counts
+    //a piece that is about to be taken. the side to move may stand pat on the evaluation instead of capturing,
+    //captures that lose material by SEE are skipped. in check every move is tried since standing pat is no option
+    fn quiesce(&mut self, ply: usize, mut alpha: i32, beta: i32) -> i32 {
+        self.nodes += 1;
+        if self.nodes.is_multiple_of(CHECK_INTERVAL) && (self.stop.load(Ordering::Relaxed) || self.out_of_time()) {
+            self.stopped = true;
+        }
+        if self.stopped {
+            return 0;
+        }
+        if ply >= MAX_DEPTH {
+            return evaluate(&self.board, self.turn_counter);
+        }
+
+        let king = if self.turn_counter % 2 == 1 {self.board.wk_pos} else {self.board.bk_pos};
+        let in_check = self.board.is_in_check(king);
+        let mut moves = vec![];
+        let mut best_score = -INFINITY;
+        if in_check {
+            self.board.generate_moves(self.turn_counter, self.chess960, &mut moves);
+            if moves.is_empty() {
+                return -MATE + ply as i32;
+            }
+        } else {
+            best_score = evaluate(&self.board, self.turn_counter);
+            if best_score >= beta {
+                return best_score;
+            }
+            alpha = alpha.max(best_score);
+            self.board.generate_captures(self.turn_counter, &mut moves);
+        }
+
+        self.order_moves(&mut moves, None, ply);
+        for mv in moves {
+            if !in_check {
+                if mv.promotion.is_some_and(|p| p != PieceType::QUEEN) {
+                    continue;
+                }
+                if mv.is_capture() && mv.promotion.is_none() && self.board.see(mv) < 0 {
+                    continue;
+                }
+            }
+            let undo = self.board.make_move(mv, self.turn_counter);
+            self.turn_counter += 1;
+            let score = -self.quiesce(ply + 1, -beta, -alpha);
+            self.turn_counter -= 1;
+            self.board.unmake_move(undo);
+            if self.stopped {
+                return 0;
+            }
+            if score > best_score {
+                best_score = score;
+                if score > alpha {
+                    alpha = score;
+                    if alpha >= beta {
+                        break;
+                    }
+                }
+            }
+        }
+        return best_score;
+    }
+
+    //best first: the move the table remembers, captures of the most valuable piece by the least valuable one,
+    //the killers of this ply and then quiet moves by history
+    fn order_moves(&self, moves: &mut Vec<Move>, hash_move: Option<Move>, ply: usize) {
+        let us = if self.turn_counter % 2 == 1 {0} else {1};
+        let mut scored: Vec<(i32, Move)> = moves.iter().map(|m| {
+            let score = if Some(*m) == hash_move {
+                HASH_MOVE_SCORE
+            } else if m.is_capture() || m.promotion.is_some() {
+                let victim = if m.is_en_passant() {PieceType::PAWN} else {self.board.piece_at(m.to).map_or(PieceType::PAWN, |p| p.variant)};
+                let attacker = self.board.get_piece(m.from).variant;
+                let gain = if m.is_capture() {victim.value()} else {0} + m.promotion.map_or(0, |p| p.value());
+                CAPTURE_SCORE + 10 * gain - attacker.index() as i32
+            } else if self.killers[ply][0] == Some(*m) {
+                KILLER_SCORE
+            } else if self.killers[ply][1] == Some(*m) {
+                KILLER_SCORE - 1
+            } else {
+                self.history[us][m.from.index()][m.to.index()]
+            };
+            return (score, *m);
+        }).collect();
+        scored.sort_by_key(|s| std::cmp::Reverse(s.0));
+        moves.clear();
+        moves.extend(scored.into_iter().map(|(_, m)| m));
+    }
+
+    //a quiet move that cut off is tried early in sibling positions and wherever it does well
+    fn remember_quiet(&mut self, mv: Move, depth: usize, ply: usize) {
+        if self.killers[ply][0] != Some(mv) {
+            self.killers[ply][1] = self.killers[ply][0];
+            self.killers[ply][0] = Some(mv);
+        }
+        let us = if self.turn_counter % 2 == 1 {0} else {1};
+        let entry = &mut self.history[us][mv.from.index()][mv.to.index()];
+        //kept below the killers however often it cuts off
+        *entry = (*entry + (depth * depth) as i32).min(KILLER_SCORE - 2);
+    }
+
+    fn out_of_time(&self) -> bool {
+        return self.deadline.is_some_and(|d| Instant::now() >= d);
+    }
+}
+
+//positions searched by bench, the start position, kiwipete and a quiet middlegame
+const BENCH_POSITIONS: [&str; 3] = [
+    my_chess::START_FEN,
+    "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+    "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+];
+
+//searches the bench positions to depth with a fresh table each and prints what it took
+pub fn bench(depth: usize) {
+    let (mut nodes, mut elapsed) = (0, Duration::ZERO);
+    for fen in BENCH_POSITIONS {
+        let game = Game::from_fen(fen).unwrap();
+        let result = Searcher::new().search(&game, Limits::depth(depth), |_| {});
+        println!("{}", fen);
+        println!("  best {} score {} nodes {} time {:?} tt probes {} hits {} ({:.1}%)", result.best.map_or("none".to_string(), |m| m.to_string()),
+            result.score, result.nodes, result.elapsed, result.tt_probes, result.tt_hits, result.tt_hit_rate() * 100.0);
+        nodes += result.nodes;
+        elapsed += result.elapsed;
+    }
+    println!("{} nodes in {:?}, {} nps", nodes, elapsed, (nodes as f64 / elapsed.as_secs_f64().max(1e-9)) as u64);
+}
+
+//piece-square tables for white, written as seen from white's side: the first row is the eighth rank
+#[rustfmt::skip]
+const PAWN_TABLE: [i32; 64] = [
+     0,  0,  0,  0,  0,  0,  0,  0,
+    50, 50, 50, 50, 50, 50, 50, 50,
+    10, 10, 20, 30, 30, 20, 10, 10,
+     5,  5, 10, 25, 25, 10,  5,  5,
+     0,  0,  0, 20, 20,  0,  0,  0,
+     5, -5,-10,  0,  0,-10, -5,  5,
+     5, 10, 10,-20,-20, 10, 10,  5,
+     0,  0,  0,  0,  0,  0,  0,  0,
+];
+
+#[rustfmt::skip]
+const KNIGHT_TABLE: [i32; 64] = [
+    -50,-40,-30,-30,-30,-30,-40,-50,
+    -40,-20,  0,  0,  0,  0,-20,-40,
+    -30,  0, 10, 15, 15, 10,  0,-30,
+    -30,  5, 15, 20, 20, 15,  5,-30,
+    -30,  0, 15, 20, 20, 15,  0,-30,
+    -30,  5, 10, 15, 15, 10,  5,-30,
+    -40,-20,  0,  5,  5,  0,-20,-40,
+    -50,-40,-30,-30,-30,-30,-40,-50,
+];
+
+#[rustfmt::skip]
+const BISHOP_TABLE: [i32; 64] = [
+    -20,-10,-10,-10,-10,-10,-10,-20,
+    -10,  0,  0,  0,  0,  0,  0,-10,
+    -10,  0,  5, 10, 10,  5,  0,-10,
+    -10,  5,  5, 10, 10,  5,  5,-10,
+    -10,  0, 10, 10, 10, 10,  0,-10,
+    -10, 10, 10, 10, 10, 10, 10,-10,
+    -10,  5,  0,  0,  0,  0,  5,-10,
+    -20,-10,-10,-10,-10,-10,-10,-20,
+];
+
+#[rustfmt::skip]
+const ROOK_TABLE: [i32; 64] = [
+     0,  0,  0,  0,  0,  0,  0,  0,
+     5, 10, 10, 10, 10, 10, 10,  5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+     0,  0,  0,  5,  5,  0,  0,  0,
+];
+
+#[rustfmt::skip]
+const QUEEN_TABLE: [i32; 64] = [
+    -20,-10,-10, -5, -5,-10,-10,-20,
+    -10,  0,  0,  0,  0,  0,  0,-10,
+    -10,  0,  5,  5,  5,  5,  0,-10,
+     -5,  0,  5,  5,  5,  5,  0, -5,
+      0,  0,  5,  5,  5,  5,  0, -5,
+    -10,  5,  5,  5,  5,  5,  0,-10,
+    -10,  0,  5,  0,  0,  0,  0,-10,
+    -20,-10,-10, -5, -5,-10,-10,-20,
+];
+
+//the king hides behind its pawns while queens are around
+#[rustfmt::skip]
+const KING_TABLE: [i32; 64] = [
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -20,-30,-30,-40,-40,-30,-30,-20,
+    -10,-20,-20,-20,-20,-20,-20,-10,
+     20, 20,  0,  0,  0,  0, 20, 20,
+     20, 30, 10,  0,  0, 10, 30, 20,
+];
+
+//and walks to the centre in the endgame
+#[rustfmt::skip]
+const KING_ENDGAME_TABLE: [i32; 64] = [
+    -50,-40,-30,-20,-20,-30,-40,-50,
+    -30,-20,-10,  0,  0,-10,-20,-30,
+    -30,-10, 20, 30, 30, 20,-10,-30,
+    -30,-10, 30, 40, 40, 30,-10,-30,
+    -30,-10, 30, 40, 40, 30,-10,-30,
+    -30,-10, 20, 30, 30, 20,-10,-30,
+    -30,-30,  0,  0,  0,  0,-30,-30,
+    -50,-30,-30,-30,-30,-30,-30,-50,
+];
+
+//pieces other than pawns and kings worth less than this on both sides together make an endgame
+const ENDGAME_MATERIAL: i32 = 1300;
+
+//the table entry for a piece of color on the square with index sq
+fn table_index(color: Color, sq: usize) -> usize {
+    let (x, y) = (sq % 8, sq / 8);
+    return if color == Color::W {(7 - y) * 8 + x} else {y * 8 + x};
+}
+
+//static score of the position in centipawns from the side to move's point of view
+pub fn evaluate(board: &Board, t_count: usize) -> i32 {
+    let mut officers = 0;
+    for color in [Color::W, Color::B] {
+        for variant in [PieceType::KNIGHT, PieceType::BISHIOP, PieceType::ROOK, PieceType::QUEEN] {
+            officers += board.pieces[color.index()][variant.index()].count_ones() as i32 * variant.value();
+        }
+    }
+    let king_table = if officers <= ENDGAME_MATERIAL {&KING_ENDGAME_TABLE} else {&KING_TABLE};
+
+    let mut score = 0;
+    for color in [Color::W, Color::B] {
+        let sign = if color == Color::W {1} else {-1};
+        for (variant, table) in [(PieceType::PAWN, &PAWN_TABLE), (PieceType::KNIGHT, &KNIGHT_TABLE), (PieceType::BISHIOP, &BISHOP_TABLE),
+                                 (PieceType::ROOK, &ROOK_TABLE), (PieceType::QUEEN, &QUEEN_TABLE), (PieceType::KING, king_table)] {
+            for sq in my_chess::bits(board.pieces[color.index()][variant.index()]) {
+                score += sign * (variant.value() + table[table_index(color, sq)]);
+            }
+        }
+    }
+    return if t_count % 2 == 1 {score} else {-score};
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn best(fen: &str, depth: usize) -> SearchResult {
+        let game = Game::from_fen(fen).unwrap();
+        return Searcher::new().search(&game, Limits::depth(depth), |_| {});
+    }
+
+    #[test]
+    fn finds_mate(){
+        //back rank mate
+        let result = best("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", 3);
+        assert!(result.best.unwrap().matches(Move::parse("a1a8").unwrap()));
+        assert_eq!(result.mate_in(), Some(1));
+        //black is mated whatever it does
+        let result = best("R5k1/5ppp/8/8/8/8/8/6K1 b - - 0 1", 3);
+        assert_eq!(result.best, None);
+    }
+
+    #[test]
+    fn wins_material(){
+        //the queen on d5 hangs to the knight
+        let result = best("4k3/8/8/3q4/8/4N3/4P3/4K3 w - - 0 1", 2);
+        assert!(result.best.unwrap().matches(Move::parse("e3d5").unwrap()));
+        assert!(result.score > 300);
+        //but the knight does not take a pawn guarded by a pawn
+        let result = best("4k3/2p5/3p4/8/4N3/8/8/4K3 w - - 0 1", 2);
+        assert!(!result.best.unwrap().matches(Move::parse("e4d6").unwrap()));
+    }
+
+    #[test]
+    fn multi_pv(){
+        let game = Game::from_fen("4k3/8/8/3q4/8/4N3/4P3/4K3 w - - 0 1").unwrap();
+        let mut searcher = Searcher::new();
+        searcher.multi_pv = 3;
+        let result = searcher.search(&game, Limits::depth(3), |r| assert_eq!(r.lines.len(), 3));
+        assert!(result.best.unwrap().matches(Move::parse("e3d5").unwrap()));
+        assert_eq!((result.lines[0].score, &result.lines[0].pv), (result.score, &result.pv));
+        //the other lines leave the queen alone and are worse by about as much
+        assert!(result.lines.windows(2).all(|w| w[0].score >= w[1].score));
+        assert!(result.lines[1].score < result.score - 300);
+        let firsts: Vec<Move> = result.lines.iter().map(|l| l.pv[0]).collect();
+        assert!(firsts.iter().enumerate().all(|(n, m)| !firsts[..n].contains(m)));
+
+        //no more lines than moves
+        searcher.multi_pv = 5;
+        let game = Game::from_fen("7k/8/8/8/8/8/8/K7 w - - 0 1").unwrap();
+        assert_eq!(searcher.search(&game, Limits::depth(2), |_| {}).lines.len(), 3);
+    }
+
+    #[test]
+    fn evaluation_is_symmetric(){
+        let game = Game::from_fen(my_chess::START_FEN).unwrap();
+        assert_eq!(evaluate(&game.board, 1), 0);
+        let white = Game::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1").unwrap();
+        let black = Game::from_fen("r3k2r/pppbbppp/2n2q1P/1P2p3/3pn3/BN2PNP1/P1PPQPB1/R3K2R b KQkq - 0 1").unwrap();
+        assert_eq!(evaluate(&white.board, white.turn_counter), evaluate(&black.board, black.turn_counter));
+    }
+
+    #[test]
+    fn quiescence(){
+        //one ply sees the pawn but only the quiescence search sees it taken back
+        let result = best("4k3/8/4p3/3p4/8/8/8/3QK3 w - - 0 1", 1);
+        assert!(!result.best.unwrap().matches(Move::parse("d1d5").unwrap()));
+        assert!(result.score > 600);
+        //the rook takes a loose knight but leaves a guarded one alone
+        let result = best("4k3/8/8/4n3/8/8/8/4RK2 w - - 0 1", 1);
+        assert!(result.best.unwrap().matches(Move::parse("e1e5").unwrap()));
+        let result = best("4k3/8/5p2/4n3/8/8/8/4RK2 w - - 0 1", 1);
+        assert!(!result.best.unwrap().matches(Move::parse("e1e5").unwrap()));
+    }
+
+    #[test]
+    fn depth_preferred_table(){
+        let mut tt = TranspositionTable::new(1);
+        let slots = tt.entries.len() as u64;
+        let entry = |key, depth| TtEntry{key: key, depth: depth, score: 0, bound: Bound::Exact, best: None};
+        tt.store(entry(5, 4));
+        //another position in the same slot needs at least the same depth
+        tt.store(entry(5 + slots, 3));
+        assert_eq!(tt.probe(5 + slots), None);
+        assert_eq!(tt.probe(5).map(|e| e.depth), Some(4));
+        tt.store(entry(5 + slots, 6));
+        assert_eq!(tt.probe(5), None);
+        //the same position is always updated
+        tt.store(entry(5 + slots, 1));
+        assert_eq!(tt.probe(5 + slots).map(|e| e.depth), Some(1));
+        assert_eq!((tt.probes, tt.hits), (4, 2));
+        assert_eq!(tt.hit_rate(), 0.5);
+
+        //a mate stored at one ply is still the same mate when reached at another
+        assert_eq!(score_from_tt(score_to_tt(MATE - 5, 3), 1), MATE - 3);
+        assert_eq!(score_from_tt(score_to_tt(-MATE + 5, 3), 1), -MATE + 3);
+        assert_eq!(score_from_tt(score_to_tt(120, 3), 1), 120);
+    }
+
+    #[test]
+    fn move_ordering(){
+        let game = Game::from_fen("4k3/8/2q1r3/3P4/8/8/8/R5KR w - - 0 1").unwrap();
+        let mut searcher = Searcher::new();
+        searcher.board = game.board.clone();
+        searcher.turn_counter = game.turn_counter;
+        let mut moves = vec![];
+        searcher.board.generate_moves(searcher.turn_counter, false, &mut moves);
+        let killer = Move::parse("a1a7").unwrap();
+        searcher.killers[3][0] = moves.iter().copied().find(|m| m.matches(killer));
+        let hash_move = moves.iter().copied().find(|m| m.matches(Move::parse("h1h8").unwrap()));
+        searcher.order_moves(&mut moves, hash_move, 3);
+        let order: Vec<String> = moves.iter().take(4).map(|m| m.to_string()).collect();
+        //pawn takes queen before pawn takes rook, then the killer
+        assert_eq!(order, vec!["h1h8", "d5c6", "d5e6", "a1a7"]);
+    }
+
+    #[test]
+    fn table_speeds_up_search(){
+        let game = Game::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1").unwrap();
+        let mut searcher = Searcher::new();
+        let first = searcher.search(&game, Limits::depth(4), |_| {});
+        assert!(first.tt_probes > 0 && first.tt_hits > 0);
+        //the same search again finds most of the tree in the table
+        let second = searcher.search(&game, Limits::depth(4), |_| {});
+        assert!(second.nodes < first.nodes / 2);
+        assert!(second.tt_hit_rate() > first.tt_hit_rate());
+        assert_eq!(second.score, first.score);
+        //the table cuts off at the root's children, the line goes on along the hash moves
+        assert_eq!(first.pv.len(), 4);
+        assert_eq!(second.pv.len(), 4);
+        searcher.clear();
+        assert_eq!(searcher.tt.hashfull(), 0);
+    }
+
+    #[test]
+    fn stops_on_request(){
+        let game = Game::from_fen(my_chess::START_FEN).unwrap();
+        let mut searcher = Searcher::new();
+        let stop = searcher.stop.clone();
+        let stopper = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(50));
+            stop.store(true, Ordering::Relaxed);
+        });
+        //would take forever without the stop
+        let result = searcher.search(&game, Limits::depth(MAX_DEPTH), |_| {});
+        stopper.join().unwrap();
+        assert!(result.depth < MAX_DEPTH);
+        assert!(result.best.is_some());
+        //the flag stays set until the caller clears it, so a stop sent before the search starts isn't lost
+        let result = searcher.search(&game, Limits::depth(MAX_DEPTH), |_| {});
+        assert!(result.depth < MAX_DEPTH);
+
+        let mut depths = vec![];
+        let result = Searcher::new().search(&game, Limits::movetime(Duration::from_millis(50)), |r| depths.push(r.depth));
+        assert!(result.depth >= 1);
+        assert_eq!(depths, (1..=result.depth).collect::<Vec<usize>>());
+    }
+}
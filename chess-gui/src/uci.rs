@@ -0,0 +1,267 @@
+//engine programs that speak the Universal Chess Interface, e.g. Stockfish.
+//the engine is told the whole game before every search, so it never has to be kept in step with the GUI
+use std::io;
+use std::sync::mpsc::TryRecvError;
+use std::time::Duration;
+
+use crate::external::{Event, ExternalEngine, Info, Process, Score, HANDSHAKE_TIMEOUT};
+use crate::my_chess::{Game, Move, START_FEN};
+
+pub struct UciEngine {
+    name: String,
+    info: Info,
+    process: Process,
+    //searches that were started and haven't sent their bestmove yet, all but the last one were stopped
+    pending: usize,
+    //the GUI still wants the move of the last search
+    wanted: bool,
+    chess960: bool,
+}
+
+impl UciEngine {
+    //uci until uciok, then isready until readyok
+    pub fn new(process: Process) -> io::Result<UciEngine> {
+        let mut engine = UciEngine{name: String::new(), info: Info::default(), process: process, pending: 0, wanted: false, chess960: false};
+        engine.process.send("uci")?;
+        while let Some(line) = engine.process.wait_for_line(HANDSHAKE_TIMEOUT)? {
+            if let Some(name) = line.strip_prefix("id name ") {
+                engine.name = name.trim().to_string();
+            }
+            if line.trim() == "uciok" {
+                engine.sync()?;
+                return Ok(engine);
+            }
+        }
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "engine exited before uciok"));
+    }
+
+    //waits until the engine has dealt with everything sent so far
+    fn sync(&mut self) -> io::Result<()> {
+        self.process.send("isready")?;
+        while let Some(line) = self.process.wait_for_line(HANDSHAKE_TIMEOUT)? {
+            if line.trim() == "readyok" {
+                return Ok(());
+            }
+        }
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "engine exited before readyok"));
+    }
+
+    pub fn set_option(&mut self, name: &str, value: &str) -> io::Result<()> {
+        return self.process.send(&format!("setoption name {} value {}", name, value));
+    }
+}
+
+impl ExternalEngine for UciEngine {
+    fn name(&self) -> &str {
+        return &self.name;
+    }
+
+    fn info(&self) -> &Info {
+        return &self.info;
+    }
+
+    fn new_game(&mut self) -> io::Result<()> {
+        self.stop()?;
+        return self.process.send("ucinewgame");
+    }
+
+    fn go(&mut self, game: &Game, movetime: Duration) -> io::Result<()> {
+        self.stop()?;
+        if game.chess960 != self.chess960 {
+            self.chess960 = game.chess960;
+            self.set_option("UCI_Chess960", if game.chess960 {"true"} else {"false"})?;
+        }
+        self.process.send(&position_command(game))?;
+        self.process.send(&format!("go movetime {}", movetime.as_millis()))?;
+        self.pending += 1;
+        self.wanted = true;
+        self.info = Info::default();
+        return Ok(());
+    }
+
+    fn stop(&mut self) -> io::Result<()> {
+        if self.wanted {
+            self.wanted = false;
+            return self.process.send("stop");
+        }
+        return Ok(());
+    }
+
+    fn is_thinking(&self) -> bool {
+        return self.wanted;
+    }
+
+    fn poll(&mut self) -> Option<Event> {
+        loop {
+            let line = match self.process.try_line() {
+                Ok(line) => line,
+                Err(TryRecvError::Empty) => return None,
+                Err(TryRecvError::Disconnected) => {
+                    if !self.wanted {
+                        return None;
+                    }
+                    self.wanted = false;
+                    return Some(Event::Exited);
+                }
+            };
+            //a stopped search still reports until its bestmove
+            let current = self.wanted && self.pending == 1;
+            if let Some(best) = parse_bestmove(&line) {
+                self.pending = self.pending.saturating_sub(1);
+                if current {
+                    self.wanted = false;
+                    return Some(Event::BestMove(best));
+                }
+            } else if current && line.starts_with("info ") {
+                let info = parse_info(&line);
+                //e.g. info string, nothing the GUI shows
+                if info == Info::default() {
+                    continue;
+                }
+                //lines about the move being searched don't replace the last full one
+                if !info.pv.is_empty() {
+                    self.info = info.clone();
+                }
+                return Some(Event::Info(info));
+            }
+        }
+    }
+}
+
+//the game as a position command, from its start position with every move played since
+pub fn position_command(game: &Game) -> String {
+    let mut command = if game.start_fen == START_FEN {"position startpos".to_string()} else {format!("position fen {}", game.start_fen)};
+    if !game.history.is_empty() {
+        command.push_str(" moves");
+        for mv in &game.history {
+            command.push_str(&format!(" {}", mv));
+        }
+    }
+    return command;
+}
+
+//Some(None) for the null move 0000 or (none) that some engines send when mated
+pub fn parse_bestmove(line: &str) -> Option<Option<Move>> {
+    let mut words = line.split_whitespace();
+    if words.next() != Some("bestmove") {
+        return None;
+    }
+    return Some(words.next().and_then(|m| Move::parse(m).ok()));
+}
+
+pub fn parse_info(line: &str) -> Info {
+    let mut info = Info::default();
+    let mut words = line.split_whitespace().skip(1);
+    while let Some(word) = words.next() {
+        match word {
+            "depth" => info.depth = words.next().and_then(|d| d.parse().ok()),
+            "nodes" => info.nodes = words.next().and_then(|n| n.parse().ok()),
+            "score" => {
+                info.score = match (words.next(), words.next().and_then(|s| s.parse().ok())) {
+                    (Some("cp"), Some(cp)) => Some(Score::Cp(cp)),
+                    (Some("mate"), Some(moves)) => Some(Score::Mate(moves)),
+                    _ => None,
+                };
+            }
+            //the rest of the line is the pv
+            "pv" => {
+                info.pv = words.by_ref().map_while(|m| Move::parse(m).ok()).collect();
+            }
+            //free text until the end of the line
+            "string" => break,
+            _ => {}
+        }
+    }
+    return info;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::external::tests::scripted;
+    use crate::my_chess;
+    use std::sync::{Arc, Mutex};
+
+    fn engine(script: fn(&str) -> Vec<&'static str>) -> (UciEngine, Arc<Mutex<Vec<String>>>) {
+        let (process, received) = scripted(script);
+        return (UciEngine::new(process).unwrap(), received);
+    }
+    fn sparring(command: &str) -> Vec<&'static str> {
+        match command.split_whitespace().next() {
+            Some("uci") => return vec!["id name Sparring 1.0", "id author nobody", "option name Hash type spin default 1 min 1 max 8", "uciok"],
+            Some("isready") => return vec!["readyok"],
+            Some("go") => return vec!["info string thinking", "info depth 1 seldepth 1 score cp 35 nodes 20 pv e7e5", "info depth 2 score mate -3 nodes 400 nps 1000 pv e7e5 g1f3", "bestmove e7e5 ponder g1f3"],
+            _ => return vec![],
+        }
+    }
+
+    #[test]
+    fn plays_scripted_engine() {
+        let (mut engine, received) = engine(sparring);
+        assert_eq!(engine.name, "Sparring 1.0");
+        assert!(!engine.is_thinking());
+
+        let mut game = my_chess::start();
+        game.move_piece(Move::parse("e2e4").unwrap()).unwrap();
+        engine.go(&game, Duration::from_millis(250)).unwrap();
+        assert!(engine.is_thinking());
+        assert_eq!(engine.poll(), Some(Event::Info(Info{depth: Some(1), score: Some(Score::Cp(35)), nodes: Some(20), pv: vec![Move::parse("e7e5").unwrap()]})));
+        match engine.poll() {
+            Some(Event::Info(info)) => assert_eq!(info.score, Some(Score::Mate(-3))),
+            other => panic!("expected info, got {:?}", other),
+        }
+        assert_eq!(engine.poll(), Some(Event::BestMove(Some(Move::parse("e7e5").unwrap()))));
+        assert_eq!(engine.poll(), None);
+        assert_eq!(engine.info().depth, Some(2));
+        assert!(!engine.is_thinking());
+        assert_eq!(*received.lock().unwrap(), vec!["uci", "isready", "position startpos moves e2e4", "go movetime 250"]);
+    }
+
+    //answers go only after stop, like a search that takes a while
+    fn slow(command: &str) -> Vec<&'static str> {
+        match command {
+            "stop" => return vec!["info depth 9 score cp 0 pv a7a6", "bestmove a7a6"],
+            "go movetime 1000" => return vec![],
+            _ => return sparring(command),
+        }
+    }
+
+    #[test]
+    fn stopped_search_is_dropped() {
+        let (mut engine, received) = engine(slow);
+        let mut game = my_chess::Game::from_fen("4k3/8/8/8/8/8/8/4K2R w K - 0 1").unwrap();
+        engine.go(&game, Duration::from_millis(1000)).unwrap();
+        //the position changed, e.g. after undo, so the answer to the first go must not be played
+        game.move_piece(Move::parse("e1g1").unwrap()).unwrap();
+        engine.go(&game, Duration::from_millis(10)).unwrap();
+        assert_eq!(engine.poll(), Some(Event::Info(parse_info("info depth 1 score cp 35 nodes 20 pv e7e5"))));
+        engine.poll();
+        assert_eq!(engine.poll(), Some(Event::BestMove(Some(Move::parse("e7e5").unwrap()))));
+        let received = received.lock().unwrap();
+        assert_eq!(received[2..], ["position fen 4k3/8/8/8/8/8/8/4K2R w K - 0 1", "go movetime 1000", "stop", "position fen 4k3/8/8/8/8/8/8/4K2R w K - 0 1 moves e1g1", "go movetime 10"]);
+    }
+
+    #[test]
+    fn chess960_position() {
+        let (mut engine, received) = engine(sparring);
+        let game = my_chess::start_chess960(0);
+        engine.go(&game, Duration::from_millis(10)).unwrap();
+        let received = received.lock().unwrap();
+        assert_eq!(received[2], "setoption name UCI_Chess960 value true");
+        assert_eq!(received[3], format!("position fen {}", game.start_fen));
+    }
+
+    #[test]
+    fn engine_output() {
+        assert_eq!(parse_bestmove("bestmove b7b8q ponder a2a1"), Some(Some(Move::parse("b7b8q").unwrap())));
+        assert_eq!(parse_bestmove("bestmove (none)"), Some(None));
+        assert_eq!(parse_bestmove("bestmove 0000"), Some(None));
+        assert_eq!(parse_bestmove("info depth 1"), None);
+        let info = parse_info("info depth 12 seldepth 20 multipv 1 score cp -15 upperbound nodes 123456 nps 99 hashfull 3 tbhits 0 time 5 pv d2d4 g8f6 c2c4");
+        assert_eq!(info.depth, Some(12));
+        assert_eq!(info.score, Some(Score::Cp(-15)));
+        assert_eq!(info.nodes, Some(123456));
+        assert_eq!(info.pv.len(), 3);
+        assert_eq!(parse_info("info string pv e2e4"), Info::default());
+    }
+}